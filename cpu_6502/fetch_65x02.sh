@@ -0,0 +1,10 @@
+#!/bin/sh
+# Clones the 65x02 single step tests the opcode suites run against. They're
+# ignored by default, run them with cargo test -p cpu_6502 -- --include-ignored
+set -e
+dir="$(dirname "$0")/tests/65x02"
+if [ -d "$dir" ]; then
+    git -C "$dir" pull --ff-only
+else
+    git clone --depth 1 https://github.com/SingleStepTests/65x02 "$dir"
+fi
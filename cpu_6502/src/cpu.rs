@@ -31,14 +31,6 @@ impl fmt::Debug for Registers {
 }
 
 impl Registers {
-    fn reset(&mut self, address: u16) {
-        // According to the cpu reset registers test, SP should decrement by 3
-        // and the interrupt flag should be set
-        self.pc.set_addr(address);
-        self.sp -= 3;
-        self.flags.set_itr(true);
-    }
-
     pub fn from_values(acc: u8, x: u8, y: u8, pc: u16, sp: u8, flags: u8) -> Self {
         Registers {
             acc,
@@ -56,17 +48,13 @@ pub struct ProgramCounter(u16);
 
 impl ProgramCounter {
     pub fn new(val: u16) -> ProgramCounter {
-        ProgramCounter { 0: val }
+        ProgramCounter(val)
     }
 
     fn add_unsigned(&mut self, offset: u16) {
         self.0 = self.0.wrapping_add(offset);
     }
 
-    fn add_signed(&mut self, offset: i8) {
-        self.0 = (self.0 as i32 + offset as i32) as u16;
-    }
-
     pub fn set_addr(&mut self, addr: u16) {
         self.0 = addr;
     }
@@ -97,9 +85,220 @@ bitfield! {
 
 pub struct Cpu {
     pub regs: Registers,
+    pub interrupts: InterruptState,
+    variant: Variant,
+    // Set by JAM, and by WAI and STP on the 65C02
+    halted: Option<Halt>,
+    // Since the CPU was created
+    cycles: u64,
+    // The instruction or interrupt tick is part way through
+    partial: Option<Partial>,
+    // The DMAs the CPU is halted for
+    dma: Option<Dma>,
+}
+
+// How far tick has got through an instruction or interrupt. The registers
+// change on the cycle they do on the chip, this is everything else it keeps
+// between cycles.
+#[derive(Clone, Copy)]
+struct Partial {
+    work: Work,
+    // Bus cycles done so far, the opcode fetch being the first
+    cycle: u8,
+    // The cycle the effective address was ready on, 0 until then
+    addressed: u8,
+    // The address being worked out, and then the one being used
+    addr: u16,
+    // A pointer, an operand byte or the value a read-modify-write read
+    val: u8,
+}
+
+impl Partial {
+    fn new(work: Work) -> Partial {
+        Partial {
+            work,
+            cycle: 0,
+            addressed: 0,
+            addr: 0,
+            val: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Work {
+    // The first cycle of an instruction, which fetches the opcode
+    Fetch,
+    Instruction(u8, Instr),
+    Interrupt,
+    Reset,
+}
+
+// What an opcode does on the cycles after its fetch. The functions get the
+// value that was read, or return the one to write.
+#[derive(Clone, Copy)]
+enum Instr {
+    // The 65C02's one cycle NOPs
+    Skip,
+    Implied(fn(&mut Cpu)),
+    Read(Mode, fn(&mut Cpu, u8)),
+    // ADC and SBC, which take an extra cycle in decimal mode on the 65C02
+    Arithmetic(Mode, fn(&mut Cpu, u8)),
+    Write(Mode, fn(&mut Cpu) -> u8),
+    // SHA, SHX, SHY and TAS, which return the value and the index
+    StoreHigh(Mode, fn(&mut Cpu) -> (u8, u8)),
+    Modify(Mode, fn(&mut Cpu, u8) -> u8),
+    // RMB and SMB
+    SetBit,
+    // BBR and BBS
+    BranchOnBit,
+    Push(fn(&Cpu) -> u8),
+    Pull(fn(&mut Cpu, u8)),
+    Branch(fn(&Cpu) -> bool),
+    Jsr,
+    Rts,
+    Rti,
+    Brk,
+    JmpAbs,
+    JmpInd,
+    JmpAbsIndX,
+    // WAI and STP
+    Halt(Halt),
+    Jam,
+    // The 65C02's 8 cycle NOP
+    LongNop,
+}
+
+// What the CPU is halted for. It stays halted until both the page copy and
+// the byte read are done, repeating the read it got halted on for any cycle
+// neither of them uses.
+#[derive(Clone, Copy)]
+struct Dma {
+    // The read the CPU got halted on, which it repeats while waiting
+    halted_addr: u16,
+    page: Option<PageDma>,
+    // The cycles left before the byte can be read, it needs a halt cycle
+    // and a dummy one
+    byte_wait: Option<u8>,
+}
+
+// OAM DMA copies a page to a single address, reading on get cycles and
+// writing on put cycles
+#[derive(Clone, Copy)]
+struct PageDma {
+    page: u8,
+    addr: u16,
+    copied: u16,
+    // Read on the last get cycle, waiting for a put cycle
+    val: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
+// The chips this core can be. The NES uses the 2A03, an NMOS 6502 with the
+// decimal mode cut out.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Variant {
+    #[default]
+    Ricoh2A03,
+    // Adds BCD arithmetic to ADC and SBC when the D flag is set
+    Nmos6502,
+    // The CMOS instruction set, including the bit instructions. The undefined
+    // opcodes are all NOPs.
+    Rockwell65C02,
+    // The Rockwell instruction set plus WAI and STP
+    Wdc65C02,
+}
+
+impl Variant {
+    pub fn decimal(self) -> bool {
+        self != Variant::Ricoh2A03
+    }
+
+    pub fn cmos(self) -> bool {
+        matches!(self, Variant::Rockwell65C02 | Variant::Wdc65C02)
+    }
+}
+
+// Why the CPU has stopped running instructions
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Halt {
+    // WAI, until an interrupt line goes active
+    Wait,
+    // STP, until a reset
+    Stop,
+    // One of the NMOS JAM opcodes, also until a reset
+    Jam,
+}
+
+// What the CPU last saw on its IRQ and NMI inputs, see
+// https://www.nesdev.org/wiki/CPU_interrupts
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+pub struct InterruptState {
+    // IRQ is level sensitive and shared, every bit is a device holding it low
+    irq_sources: u32,
+    nmi_input: bool,
+    // NMI fires on a rising edge, which stays latched until it's serviced
+    nmi_level: bool,
+    nmi_edge: bool,
+    // The lines after the last three cycles, newest first
+    samples: [Sample; 3],
+    // Decided at the end of the last instruction, runs before the next one
+    pending: Option<Interrupt>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+struct Sample {
+    irq: bool,
+    nmi: bool,
+    // The I flag at the end of the cycle, which is what a poll goes by
+    itr: bool,
+}
+
+// The bus for one cycle of an instruction. A DMA request halts the CPU on
+// its next read, which still goes out to the bus but gets done again once
+// the DMA is over.
+struct CycleBus<'a, M: Memory> {
+    mem: &'a mut M,
+    halted: Option<Dma>,
+}
+
+impl<M: Memory> Memory for CycleBus<'_, M> {
+    fn ld8(&mut self, addr: u16) -> u8 {
+        let page = self.mem.dma_request();
+        let byte = self.mem.byte_dma_request();
+        if page.is_some() || byte.is_some() {
+            self.halted = Some(Dma {
+                halted_addr: addr,
+                page: page.map(|(page, to)| PageDma {
+                    page,
+                    addr: to,
+                    copied: 0,
+                    val: None,
+                }),
+                // This read is the halt cycle
+                byte_wait: byte.map(|_| 1),
+            });
+        }
+        self.mem.ld8(addr)
+    }
+
+    fn ld16(&mut self, addr: u16) -> u16 {
+        let low = self.ld8(addr);
+        let high = self.ld8(addr.wrapping_add(1));
+        (high as u16) << 8 | low as u16
+    }
+
+    fn store(&mut self, addr: u16, val: u8) {
+        self.mem.store(addr, val);
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 pub enum Mode {
     Imm,
     ZP,
@@ -110,190 +309,546 @@ pub enum Mode {
     NoPBAbsX,
     AbsY,
     NoPBAbsY,
-    JmpIndir,
     IndX,
     IndY,
     NoPBIndY,
+    // The 65C02's (zp)
+    ZPInd,
 }
 
 impl Cpu {
     pub fn new<M: Memory>(mem: &mut M) -> Cpu {
+        Cpu::with_variant(mem, Variant::default())
+    }
+
+    pub fn with_variant<M: Memory>(mem: &mut M, variant: Variant) -> Cpu {
         let mut cpu = Cpu {
             regs: Registers {
                 acc: 0,
                 x: 0,
                 y: 0,
                 pc: ProgramCounter::new(0),
-                sp: 0xFD,
+                sp: 0,
                 flags: Flags(0b00100100),
             },
+            interrupts: InterruptState::default(),
+            variant,
+            halted: None,
+            cycles: 0,
+            partial: None,
+            dma: None,
         };
-        cpu.regs.pc.set_addr(mem.ld16(RESET_VEC));
+        cpu.reset(mem);
         cpu
     }
 
     pub fn from_registers(regs: Registers) -> Cpu {
-        Cpu { regs }
+        Cpu {
+            regs,
+            interrupts: InterruptState::default(),
+            variant: Variant::default(),
+            halted: None,
+            cycles: 0,
+            partial: None,
+            dma: None,
+        }
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+    }
+
+    // Why the CPU stopped, None while running instructions
+    pub fn halted(&self) -> Option<Halt> {
+        self.halted
     }
 
-    pub fn reset<M: Memory>(&mut self, mem: &mut M) {
-        let addr = mem.ld16(RESET_VEC);
-        self.regs.reset(addr);
+    // For restoring a saved state
+    pub fn set_halted(&mut self, halted: Option<Halt>) {
+        self.halted = halted;
     }
 
-    fn check_pb<M: Memory>(&mut self, low: u8, high: u8, offset: u8, mem: &mut M) -> u16 {
-        let (new_low, overflowed) = low.overflowing_add(offset);
-        if overflowed {
-            mem.ld8((high as u16) << 8 | new_low as u16);
+    // Holds the IRQ line low for source, a single bit the caller picks for
+    // each device. The line stays asserted while any source holds it.
+    pub fn set_irq(&mut self, source: u32, active: bool) {
+        if active {
+            self.interrupts.irq_sources |= source;
+        } else {
+            self.interrupts.irq_sources &= !source;
         }
-        ((high as u16) << 8 | (low as u16)) + offset as u16
     }
 
-    fn address_mem<M: Memory>(&mut self, mode: Mode, mem: &mut M) -> u16 {
-        match mode {
-            Mode::Imm => {
-                let tmp = self.regs.pc.get_addr();
-                self.regs.pc.add_unsigned(1);
-                tmp
+    pub fn irq_sources(&self) -> u32 {
+        self.interrupts.irq_sources
+    }
+
+    // Drives the NMI input, the NMI triggers when it goes from low to high
+    pub fn set_nmi(&mut self, active: bool) {
+        self.interrupts.nmi_input = active;
+    }
+
+    // The interrupt the next step will run instead of an instruction
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        self.interrupts.pending
+    }
+
+    // Total cycles run, including resets, interrupts and DMA
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn set_cycles(&mut self, cycles: u64) {
+        self.cycles = cycles;
+    }
+
+    // Every cycle is a bus read or write, and the interrupt lines get sampled
+    // at the end of each one
+    fn end_cycle<M: Memory>(&mut self, mem: &M) {
+        self.cycles += 1;
+        let state = &mut self.interrupts;
+        let nmi = state.nmi_input || mem.nmi_line();
+        if nmi && !state.nmi_level {
+            state.nmi_edge = true;
+        }
+        state.nmi_level = nmi;
+        let sample = Sample {
+            irq: state.irq_sources != 0 || mem.irq_line(),
+            nmi: state.nmi_edge,
+            itr: self.regs.flags.itr(),
+        };
+        state.samples = [sample, state.samples[0], state.samples[1]];
+    }
+
+    // Whether an NMI has come in, acknowledging it
+    fn take_nmi(&mut self) -> bool {
+        let nmi = self.interrupts.nmi_edge;
+        self.interrupts.nmi_edge = false;
+        // Otherwise the latched edge would get serviced again right away
+        for sample in self.interrupts.samples.iter_mut() {
+            sample.nmi = false;
+        }
+        nmi
+    }
+
+    // Reset runs the interrupt sequence with the stack writes turned into
+    // reads, so it takes 7 cycles and SP goes down by 3. According to the cpu
+    // reset registers test the interrupt flag gets set as well.
+    pub fn reset<M: Memory>(&mut self, mem: &mut M) -> u32 {
+        self.halted = None;
+        self.dma = None;
+        self.partial = Some(Partial::new(Work::Reset));
+        let cycles = self.step(mem);
+        self.interrupts.pending = None;
+        self.interrupts.nmi_edge = false;
+        cycles
+    }
+
+    fn reset_cycle<M: Memory>(&mut self, p: &mut Partial, mem: &mut M) -> bool {
+        match p.cycle {
+            1 | 2 => {
+                mem.ld8(self.regs.pc.get_addr());
             }
-            Mode::ZP => self.ld8_pc_up(mem) as u16,
-            Mode::ZPX => {
-                let tmp = self.ld8_pc_up(mem);
-                mem.ld8(tmp as u16);
-                tmp.wrapping_add(self.regs.x) as u16
+            3..=5 => {
+                mem.ld8(0x100 | self.regs.sp as u16);
+                self.regs.sp = self.regs.sp.wrapping_sub(1);
+                if p.cycle == 5 {
+                    self.regs.flags.set_itr(true);
+                    if self.variant.cmos() {
+                        self.regs.flags.set_dec(false);
+                    }
+                }
             }
-            Mode::ZPY => {
-                let tmp = self.ld8_pc_up(mem);
-                mem.ld8(tmp as u16);
-                tmp.wrapping_add(self.regs.y) as u16
+            6 => p.val = mem.ld8(RESET_VEC),
+            _ => {
+                let high = mem.ld8(RESET_VEC + 1);
+                self.regs.pc.set_addr((high as u16) << 8 | p.val as u16);
+                return true;
             }
-            Mode::Abs => self.ld16_pc_up(mem),
-            Mode::AbsX => {
-                let low = self.ld8_pc_up(mem);
-                let high = self.ld8_pc_up(mem);
-                self.check_pb(low, high, self.regs.x, mem)
+        }
+        false
+    }
+
+    // A cycle spent on address arithmetic. The NMOS 6502 reads from the
+    // address it has so far, the 65C02 reads the last instruction byte again.
+    fn dummy_read<M: Memory>(&mut self, addr: u16, mem: &mut M) {
+        if self.variant.cmos() {
+            mem.ld8(self.regs.pc.get_addr().wrapping_sub(1));
+        } else {
+            mem.ld8(addr);
+        }
+    }
+
+    fn crosses_page(base: u16, index: u8) -> bool {
+        (base as u8).checked_add(index).is_none()
+    }
+
+    // The cycle that carries the index into the high byte. It reads the
+    // address without the carry, which is the right one unless the index
+    // crossed a page.
+    fn fix_high<M: Memory>(&mut self, base: u16, index: u8, mem: &mut M) -> u16 {
+        let unfixed = base & 0xFF00 | (base as u8).wrapping_add(index) as u16;
+        if Cpu::crosses_page(base, index) {
+            self.dummy_read(unfixed, mem);
+        } else {
+            mem.ld8(unfixed);
+        }
+        base.wrapping_add(index as u16)
+    }
+
+    // Works out the address for mode, one bus cycle per call, and returns
+    // whether it's in p.addr now. Indexing only takes the cycle to fix the
+    // high byte when it crosses a page, apart from the NoPB modes.
+    fn address<M: Memory>(&mut self, mode: Mode, p: &mut Partial, mem: &mut M) -> bool {
+        let index = match mode {
+            Mode::ZPX | Mode::AbsX | Mode::NoPBAbsX | Mode::IndX => self.regs.x,
+            _ => self.regs.y,
+        };
+        match mode {
+            Mode::Imm => true,
+            Mode::ZP => {
+                p.addr = self.ld8_pc_up(mem) as u16;
+                true
             }
-            Mode::AbsY => {
-                let low = self.ld8_pc_up(mem);
-                let high = self.ld8_pc_up(mem);
-                self.check_pb(low, high, self.regs.y, mem)
+            Mode::ZPX | Mode::ZPY => {
+                if p.cycle == 2 {
+                    p.addr = self.ld8_pc_up(mem) as u16;
+                    return false;
+                }
+                self.dummy_read(p.addr, mem);
+                p.addr = (p.addr as u8).wrapping_add(index) as u16;
+                true
             }
-            Mode::NoPBAbsX => {
-                let low = self.ld8_pc_up(mem);
-                let high = self.ld8_pc_up(mem);
-                let (over_low, _) = low.overflowing_add(self.regs.x);
-                mem.ld8((high as u16) << 8 | over_low as u16);
-                ((high as u16) << 8 | low as u16) + self.regs.x as u16
+            Mode::Abs | Mode::AbsX | Mode::AbsY | Mode::NoPBAbsX | Mode::NoPBAbsY => {
+                match p.cycle {
+                    2 => {
+                        p.addr = self.ld8_pc_up(mem) as u16;
+                        false
+                    }
+                    3 => {
+                        p.addr |= (self.ld8_pc_up(mem) as u16) << 8;
+                        match mode {
+                            Mode::Abs => true,
+                            Mode::AbsX | Mode::AbsY if !Cpu::crosses_page(p.addr, index) => {
+                                p.addr = p.addr.wrapping_add(index as u16);
+                                true
+                            }
+                            _ => false,
+                        }
+                    }
+                    _ => {
+                        p.addr = self.fix_high(p.addr, index, mem);
+                        true
+                    }
+                }
             }
-            Mode::NoPBAbsY => {
-                let low = self.ld8_pc_up(mem);
-                let high = self.ld8_pc_up(mem);
-                let (over_low, _) = low.overflowing_add(self.regs.y);
-                mem.ld8((high as u16) << 8 | over_low as u16);
-                ((high as u16) << 8 | low as u16) + self.regs.y as u16
+            Mode::IndX => match p.cycle {
+                2 => {
+                    p.val = self.ld8_pc_up(mem);
+                    false
+                }
+                3 => {
+                    self.dummy_read(p.val as u16, mem);
+                    p.val = p.val.wrapping_add(index);
+                    false
+                }
+                4 => {
+                    p.addr = mem.ld8(p.val as u16) as u16;
+                    false
+                }
+                _ => {
+                    p.addr |= (mem.ld8(p.val.wrapping_add(1) as u16) as u16) << 8;
+                    true
+                }
+            },
+            Mode::IndY | Mode::NoPBIndY | Mode::ZPInd => match p.cycle {
+                2 => {
+                    p.val = self.ld8_pc_up(mem);
+                    false
+                }
+                3 => {
+                    p.addr = mem.ld8(p.val as u16) as u16;
+                    false
+                }
+                4 => {
+                    p.addr |= (mem.ld8(p.val.wrapping_add(1) as u16) as u16) << 8;
+                    match mode {
+                        Mode::ZPInd => true,
+                        Mode::IndY if !Cpu::crosses_page(p.addr, index) => {
+                            p.addr = p.addr.wrapping_add(index as u16);
+                            true
+                        }
+                        _ => false,
+                    }
+                }
+                _ => {
+                    p.addr = self.fix_high(p.addr, index, mem);
+                    true
+                }
+            },
+        }
+    }
+
+    // Spends the cycle on the address if it isn't ready yet, returning
+    // whether it did. Immediate operands are ready once the opcode is fetched.
+    fn addressing<M: Memory>(&mut self, mode: Mode, p: &mut Partial, mem: &mut M) -> bool {
+        if p.addressed != 0 {
+            return false;
+        }
+        if let Mode::Imm = mode {
+            p.addressed = 1;
+            return false;
+        }
+        if self.address(mode, p, mem) {
+            p.addressed = p.cycle;
+        }
+        true
+    }
+
+    fn operand<M: Memory>(&mut self, mode: Mode, p: &Partial, mem: &mut M) -> u8 {
+        match mode {
+            Mode::Imm => self.ld8_pc_up(mem),
+            _ => mem.ld8(p.addr),
+        }
+    }
+
+    // Read-modify-write instructions store the value they read while they
+    // work on it, the 65C02 reads it again instead
+    fn modify<M: Memory>(
+        &mut self,
+        mode: Mode,
+        p: &mut Partial,
+        mem: &mut M,
+        modify: impl FnOnce(&mut Cpu, u8) -> u8,
+    ) -> bool {
+        if self.addressing(mode, p, mem) {
+            return false;
+        }
+        match p.cycle - p.addressed {
+            1 => p.val = mem.ld8(p.addr),
+            2 if self.variant.cmos() => {
+                mem.ld8(p.addr);
             }
-            Mode::JmpIndir => {
-                let tmp = self.ld16_pc_up(mem);
-                let low = mem.ld8(tmp);
-                let high: u8 = if tmp & 0xFF == 0xFF {
-                    mem.ld8(tmp - 0xFF)
-                } else {
-                    mem.ld8(tmp + 1)
-                };
-                (high as u16) << 8 | (low as u16)
+            2 => mem.store(p.addr, p.val),
+            _ => {
+                let val = modify(self, p.val);
+                mem.store(p.addr, val);
+                return true;
             }
-            Mode::IndX => {
-                let tmp = self.ld8_pc_up(mem);
-                mem.ld8(tmp as u16);
-                let base_address = tmp.wrapping_add(self.regs.x) as u16;
-                if base_address == 0xFF {
-                    (mem.ld8(base_address) as u16) | (mem.ld8(0) as u16) << 8
-                } else {
-                    mem.ld16(base_address)
-                }
+        }
+        false
+    }
+
+    // SHA, SHX, SHY and TAS store val ANDed with the high byte of the base
+    // address plus one. When indexing crosses a page that value also
+    // replaces the high byte of the address.
+    fn store_high<M: Memory>(&mut self, addr: u16, val: u8, index: u8, mem: &mut M) {
+        let base = addr.wrapping_sub(index as u16);
+        let tmp = val & ((base >> 8) as u8).wrapping_add(1);
+        if (base ^ addr) & 0xFF00 != 0 {
+            mem.store((tmp as u16) << 8 | (addr & 0xFF), tmp);
+        } else {
+            mem.store(addr, tmp);
+        }
+    }
+
+    // The cycles of a branch after the opcode, starting with the offset
+    // fetch. A taken branch moves the low byte of PC on the next cycle, and
+    // takes one more to fix the high byte if that crossed a page.
+    fn branch<M: Memory>(&mut self, step: u8, taken: bool, p: &mut Partial, mem: &mut M) -> bool {
+        let pc = self.regs.pc.get_addr();
+        match step {
+            1 => {
+                p.val = self.ld8_pc_up(mem);
+                !taken
             }
-            Mode::IndY => {
-                let base = self.ld8_pc_up(mem);
-                let tmp = if base == 0xFF {
-                    (mem.ld8(0xFF) as u16) | (mem.ld8(0) as u16) << 8
-                } else {
-                    mem.ld16(base as u16)
-                };
-                self.check_pb(tmp as u8, (tmp >> 8) as u8, self.regs.y, mem)
+            2 => {
+                mem.ld8(pc);
+                p.addr = pc.wrapping_add(p.val as i8 as u16);
+                self.regs.pc.set_addr(pc & 0xFF00 | p.addr & 0xFF);
+                pc & 0xFF00 == p.addr & 0xFF00
+            }
+            _ => {
+                mem.ld8(pc);
+                self.regs.pc.set_addr(p.addr);
+                true
             }
-            Mode::NoPBIndY => {
-                let base = self.ld8_pc_up(mem);
-                let tmp = if base == 0xFF {
-                    (mem.ld8(0xFF) as u16) | (mem.ld8(0) as u16) << 8
+        }
+    }
+
+    // The 65C02 takes a cycle to carry into the high byte of the pointer,
+    // the NMOS 6502 wraps around the page instead
+    fn jmp_indirect<M: Memory>(&mut self, p: &mut Partial, mem: &mut M) -> bool {
+        let cmos = self.variant.cmos();
+        match (p.cycle, cmos) {
+            (2, _) => p.addr = self.ld8_pc_up(mem) as u16,
+            (3, _) => p.addr |= (self.ld8_pc_up(mem) as u16) << 8,
+            (4, true) => self.dummy_read(p.addr, mem),
+            (4, false) | (5, true) => p.val = mem.ld8(p.addr),
+            _ => {
+                let high_addr = if cmos {
+                    p.addr.wrapping_add(1)
                 } else {
-                    mem.ld16(base as u16)
+                    p.addr & 0xFF00 | (p.addr as u8).wrapping_add(1) as u16
                 };
-                let low = (tmp & 0xFF) as u8;
-                mem.ld8((tmp & 0xFF00) | low.wrapping_add(self.regs.y) as u16);
-                tmp.wrapping_add(self.regs.y as u16)
+                let high = mem.ld8(high_addr);
+                self.regs.pc.set_addr((high as u16) << 8 | p.val as u16);
+                return true;
             }
         }
+        false
     }
 
-    pub fn intr_handler<M: Memory>(&mut self, mem: &mut M, intr_vec: u16) {
-        // We read 2 bytes and discard, PC does NOT go up here
-        // Cycle 1 and 2
-        mem.ld8(self.regs.pc.get_addr());
-        mem.ld8(self.regs.pc.get_addr());
-        // Cycle 3 and 4
-        self.push_pc(mem);
-
-        // Clear break flag
-        self.regs.flags.set_brk(false);
-        let flags = self.regs.flags;
-
-        // Cycle 5
-        self.push(flags.as_byte(), mem);
+    // IRQ and NMI read the next opcode twice without using it, then run the
+    // same sequence as BRK
+    fn interrupt_cycle<M: Memory>(&mut self, p: &mut Partial, mem: &mut M) -> bool {
+        match p.cycle {
+            1 | 2 => {
+                mem.ld8(self.regs.pc.get_addr());
+                self.regs.flags.set_brk(false);
+                false
+            }
+            _ => self.interrupt_sequence(self.regs.flags.as_byte(), p, mem),
+        }
+    }
 
-        // Cycle 6 and 7
-        self.regs.pc.set_addr(mem.ld16(intr_vec));
-        self.regs.flags.set_itr(true);
+    // Pushes PC and the status, then jumps through a vector. An NMI that
+    // comes in before the status push hijacks the sequence, so BRK and IRQ
+    // end up at the NMI vector, which also services that NMI.
+    fn interrupt_sequence<M: Memory>(&mut self, status: u8, p: &mut Partial, mem: &mut M) -> bool {
+        let pc = self.regs.pc.get_addr();
+        match p.cycle {
+            3 => self.push((pc >> 8) as u8, mem),
+            4 => self.push(pc as u8, mem),
+            5 => {
+                p.addr = if self.take_nmi() { NMI_VEC } else { IRQ_VEC };
+                self.push(status, mem);
+            }
+            6 => {
+                p.val = mem.ld8(p.addr);
+                self.regs.flags.set_itr(true);
+                if self.variant.cmos() {
+                    self.regs.flags.set_dec(false);
+                }
+            }
+            _ => {
+                let high = mem.ld8(p.addr + 1);
+                self.regs.pc.set_addr((high as u16) << 8 | p.val as u16);
+                return true;
+            }
+        }
+        false
+    }
+
+    // Interrupts get polled at the end of the second to last cycle of every
+    // instruction, going by I as it was then. CLI, SEI and PLP change I on
+    // their last cycle, so the change only shows up one instruction later.
+    // Taken branches that stay on the same page don't poll on their last
+    // cycle.
+    fn poll_interrupts(&mut self, op: u8, cycles: u8) {
+        let interrupts = &mut self.interrupts;
+        let branch = matches!(op, BPL | BMI | BVC | BVS | BCC | BCS | BNE | BEQ | BRA);
+        let sample = if branch && cycles == 3 {
+            interrupts.samples[2]
+        } else {
+            interrupts.samples[1]
+        };
+        interrupts.pending = Cpu::interrupt_for(sample);
     }
 
-    fn read_op<M: Memory>(&mut self, mode: Mode, mem: &mut M) -> u8 {
-        let addr = self.address_mem(mode, mem);
-        mem.ld8(addr)
+    fn interrupt_for(sample: Sample) -> Option<Interrupt> {
+        if sample.nmi {
+            Some(Interrupt::Nmi)
+        } else if sample.irq && !sample.itr {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        }
     }
 
-    pub fn dma<M: Memory>(&mut self, high_nyb: u8, mem: &mut M, addr: u16) -> bool {
-        // let off_cycle = self.delta_cycles % 2;
+    // One cycle of the DMAs the CPU is halted for, see
+    // https://www.nesdev.org/wiki/DMA. A page copy is a read and a write for
+    // every byte, after an alignment cycle if it got halted on a get cycle,
+    // so 513 or 514 cycles with the one it got halted on. A byte read takes
+    // the first get cycle after its halt and dummy cycles. Those overlap a
+    // page copy that's already running, which then loses its get cycle and
+    // has to realign.
+    fn dma_cycle<M: Memory>(&mut self, mem: &mut M) {
+        let Some(dma) = &mut self.dma else {
+            return;
+        };
+        if dma.byte_wait.is_none() && mem.byte_dma_request().is_some() {
+            dma.byte_wait = Some(2);
+        }
+        let get = mem.is_get_cycle();
+        let byte_ready = dma.byte_wait == Some(0);
+        if let Some(wait) = &mut dma.byte_wait {
+            *wait = wait.saturating_sub(1);
+        }
 
-        let page_num = (high_nyb as u16) << 8;
-        for address in page_num..=page_num + 0xFF {
-            let tmp = mem.ld8(address);
-            mem.store(addr, tmp);
+        if byte_ready && get {
+            dma.byte_wait = None;
+            if let Some(addr) = mem.byte_dma_request() {
+                let val = mem.ld8(addr);
+                mem.byte_dma_done(val);
+            }
+        } else if let Some(page) = &mut dma.page
+            && let Some(val) = page.val.take()
+        {
+            mem.store(page.addr, val);
+            page.copied += 1;
+            if page.copied == 0x100 {
+                dma.page = None;
+                mem.dma_done();
+            }
+        } else if let Some(page) = &mut dma.page
+            && get
+        {
+            page.val = Some(mem.ld8((page.page as u16) << 8 | page.copied));
+        } else {
+            mem.ld8(dma.halted_addr);
         }
-        true
-        // off_cycle == 1
+        if dma.page.is_none() && dma.byte_wait.is_none() {
+            self.dma = None;
+        }
+        self.end_cycle(mem);
     }
 
-    fn and<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
-        let tmp = self.regs.acc & val;
-        self.set_zero_neg(tmp);
-        self.regs.acc = tmp;
+    fn and(&mut self, val: u8) {
+        self.regs.acc &= val;
+        self.set_zero_neg(self.regs.acc);
     }
 
-    fn ora<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
-        let tmp = self.regs.acc | val;
-        self.set_zero_neg(tmp);
-        self.regs.acc = tmp;
+    fn ora(&mut self, val: u8) {
+        self.regs.acc |= val;
+        self.set_zero_neg(self.regs.acc);
     }
 
-    fn eor<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
-        let tmp = self.regs.acc ^ val;
-        self.set_zero_neg(tmp);
-        self.regs.acc = tmp;
+    fn eor(&mut self, val: u8) {
+        self.regs.acc ^= val;
+        self.set_zero_neg(self.regs.acc);
+    }
+
+    fn adc(&mut self, val: u8) {
+        if self.regs.flags.dec() && self.variant.decimal() {
+            self.adc_decimal(val);
+        } else {
+            self.add_binary(val);
+        }
     }
 
-    fn adc_val(&mut self, val: u8) {
+    fn sbc(&mut self, val: u8) {
+        if self.regs.flags.dec() && self.variant.decimal() {
+            self.sbc_decimal(val);
+        } else {
+            self.add_binary(val ^ 0xFF);
+        }
+    }
+
+    fn add_binary(&mut self, val: u8) {
         let acc = self.regs.acc;
         let tmp = acc as u16 + val as u16 + self.regs.flags.carry() as u16;
         self.regs.flags.set_carry(tmp > 0xFF);
@@ -305,222 +860,182 @@ impl Cpu {
         self.regs.acc = tmp;
     }
 
-    fn adc<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
-        self.adc_val(val);
+    // BCD addition, see http://www.6502.org/tutorials/decimal_mode.html. The
+    // NMOS 6502 sets N and V before adjusting the high digit and Z from the
+    // binary sum, the 65C02 sets N and Z from the result.
+    fn adc_decimal(&mut self, val: u8) {
+        let acc = self.regs.acc;
+        let carry = self.regs.flags.carry() as u16;
+        let binary = acc.wrapping_add(val).wrapping_add(carry as u8);
+        let mut low = (acc & 0x0F) as u16 + (val & 0x0F) as u16 + carry;
+        if low >= 0x0A {
+            low = ((low + 0x06) & 0x0F) + 0x10;
+        }
+        let mut tmp = (acc & 0xF0) as u16 + (val & 0xF0) as u16 + low;
+        self.regs
+            .flags
+            .set_overflow(((acc as u16 ^ tmp) & (val as u16 ^ tmp) & 0x80) != 0);
+        self.set_zero_neg(tmp as u8);
+        self.regs.flags.set_zero(binary == 0);
+        if tmp >= 0xA0 {
+            tmp += 0x60;
+        }
+        self.regs.flags.set_carry(tmp > 0xFF);
+        self.regs.acc = tmp as u8;
+        if self.variant.cmos() {
+            self.set_zero_neg(self.regs.acc);
+        }
     }
 
-    fn sbc<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
-        self.adc_val(val ^ 0xFF);
+    // BCD subtraction sets the flags the same way as binary, apart from N and
+    // Z on the 65C02
+    fn sbc_decimal(&mut self, val: u8) {
+        let acc = self.regs.acc;
+        let borrow = !self.regs.flags.carry() as i16;
+        self.add_binary(val ^ 0xFF);
+        let mut low = (acc & 0x0F) as i16 - (val & 0x0F) as i16 - borrow;
+        let tmp = if self.variant.cmos() {
+            let mut tmp = acc as i16 - val as i16 - borrow;
+            if tmp < 0 {
+                tmp -= 0x60;
+            }
+            if low < 0 {
+                tmp -= 0x06;
+            }
+            tmp
+        } else {
+            if low < 0 {
+                low = ((low - 0x06) & 0x0F) - 0x10;
+            }
+            let mut tmp = (acc & 0xF0) as i16 - (val & 0xF0) as i16 + low;
+            if tmp < 0 {
+                tmp -= 0x60;
+            }
+            tmp
+        };
+        self.regs.acc = tmp as u8;
+        if self.variant.cmos() {
+            self.set_zero_neg(self.regs.acc);
+        }
     }
 
-    fn lda<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
+    fn lda(&mut self, val: u8) {
         self.regs.acc = val;
         self.set_zero_neg(val);
     }
 
-    fn ldx<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
+    fn ldx(&mut self, val: u8) {
         self.regs.x = val;
         self.set_zero_neg(val);
     }
 
-    fn ldy<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
+    fn ldy(&mut self, val: u8) {
         self.regs.y = val;
         self.set_zero_neg(val);
     }
 
-    fn ror_acc(&mut self) {
-        let (tmp, n_flag) = Cpu::get_ror(self.regs.flags.carry(), self.regs.acc);
+    fn ror(&mut self, val: u8) -> u8 {
+        let (tmp, n_flag) = Cpu::get_ror(self.regs.flags.carry(), val);
         self.regs.flags.set_carry(n_flag);
         self.set_zero_neg(tmp);
-        self.regs.acc = tmp;
+        tmp
     }
 
-    fn ror_addr<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let val = mem.ld8(addr);
-        let (tmp, n_flag) = Cpu::get_ror(self.regs.flags.carry(), val);
-        mem.store(addr, val);
-        self.regs.flags.set_carry(n_flag);
-        self.set_zero_neg(tmp);
-        mem.store(addr, tmp);
+    fn ror_acc(&mut self) {
+        self.regs.acc = self.ror(self.regs.acc);
     }
 
     fn get_ror(carry_flag: bool, val: u8) -> (u8, bool) {
         ((val >> 1) | ((carry_flag as u8) << 7), (val & 0b01) != 0)
     }
 
-    fn rol_acc(&mut self) {
-        let (tmp, n_flag) = Cpu::get_rol(self.regs.flags.carry(), self.regs.acc);
+    fn rol(&mut self, val: u8) -> u8 {
+        let (tmp, n_flag) = Cpu::get_rol(self.regs.flags.carry(), val);
         self.regs.flags.set_carry(n_flag);
         self.set_zero_neg(tmp);
-        self.regs.acc = tmp;
+        tmp
     }
 
-    fn rol_addr<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let val = mem.ld8(addr);
-        mem.store(addr, val);
-        let (tmp, n_flag) = Cpu::get_rol(self.regs.flags.carry(), val);
-        self.regs.flags.set_carry(n_flag);
-        self.set_zero_neg(tmp);
-        mem.store(addr, tmp);
+    fn rol_acc(&mut self) {
+        self.regs.acc = self.rol(self.regs.acc);
     }
 
     fn get_rol(carry_flag: bool, val: u8) -> (u8, bool) {
         ((val << 1) | (carry_flag as u8), (val & 0x80) != 0)
     }
 
-    fn asl_acc(&mut self) {
-        let acc = self.regs.acc;
-        self.regs.flags.set_carry((acc >> 7) != 0);
-        let tmp = acc << 1;
-        self.set_zero_neg(tmp);
-        self.regs.acc = tmp;
-    }
-
-    fn asl_addr<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let val = mem.ld8(addr);
-        mem.store(addr, val);
+    fn asl(&mut self, val: u8) -> u8 {
         self.regs.flags.set_carry((val >> 7) != 0);
         let tmp = val << 1;
         self.set_zero_neg(tmp);
-        mem.store(addr, tmp);
+        tmp
     }
 
-    fn lsr_acc(&mut self) {
-        let acc = self.regs.acc;
-        self.regs.flags.set_carry((acc & 0b01) != 0);
-        let tmp = acc >> 1;
-        self.set_zero_neg(tmp);
-        self.regs.acc = tmp;
+    fn asl_acc(&mut self) {
+        self.regs.acc = self.asl(self.regs.acc);
     }
 
-    fn lsr_addr<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let val = mem.ld8(addr);
-        mem.store(addr, val);
+    fn lsr(&mut self, val: u8) -> u8 {
         self.regs.flags.set_carry((val & 0b01) != 0);
         let tmp = val >> 1;
         self.set_zero_neg(tmp);
-        mem.store(addr, tmp);
+        tmp
     }
 
-    fn cpx<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
-        let tmp = self.regs.x as i16 - val as i16;
-        self.regs.flags.set_carry(tmp >= 0);
-        self.set_zero_neg(tmp as u8);
+    fn lsr_acc(&mut self) {
+        self.regs.acc = self.lsr(self.regs.acc);
     }
 
-    fn cpy<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
-        let tmp = self.regs.y as i16 - val as i16;
+    fn compare(&mut self, reg: u8, val: u8) {
+        let tmp = reg as i16 - val as i16;
         self.regs.flags.set_carry(tmp >= 0);
         self.set_zero_neg(tmp as u8);
     }
 
-    fn cmp<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
-        let tmp = self.regs.acc as i16 - val as i16;
-        self.regs.flags.set_carry(tmp >= 0);
-        self.set_zero_neg(tmp as u8);
+    fn cpx(&mut self, val: u8) {
+        self.compare(self.regs.x, val);
     }
 
-    fn generic_branch<M: Memory>(&mut self, flag: bool, mem: &mut M) {
-        let val = self.ld8_pc_up(mem) as i8;
-        if flag {
-            let addr = self.regs.pc.get_addr();
-            mem.ld8(addr);
-            let low = (addr & 0x00FF) as u8;
-
-            let overflow_low;
-            let carry;
-
-            if val >= 0 {
-                let us_v = val as u8;
-                (overflow_low, carry) = low.overflowing_add(us_v);
-            } else {
-                let us_v = (val * -1) as u8;
-                (overflow_low, carry) = low.overflowing_sub(us_v);
-            }
-
-            if carry {
-                mem.ld8((addr & 0xFF00) | overflow_low as u16);
-            }
+    fn cpy(&mut self, val: u8) {
+        self.compare(self.regs.y, val);
+    }
 
-            self.regs.pc.add_signed(val as i8);
-        }
+    fn cmp(&mut self, val: u8) {
+        self.compare(self.regs.acc, val);
     }
 
-    fn bit<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
+    fn bit(&mut self, val: u8) {
         let acc = self.regs.acc;
         self.regs.flags.set_zero((val & acc) == 0);
         self.regs.flags.set_overflow((val & 0x40) != 0);
         self.regs.flags.set_neg((val & 0x80) != 0);
     }
 
-    fn dec<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let val: u8 = mem.ld8(addr).wrapping_sub(1);
+    fn dec(&mut self, val: u8) -> u8 {
+        let val = val.wrapping_sub(1);
         self.set_zero_neg(val);
-        mem.store(addr, val + 1);
-        mem.store(addr, val);
+        val
     }
 
-    fn inc<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let val: u8 = mem.ld8(addr).wrapping_add(1);
+    fn inc(&mut self, val: u8) -> u8 {
+        let val = val.wrapping_add(1);
         self.set_zero_neg(val);
-        mem.store(addr, val - 1);
-        mem.store(addr, val);
-    }
-
-    fn sta<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let tmp = self.regs.acc;
-        mem.store(addr, tmp);
+        val
     }
 
-    fn stx<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let tmp = self.regs.x;
-        mem.store(addr, tmp);
-    }
-
-    fn sty<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let tmp = self.regs.y;
-        mem.store(addr, tmp);
-    }
+    fn nop_read(&mut self, _val: u8) {}
 
-    fn jmp<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        self.regs.pc.set_addr(addr);
-    }
-
-    fn aac<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
-        let acc = self.regs.acc;
-        self.regs.acc = acc & val;
-        self.set_zero_neg(self.regs.acc);
+    fn aac(&mut self, val: u8) {
+        self.and(val);
         self.regs.flags.set_carry(self.regs.flags.neg());
     }
 
-    fn aax<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let tmp = self.regs.acc & self.regs.x;
-        mem.store(addr, tmp);
-    }
-
-    fn arr<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        self.and(mode, mem);
+    fn arr(&mut self, val: u8) {
+        self.and(val);
+        if self.regs.flags.dec() && self.variant.decimal() {
+            self.arr_decimal();
+            return;
+        }
         let (acc, _) = Cpu::get_ror(self.regs.flags.carry(), self.regs.acc);
         self.regs.acc = acc;
         let b5 = ((acc >> 5) & 1) == 1;
@@ -530,20 +1045,38 @@ impl Cpu {
         self.set_zero_neg(self.regs.acc);
     }
 
-    fn alr<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        self.and(mode, mem);
+    // In decimal mode ARR fixes up both digits of the rotated value the way ADC
+    // would, going by the AND result before the rotate
+    fn arr_decimal(&mut self) {
+        let and = self.regs.acc;
+        let carry = self.regs.flags.carry();
+        let mut acc = (and >> 1) | (carry as u8) << 7;
+        self.regs.flags.set_neg(carry);
+        self.regs.flags.set_zero(acc == 0);
+        self.regs.flags.set_overflow((and ^ acc) & 0x40 != 0);
+        if (and & 0x0F) + (and & 0x01) > 0x05 {
+            acc = (acc & 0xF0) | (acc.wrapping_add(0x06) & 0x0F);
+        }
+        let high = (and & 0xF0) as u16 + (and & 0x10) as u16 > 0x50;
+        if high {
+            acc = acc.wrapping_add(0x60);
+        }
+        self.regs.flags.set_carry(high);
+        self.regs.acc = acc;
+    }
+
+    fn alr(&mut self, val: u8) {
+        self.and(val);
         self.lsr_acc();
     }
 
-    fn lax<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
+    fn lax(&mut self, val: u8) {
         self.regs.acc = val;
         self.regs.x = val;
         self.set_zero_neg(val);
     }
 
-    fn axs<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let val = self.read_op(mode, mem);
+    fn axs(&mut self, val: u8) {
         let tmp = self.regs.x & self.regs.acc;
         let (tmp, carry) = tmp.overflowing_sub(val);
         // No idea why this is !carry
@@ -552,205 +1085,296 @@ impl Cpu {
         self.set_zero_neg(tmp);
     }
 
-    //TODO this is dec followed by cmp, refactor this to use those functions
-    fn dcp<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let val: u8 = mem.ld8(addr).wrapping_sub(1);
-        self.set_zero_neg(val);
-        mem.store(addr, val);
-        let tmp = self.regs.acc as i16 - val as i16;
-        self.regs.flags.set_carry(tmp >= 0);
-        self.set_zero_neg(tmp as u8);
+    // The unofficial read-modify-write instructions do the official one and
+    // then use the result the way another official instruction would. Like
+    // the official ones, they write the value they read back unchanged first.
+    // DCP is DEC then CMP.
+    fn dcp(&mut self, val: u8) -> u8 {
+        let val = val.wrapping_sub(1);
+        self.cmp(val);
+        val
     }
 
-    //TODO This one can also probably be refactored
-    fn isc<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let val: u8 = mem.ld8(addr).wrapping_add(1);
-        self.set_zero_neg(val);
-        mem.store(addr, val);
-        self.adc_val(val ^ 0xFF);
+    // INC then SBC
+    fn isc(&mut self, val: u8) -> u8 {
+        let val = val.wrapping_add(1);
+        self.sbc(val);
+        val
     }
 
-    //TODO same as this one
-    fn slo<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let val = mem.ld8(addr);
+    // ASL then ORA
+    fn slo(&mut self, val: u8) -> u8 {
         self.regs.flags.set_carry((val >> 7) != 0);
-        let tmp = val << 1;
-        mem.store(addr, tmp);
-
-        let tmp = self.regs.acc | tmp;
-        self.set_zero_neg(tmp);
-        self.regs.acc = tmp;
+        let val = val << 1;
+        self.ora(val);
+        val
     }
 
-    fn rla<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let (tmp, n_flag) = Cpu::get_rol(self.regs.flags.carry(), mem.ld8(addr));
+    // ROL then AND
+    fn rla(&mut self, val: u8) -> u8 {
+        let (val, n_flag) = Cpu::get_rol(self.regs.flags.carry(), val);
         self.regs.flags.set_carry(n_flag);
-        mem.store(addr, tmp);
-
-        let tmp = self.regs.acc & tmp;
-        self.set_zero_neg(tmp);
-        self.regs.acc = tmp;
+        self.and(val);
+        val
     }
 
-    fn sre<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let val = mem.ld8(addr);
+    // LSR then EOR
+    fn sre(&mut self, val: u8) -> u8 {
         self.regs.flags.set_carry((val & 0b01) != 0);
-        let tmp = val >> 1;
-        mem.store(addr, tmp);
+        let val = val >> 1;
+        self.eor(val);
+        val
+    }
 
-        let tmp = self.regs.acc ^ tmp;
+    // ROR then ADC, which adds the carry the rotate shifted out
+    fn rra(&mut self, val: u8) -> u8 {
+        let (val, n_flag) = Cpu::get_ror(self.regs.flags.carry(), val);
+        self.regs.flags.set_carry(n_flag);
+        self.adc(val);
+        val
+    }
+
+    // LXA and XAA mix A with bus noise that differs from chip to chip, this
+    // is the value the 65x02 tests use
+    fn lxa(&mut self, val: u8) {
+        let tmp = (self.regs.acc | UNSTABLE_MAGIC) & val;
+        self.regs.acc = tmp;
+        self.regs.x = tmp;
         self.set_zero_neg(tmp);
+    }
+
+    fn xaa(&mut self, val: u8) {
+        let tmp = (self.regs.acc | UNSTABLE_MAGIC) & self.regs.x & val;
         self.regs.acc = tmp;
+        self.set_zero_neg(tmp);
     }
 
-    fn rra<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        let addr = self.address_mem(mode, mem);
-        let (tmp, n_flag) = Cpu::get_ror(self.regs.flags.carry(), mem.ld8(addr));
-        self.regs.flags.set_carry(n_flag);
+    fn las(&mut self, val: u8) {
+        let tmp = val & self.regs.sp;
+        self.regs.acc = tmp;
+        self.regs.x = tmp;
+        self.regs.sp = tmp;
         self.set_zero_neg(tmp);
-        mem.store(addr, tmp);
-        self.adc_val(tmp);
     }
 
-    fn tax(&mut self) {
-        let acc = self.regs.acc;
-        self.regs.x = acc;
-        self.set_zero_neg(acc);
+    fn sta(&mut self) -> u8 {
+        self.regs.acc
     }
 
-    fn atx<M: Memory>(&mut self, mode: Mode, mem: &mut M) {
-        self.lda(mode, mem);
-        self.tax();
+    fn stx(&mut self) -> u8 {
+        self.regs.x
     }
 
-    fn sna<M: Memory>(&mut self, mode: Mode, and_reg: u8, addr_mode_reg: u8, mem: &mut M) {
-        let mut addr = self.address_mem(mode, mem);
-        if (addr - addr_mode_reg as u16) & 0xFF00 != addr & 0xFF00 {
-            addr &= (and_reg as u16) << 8;
-        }
-        mem.store(addr, and_reg & (((addr >> 8) as u8).wrapping_add(1)));
+    fn sty(&mut self) -> u8 {
+        self.regs.y
     }
 
-    fn brk<M: Memory>(&mut self, mem: &mut M) {
-        // Dummy read
-        mem.ld8(self.regs.pc.get_addr());
-        self.regs.pc.add_signed(1);
-        self.push_pc(mem);
-        self.push(self.regs.flags.as_byte() | 0b10000, mem);
-        self.regs.flags.set_itr(true);
-        self.regs.pc.set_addr(mem.ld16(IRQ_VEC));
+    fn stz(&mut self) -> u8 {
+        0
     }
 
-    fn rts<M: Memory>(&mut self, mem: &mut M) {
-        mem.ld8(self.regs.sp as u16 | 0x100);
-        self.pull_pc(mem);
-        mem.ld8(self.regs.pc.get_addr());
-        self.regs.pc.add_unsigned(1);
+    fn aax(&mut self) -> u8 {
+        self.regs.acc & self.regs.x
     }
 
-    fn rti<M: Memory>(&mut self, mem: &mut M) {
-        self.pull_status(mem);
-        self.pull_pc(mem);
+    fn sha(&mut self) -> (u8, u8) {
+        (self.regs.acc & self.regs.x, self.regs.y)
     }
 
-    fn jsr<M: Memory>(&mut self, mem: &mut M) {
-        let low = self.ld8_pc_up(mem);
-        mem.ld8(self.regs.sp as u16 | 0x100);
-        self.push_pc(mem);
-        let high = self.ld8_pc_up(mem);
-        self.regs.pc.set_addr((high as u16) << 8 | low as u16);
+    fn shx(&mut self) -> (u8, u8) {
+        (self.regs.x, self.regs.y)
     }
 
-    fn pla<M: Memory>(&mut self, mem: &mut M) {
-        mem.ld8(self.regs.sp as u16 | 0x100);
-        let acc = self.pop(mem);
-        self.regs.acc = acc;
-        self.set_zero_neg(acc);
+    fn shy(&mut self) -> (u8, u8) {
+        (self.regs.y, self.regs.x)
+    }
+
+    fn tas(&mut self) -> (u8, u8) {
+        self.regs.sp = self.regs.acc & self.regs.x;
+        (self.regs.sp, self.regs.y)
+    }
+
+    fn tsb(&mut self, val: u8) -> u8 {
+        self.regs.flags.set_zero(val & self.regs.acc == 0);
+        val | self.regs.acc
+    }
+
+    fn trb(&mut self, val: u8) -> u8 {
+        self.regs.flags.set_zero(val & self.regs.acc == 0);
+        val & !self.regs.acc
+    }
+
+    fn tax(&mut self) {
+        self.ldx(self.regs.acc);
     }
 
     fn txa(&mut self) {
-        let x = self.regs.x;
-        self.regs.acc = x;
-        self.set_zero_neg(x);
+        self.lda(self.regs.x);
     }
+
     fn tay(&mut self) {
-        let acc = self.regs.acc;
-        self.regs.y = acc;
-        self.set_zero_neg(acc);
+        self.ldy(self.regs.acc);
     }
+
     fn tya(&mut self) {
-        let y = self.regs.y;
-        self.regs.acc = y;
-        self.set_zero_neg(y);
+        self.lda(self.regs.y);
     }
+
     fn dex(&mut self) {
-        self.regs.x = self.regs.x.wrapping_sub(1);
-        self.set_zero_neg(self.regs.x);
+        self.regs.x = self.dec(self.regs.x);
     }
+
     fn inx(&mut self) {
-        self.regs.x = self.regs.x.wrapping_add(1);
-        self.set_zero_neg(self.regs.x);
+        self.regs.x = self.inc(self.regs.x);
     }
+
     fn dey(&mut self) {
-        self.regs.y = self.regs.y.wrapping_sub(1);
-        self.set_zero_neg(self.regs.y);
+        self.regs.y = self.dec(self.regs.y);
     }
+
     fn iny(&mut self) {
-        self.regs.y = self.regs.y.wrapping_add(1);
-        self.set_zero_neg(self.regs.y);
+        self.regs.y = self.inc(self.regs.y);
     }
+
     fn tsx(&mut self) {
-        self.regs.x = self.regs.sp;
-        self.set_zero_neg(self.regs.sp);
+        self.ldx(self.regs.sp);
+    }
+
+    fn inc_acc(&mut self) {
+        self.regs.acc = self.inc(self.regs.acc);
+    }
+
+    fn dec_acc(&mut self) {
+        self.regs.acc = self.dec(self.regs.acc);
+    }
+
+    fn set_status(&mut self, val: u8) {
+        self.regs.flags.set_byte(val);
+        self.regs.flags.set_unused(true);
+        self.regs.flags.set_brk(false);
     }
 
     fn push<M: Memory>(&mut self, val: u8, mem: &mut M) {
         let addr = self.regs.sp as u16 | 0x100;
         mem.store(addr, val);
-        self.regs.sp -= 1;
+        self.regs.sp = self.regs.sp.wrapping_sub(1);
     }
 
     fn pop<M: Memory>(&mut self, mem: &mut M) -> u8 {
-        self.regs.sp += 1;
+        self.regs.sp = self.regs.sp.wrapping_add(1);
         mem.ld8(self.regs.sp as u16 | 0x100)
     }
 
-    fn pull_pc<M: Memory>(&mut self, mem: &mut M) {
-        let low = self.pop(mem);
-        let high = self.pop(mem);
-        self.regs.pc.set_addr(((high as u16) << 8) | low as u16);
+    fn set_zero_neg(&mut self, val: u8) {
+        self.regs.flags.set_neg(val >> 7 == 1);
+        self.regs.flags.set_zero(val == 0);
     }
 
-    fn pull_status<M: Memory>(&mut self, mem: &mut M) {
-        mem.ld8(self.regs.sp as u16 | 0x100);
-        let tmp = self.pop(mem);
-        self.regs.flags.set_byte(tmp);
-        self.regs.flags.set_unused(true);
-        self.regs.flags.set_brk(false);
+    // Runs one instruction, or the pending interrupt, and returns how many
+    // cycles it took. Finishes the instruction tick was in the middle of.
+    pub fn step<M: Memory>(&mut self, mem: &mut M) -> u32 {
+        let mut cycles = 1;
+        while !self.tick(mem) {
+            cycles += 1;
+        }
+        cycles
     }
 
-    fn push_pc<M: Memory>(&mut self, mem: &mut M) {
-        let high = self.regs.pc.get_addr() >> 8;
-        let low = self.regs.pc.get_addr();
-        self.push(high as u8, mem);
-        self.push(low as u8, mem);
+    // Runs a single bus cycle of the current instruction or interrupt, and
+    // returns whether that was its last one. A DMA halts the CPU on the next
+    // read cycle, and its cycles are ticks of the instruction it halted.
+    pub fn tick<M: Memory>(&mut self, mem: &mut M) -> bool {
+        if self.dma.is_some() {
+            self.dma_cycle(mem);
+            return false;
+        }
+        if let Some(halt) = self.halted {
+            self.idle(halt, mem);
+            return true;
+        }
+        let start = self.partial.unwrap_or_else(|| {
+            Partial::new(match self.interrupts.pending.take() {
+                Some(_) => Work::Interrupt,
+                None => Work::Fetch,
+            })
+        });
+        let regs = self.regs;
+        let mut partial = start;
+        partial.cycle += 1;
+        let mut bus = CycleBus { mem, halted: None };
+        let done = self.cycle(&mut partial, &mut bus);
+
+        if let Some(dma) = bus.halted {
+            // The cycle runs again once the DMA is over
+            self.regs = regs;
+            self.halted = None;
+            self.partial = Some(start);
+            self.dma = Some(dma);
+            self.end_cycle(mem);
+            return false;
+        }
+        self.end_cycle(mem);
+        if !done {
+            self.partial = Some(partial);
+            return false;
+        }
+        self.partial = None;
+        self.finish(&partial);
+        true
     }
 
-    fn set_zero_neg(&mut self, val: u8) {
-        self.regs.flags.set_neg(val >> 7 == 1);
-        self.regs.flags.set_zero(val == 0);
+    // A halted CPU keeps reading the next opcode, one cycle per step, apart
+    // from a jammed one which is stuck on $FFFF. WAI ends as soon as an
+    // interrupt line is active, even an IRQ while I is set, which just
+    // carries on with the next instruction.
+    fn idle<M: Memory>(&mut self, halt: Halt, mem: &mut M) {
+        if halt == Halt::Jam {
+            mem.ld8(0xFFFF);
+        } else {
+            mem.ld8(self.regs.pc.get_addr());
+        }
+        self.end_cycle(mem);
+        let sample = self.interrupts.samples[0];
+        if halt == Halt::Wait && (sample.irq || sample.nmi) {
+            self.halted = None;
+            self.interrupts.pending = Cpu::interrupt_for(sample);
+        }
+    }
+
+    // Whether tick is part way through an instruction, including when it's
+    // halted for DMA
+    pub fn mid_instruction(&self) -> bool {
+        self.partial.is_some() || self.dma.is_some()
+    }
+
+    fn cycle<M: Memory>(&mut self, p: &mut Partial, mem: &mut M) -> bool {
+        match p.work {
+            Work::Fetch => {
+                let op = self.ld8_pc_up(mem);
+                let instr = self.decode(op);
+                p.work = Work::Instruction(op, instr);
+                matches!(instr, Instr::Skip)
+            }
+            Work::Instruction(op, instr) => self.instruction_cycle(op, instr, p, mem),
+            Work::Interrupt => self.interrupt_cycle(p, mem),
+            Work::Reset => self.reset_cycle(p, mem),
+        }
     }
 
-    pub fn step<M: Memory>(&mut self, mem: &mut M) {
-        let byte = self.ld8_pc_up(mem);
-        self.execute_op(byte, mem);
+    // The first instruction of an interrupt handler always runs, everything
+    // else polls for the next interrupt
+    fn finish(&mut self, p: &Partial) {
+        let Work::Instruction(op, _) = p.work else {
+            return;
+        };
+        if op != BRK {
+            self.poll_interrupts(op, p.cycle);
+        }
+        if self.halted == Some(Halt::Wait) && self.interrupts.pending.is_some() {
+            self.halted = None;
+        }
         if log_enabled!(Level::Debug) {
-            debug!("INST: {:X} {:?}", byte, self.regs.clone(),);
+            debug!("INST: {:X} {:?}", op, self.regs.clone(),);
         }
     }
 
@@ -760,335 +1384,577 @@ impl Cpu {
         mem.ld8(ram_ptr)
     }
 
-    fn ld16_pc_up<M: Memory>(&mut self, mem: &mut M) -> u16 {
-        let ram_ptr = self.regs.pc.get_addr();
-        self.regs.pc.add_unsigned(2);
-        let tmp = mem.ld16(ram_ptr);
-        tmp
-    }
-
-    pub fn execute_op<M: Memory>(&mut self, op: u8, mem: &mut M) {
-        match op {
-            INC_ABSX => self.inc(Mode::NoPBAbsX, mem),
-            DEC_ABSX => self.dec(Mode::NoPBAbsX, mem),
-            STA_ABSX => self.sta(Mode::NoPBAbsX, mem),
-            ROR_ABSX => self.ror_addr(Mode::NoPBAbsX, mem),
-            LSR_ABSX => self.lsr_addr(Mode::NoPBAbsX, mem),
-            ROL_ABSX => self.rol_addr(Mode::NoPBAbsX, mem),
-            0xDF => self.dcp(Mode::NoPBAbsX, mem),
-            ASL_ABSX => self.asl_addr(Mode::NoPBAbsX, mem),
-            0x7F => self.rra(Mode::NoPBAbsX, mem),
-            0x3F => self.rla(Mode::NoPBAbsX, mem),
-            0x1F => self.slo(Mode::NoPBAbsX, mem),
-            0xFF => self.isc(Mode::NoPBAbsX, mem),
-            0x5F => self.sre(Mode::NoPBAbsX, mem),
-
-            0xDB => self.dcp(Mode::NoPBAbsY, mem),
-            STA_ABSY => self.sta(Mode::NoPBAbsY, mem),
-            0xFB => self.isc(Mode::NoPBAbsY, mem),
-            0x1B => self.slo(Mode::NoPBAbsY, mem),
-            0x3B => self.rla(Mode::NoPBAbsY, mem),
-            0x5B => self.sre(Mode::NoPBAbsY, mem),
-            0x7B => self.rra(Mode::NoPBAbsY, mem),
-
-            INC_ZPX => self.inc(Mode::ZPX, mem),
-            SBC_ZPX => self.sbc(Mode::ZPX, mem),
-            DEC_ZPX => self.dec(Mode::ZPX, mem),
-            CMP_ZPX => self.cmp(Mode::ZPX, mem),
-            LDA_ZPX => self.lda(Mode::ZPX, mem),
-            LDY_ZPX => self.ldy(Mode::ZPX, mem),
-            STA_ZPX => self.sta(Mode::ZPX, mem),
-            STY_ZPX => self.sty(Mode::ZPX, mem),
-            ADC_ZPX => self.adc(Mode::ZPX, mem),
-            ROR_ZPX => self.ror_addr(Mode::ZPX, mem),
-            EOR_ZPX => self.eor(Mode::ZPX, mem),
-            LSR_ZPX => self.lsr_addr(Mode::ZPX, mem),
-            ROL_ZPX => self.rol_addr(Mode::ZPX, mem),
-            AND_ZPX => self.and(Mode::ZPX, mem),
-            ORA_ZPX => self.ora(Mode::ZPX, mem),
-            ASL_ZPX => self.asl_addr(Mode::ZPX, mem),
-            0xD7 => self.dcp(Mode::ZPX, mem),
-            0xF7 => self.isc(Mode::ZPX, mem),
-            0x17 => self.slo(Mode::ZPX, mem),
-            0x37 => self.rla(Mode::ZPX, mem),
-            0x57 => self.sre(Mode::ZPX, mem),
-            0x77 => self.rra(Mode::ZPX, mem),
-
-            INC_ABS => self.inc(Mode::Abs, mem),
-            SBC_ABS => self.sbc(Mode::Abs, mem),
-            CPX_ABS => self.cpx(Mode::Abs, mem),
-            LDX_ABS => self.ldx(Mode::Abs, mem),
-            DEC_ABS => self.dec(Mode::Abs, mem),
-            CMP_ABS => self.cmp(Mode::Abs, mem),
-            CPY_ABS => self.cpy(Mode::Abs, mem),
-            LDA_ABS => self.lda(Mode::Abs, mem),
-            LDY_ABS => self.ldy(Mode::Abs, mem),
-            STA_ABS => self.sta(Mode::Abs, mem),
-            STX_ABS => self.stx(Mode::Abs, mem),
-            STY_ABS => self.sty(Mode::Abs, mem),
-            ADC_ABS => self.adc(Mode::Abs, mem),
-            ROR_ABS => self.ror_addr(Mode::Abs, mem),
-            EOR_ABS => self.eor(Mode::Abs, mem),
-            LSR_ABS => self.lsr_addr(Mode::Abs, mem),
-            JMP_ABS => self.jmp(Mode::Abs, mem),
-            ROL_ABS => self.rol_addr(Mode::Abs, mem),
-            AND_ABS => self.and(Mode::Abs, mem),
-            BIT_ABS => self.bit(Mode::Abs, mem),
-            ORA_ABS => self.ora(Mode::Abs, mem),
-            ASL_ABS => self.asl_addr(Mode::Abs, mem),
-            0x8F => self.aax(Mode::Abs, mem),
-            0xAF => self.lax(Mode::Abs, mem),
-            0xCF => self.dcp(Mode::Abs, mem),
-            0xEF => self.isc(Mode::Abs, mem),
-            0x0F => self.slo(Mode::Abs, mem),
-            0x2F => self.rla(Mode::Abs, mem),
-            0x4F => self.sre(Mode::Abs, mem),
-            0x6F => self.rra(Mode::Abs, mem),
-            // TOP: Triple NOP
-            0x0C => {
-                self.address_mem(Mode::Abs, mem);
-            }
-
-            EOR_ZP => self.eor(Mode::ZP, mem),
-            ROR_ZP => self.ror_addr(Mode::ZP, mem),
-            LSR_ZP => self.lsr_addr(Mode::ZP, mem),
-            ROL_ZP => self.rol_addr(Mode::ZP, mem),
-            AND_ZP => self.and(Mode::ZP, mem),
-            BIT_ZP => self.bit(Mode::ZP, mem),
-            ORA_ZP => self.ora(Mode::ZP, mem),
-            ASL_ZP => self.asl_addr(Mode::ZP, mem),
-            0x87 => self.aax(Mode::ZP, mem),
-            0xA7 => self.lax(Mode::ZP, mem),
-            0xC7 => self.dcp(Mode::ZP, mem),
-            0xE7 => self.isc(Mode::ZP, mem),
-            0x07 => self.slo(Mode::ZP, mem),
-            0x27 => self.rla(Mode::ZP, mem),
-            0x47 => self.sre(Mode::ZP, mem),
-            0x67 => self.rra(Mode::ZP, mem),
-            INC_ZP => self.inc(Mode::ZP, mem),
-            CPX_ZP => self.cpx(Mode::ZP, mem),
-            LDX_ZP => self.ldx(Mode::ZP, mem),
-            DEC_ZP => self.dec(Mode::ZP, mem),
-            CMP_ZP => self.cmp(Mode::ZP, mem),
-            CPY_ZP => self.cpy(Mode::ZP, mem),
-            LDA_ZP => self.lda(Mode::ZP, mem),
-            LDY_ZP => self.ldy(Mode::ZP, mem),
-            STA_ZP => self.sta(Mode::ZP, mem),
-            STX_ZP => self.stx(Mode::ZP, mem),
-            STY_ZP => self.sty(Mode::ZP, mem),
-            ADC_ZP => self.adc(Mode::ZP, mem),
-            SBC_ZP => self.sbc(Mode::ZP, mem),
-
-            LDY_IMM => self.ldy(Mode::Imm, mem),
-            SBC_IMM | 0xEB => self.sbc(Mode::Imm, mem),
-            0x6B => self.arr(Mode::Imm, mem),
-            0xCB => self.axs(Mode::Imm, mem),
-            ADC_IMM => self.adc(Mode::Imm, mem),
-            0x4B => self.alr(Mode::Imm, mem),
-            EOR_IMM => self.eor(Mode::Imm, mem),
-            0xAB => self.atx(Mode::Imm, mem),
-            AND_IMM => self.and(Mode::Imm, mem),
-            ORA_IMM => self.ora(Mode::Imm, mem),
-            CPX_IMM => self.cpx(Mode::Imm, mem),
-            LDX_IMM => self.ldx(Mode::Imm, mem),
-            0x0B | 0x2B => self.aac(Mode::Imm, mem),
-            CMP_IMM => self.cmp(Mode::Imm, mem),
-            CPY_IMM => self.cpy(Mode::Imm, mem),
-            LDA_IMM => self.lda(Mode::Imm, mem),
-
-            SBC_ABSX => self.sbc(Mode::AbsX, mem),
-            CMP_ABSX => self.cmp(Mode::AbsX, mem),
-            LDA_ABSX => self.lda(Mode::AbsX, mem),
-            LDY_ABSX => self.ldy(Mode::AbsX, mem),
-            ADC_ABSX => self.adc(Mode::AbsX, mem),
-            EOR_ABSX => self.eor(Mode::AbsX, mem),
-            AND_ABSX => self.and(Mode::AbsX, mem),
-            ORA_ABSX => self.ora(Mode::AbsX, mem),
-            0x9C => self.sna(Mode::AbsX, self.regs.y, self.regs.x, mem), //sya
-            // The memory access here is necessary, since even though we don't use the u16 retrieved,
-            // we need to emulate the absolute X retrieval in case there is a page boundary cross
-            0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => {
-                self.address_mem(Mode::AbsX, mem);
-            }
-
-            SBC_ABSY => self.sbc(Mode::AbsY, mem),
-            LDX_ABSY => self.ldx(Mode::AbsY, mem),
-            CMP_ABSY => self.cmp(Mode::AbsY, mem),
-            LDA_ABSY => self.lda(Mode::AbsY, mem),
-            ADC_ABSY => self.adc(Mode::AbsY, mem),
-            EOR_ABSY => self.eor(Mode::AbsY, mem),
-            AND_ABSY => self.and(Mode::AbsY, mem),
-            ORA_ABSY => self.ora(Mode::AbsY, mem),
-            0xBF => self.lax(Mode::AbsY, mem),
-            0x9E => self.sna(Mode::AbsY, self.regs.x, self.regs.y, mem), //sxa
-
-            EOR_INDY => self.eor(Mode::IndY, mem),
-            AND_INDY => self.and(Mode::IndY, mem),
-            ORA_INDY => self.ora(Mode::IndY, mem),
-            SBC_INDY => self.sbc(Mode::IndY, mem),
-            CMP_INDY => self.cmp(Mode::IndY, mem),
-            LDA_INDY => self.lda(Mode::IndY, mem),
-            ADC_INDY => self.adc(Mode::IndY, mem),
-            0xB3 => self.lax(Mode::IndY, mem),
-
-            SBC_INDX => self.sbc(Mode::IndX, mem),
-            CMP_INDX => self.cmp(Mode::IndX, mem),
-            LDA_INDX => self.lda(Mode::IndX, mem),
-            STA_INDX => self.sta(Mode::IndX, mem),
-            ADC_INDX => self.adc(Mode::IndX, mem),
-            EOR_INDX => self.eor(Mode::IndX, mem),
-            AND_INDX => self.and(Mode::IndX, mem),
-            ORA_INDX => self.ora(Mode::IndX, mem),
-            0x83 => self.aax(Mode::IndX, mem),
-            0xA3 => self.lax(Mode::IndX, mem),
-            0xC3 => self.dcp(Mode::IndX, mem),
-            0xE3 => self.isc(Mode::IndX, mem),
-            0x03 => self.slo(Mode::IndX, mem),
-            0x23 => self.rla(Mode::IndX, mem),
-            0x43 => self.sre(Mode::IndX, mem),
-            0x63 => self.rra(Mode::IndX, mem),
-
-            LDX_ZPY => self.ldx(Mode::ZPY, mem),
-            STX_ZPY => self.stx(Mode::ZPY, mem),
-            0x97 => self.aax(Mode::ZPY, mem),
-            0xB7 => self.lax(Mode::ZPY, mem),
-
-            STA_INDY => self.sta(Mode::NoPBIndY, mem),
-            0xD3 => self.dcp(Mode::NoPBIndY, mem),
-            0xF3 => self.isc(Mode::NoPBIndY, mem),
-            0x13 => self.slo(Mode::NoPBIndY, mem),
-            0x33 => self.rla(Mode::NoPBIndY, mem),
-            0x53 => self.sre(Mode::NoPBIndY, mem),
-            0x73 => self.rra(Mode::NoPBIndY, mem),
-
-            ROR_ACC => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.ror_acc();
-            }
-            LSR_ACC => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.lsr_acc();
-            }
-            ROL_ACC => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.rol_acc();
-            }
-            ASL_ACC => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.asl_acc();
-            }
-            RTS => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.rts(mem);
-            }
-            RTI => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.rti(mem);
-            }
-            SED => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.regs.flags.set_dec(true);
-            }
-            CLC => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.regs.flags.set_carry(false);
-            }
-            SEC => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.regs.flags.set_carry(true);
-            }
-            CLI => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.regs.flags.set_itr(false);
-            }
-            SEI => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.regs.flags.set_itr(true);
-            }
-            CLV => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.regs.flags.set_overflow(false);
-            }
-            CLD => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.regs.flags.set_dec(false);
-            }
-            NOP | 0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => {
-                mem.ld8(self.regs.pc.get_addr());
-            }
-            // DOP: Double NOP
-            0x14 | 0x34 | 0x44 | 0x54 | 0x64 | 0x74 | 0x80 | 0x82 | 0x89 | 0xC2 | 0xD4 | 0xE2
-            | 0xF4 | 0x04 => {
-                self.ld8_pc_up(mem);
-            }
-            BRK => self.brk(mem),
-            TAX => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.tax();
-            }
-            TXA => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.txa();
+    // Runs an already fetched opcode, returns the cycles it took after the
+    // fetch
+    pub fn execute_op<M: Memory>(&mut self, op: u8, mem: &mut M) -> u32 {
+        let instr = self.decode(op);
+        if let Instr::Skip = instr {
+            return 0;
+        }
+        let mut partial = Partial::new(Work::Instruction(op, instr));
+        partial.cycle = 1;
+        self.partial = Some(partial);
+        self.step(mem)
+    }
+
+    // One cycle of an instruction after its opcode fetch
+    fn instruction_cycle<M: Memory>(
+        &mut self,
+        op: u8,
+        instr: Instr,
+        p: &mut Partial,
+        mem: &mut M,
+    ) -> bool {
+        let pc = self.regs.pc.get_addr();
+        let stack = 0x100 | self.regs.sp as u16;
+        match instr {
+            Instr::Skip => true,
+            Instr::Implied(apply) => {
+                mem.ld8(pc);
+                apply(self);
+                true
             }
-            TAY => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.tay();
+            Instr::Read(mode, apply) => {
+                if self.addressing(mode, p, mem) {
+                    return false;
+                }
+                let val = self.operand(mode, p, mem);
+                apply(self, val);
+                true
             }
-            TYA => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.tya();
+            Instr::Arithmetic(mode, apply) => {
+                if self.addressing(mode, p, mem) {
+                    return false;
+                }
+                if p.cycle == p.addressed + 1 {
+                    let val = self.operand(mode, p, mem);
+                    apply(self, val);
+                    return !(self.regs.flags.dec() && self.variant.cmos());
+                }
+                // The 65C02 takes an extra cycle to get decimal mode flags right
+                mem.ld8(pc.wrapping_sub(1));
+                true
             }
-            DEX => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.dex();
+            Instr::Write(mode, value) => {
+                if self.addressing(mode, p, mem) {
+                    return false;
+                }
+                let val = value(self);
+                mem.store(p.addr, val);
+                true
             }
-            INX => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.inx();
+            Instr::StoreHigh(mode, value) => {
+                if self.addressing(mode, p, mem) {
+                    return false;
+                }
+                let (val, index) = value(self);
+                self.store_high(p.addr, val, index, mem);
+                true
             }
-            DEY => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.dey();
+            Instr::Modify(mode, modify) => self.modify(mode, p, mem, modify),
+            // The opcode picks the bit and whether it gets set
+            Instr::SetBit => {
+                let mask = 1 << ((op >> 4) & 0b111);
+                let set = op & 0x80 != 0;
+                let modify = move |_: &mut Cpu, val: u8| if set { val | mask } else { val & !mask };
+                self.modify(Mode::ZP, p, mem, modify)
             }
-            INY => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.iny();
+            // The opcode picks the bit and which value branches
+            Instr::BranchOnBit => match p.cycle {
+                2 => {
+                    p.addr = self.ld8_pc_up(mem) as u16;
+                    false
+                }
+                3 => {
+                    p.val = mem.ld8(p.addr);
+                    false
+                }
+                4 => {
+                    mem.ld8(p.addr);
+                    false
+                }
+                _ => {
+                    let bit = p.val & 1 << ((op >> 4) & 0b111) != 0;
+                    self.branch(p.cycle - 4, bit == (op & 0x80 != 0), p, mem)
+                }
+            },
+            Instr::Push(value) => {
+                if p.cycle == 2 {
+                    mem.ld8(pc);
+                    return false;
+                }
+                self.push(value(self), mem);
+                true
             }
-            TSX => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.tsx();
+            Instr::Pull(apply) => match p.cycle {
+                2 => {
+                    mem.ld8(pc);
+                    false
+                }
+                3 => {
+                    mem.ld8(stack);
+                    false
+                }
+                _ => {
+                    let val = self.pop(mem);
+                    apply(self, val);
+                    true
+                }
+            },
+            Instr::Branch(taken) => {
+                let taken = taken(self);
+                self.branch(p.cycle - 1, taken, p, mem)
             }
-            TXS => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.regs.sp = self.regs.x;
+            Instr::Jsr => match p.cycle {
+                2 => {
+                    p.val = self.ld8_pc_up(mem);
+                    false
+                }
+                3 => {
+                    mem.ld8(stack);
+                    false
+                }
+                4 => {
+                    self.push((pc >> 8) as u8, mem);
+                    false
+                }
+                5 => {
+                    self.push(pc as u8, mem);
+                    false
+                }
+                _ => {
+                    let high = self.ld8_pc_up(mem);
+                    self.regs.pc.set_addr((high as u16) << 8 | p.val as u16);
+                    true
+                }
+            },
+            Instr::Rts => match p.cycle {
+                2 => {
+                    mem.ld8(pc);
+                    false
+                }
+                3 => {
+                    mem.ld8(stack);
+                    false
+                }
+                4 => {
+                    p.val = self.pop(mem);
+                    false
+                }
+                5 => {
+                    let high = self.pop(mem);
+                    self.regs.pc.set_addr((high as u16) << 8 | p.val as u16);
+                    false
+                }
+                _ => {
+                    self.ld8_pc_up(mem);
+                    true
+                }
+            },
+            Instr::Rti => match p.cycle {
+                2 => {
+                    mem.ld8(pc);
+                    false
+                }
+                3 => {
+                    mem.ld8(stack);
+                    false
+                }
+                4 => {
+                    let status = self.pop(mem);
+                    self.set_status(status);
+                    false
+                }
+                5 => {
+                    p.val = self.pop(mem);
+                    false
+                }
+                _ => {
+                    let high = self.pop(mem);
+                    self.regs.pc.set_addr((high as u16) << 8 | p.val as u16);
+                    true
+                }
+            },
+            Instr::Brk => {
+                if p.cycle == 2 {
+                    self.ld8_pc_up(mem);
+                    return false;
+                }
+                self.interrupt_sequence(self.regs.flags.as_byte() | 0b10000, p, mem)
             }
-            PHA => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.push(self.regs.acc, mem);
+            Instr::JmpAbs => {
+                if p.cycle == 2 {
+                    p.val = self.ld8_pc_up(mem);
+                    return false;
+                }
+                let high = self.ld8_pc_up(mem);
+                self.regs.pc.set_addr((high as u16) << 8 | p.val as u16);
+                true
             }
-            PLA => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.pla(mem);
+            Instr::JmpInd => self.jmp_indirect(p, mem),
+            Instr::JmpAbsIndX => match p.cycle {
+                2 => {
+                    p.addr = self.ld8_pc_up(mem) as u16;
+                    false
+                }
+                3 => {
+                    p.addr |= (self.ld8_pc_up(mem) as u16) << 8;
+                    false
+                }
+                4 => {
+                    self.dummy_read(p.addr, mem);
+                    p.addr = p.addr.wrapping_add(self.regs.x as u16);
+                    false
+                }
+                5 => {
+                    p.val = mem.ld8(p.addr);
+                    false
+                }
+                _ => {
+                    let high = mem.ld8(p.addr.wrapping_add(1));
+                    self.regs.pc.set_addr((high as u16) << 8 | p.val as u16);
+                    true
+                }
+            },
+            Instr::Halt(halt) => {
+                mem.ld8(pc);
+                if p.cycle < 3 {
+                    return false;
+                }
+                self.halted = Some(halt);
+                true
             }
-            PHP => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.push(self.regs.flags.as_byte() | 0b10000, mem);
+            // JAM reads the byte after it and then locks up
+            Instr::Jam => {
+                mem.ld8(pc);
+                self.halted = Some(Halt::Jam);
+                true
             }
-            PLP => {
-                mem.ld8(self.regs.pc.get_addr());
-                self.pull_status(mem);
+            Instr::LongNop => match p.cycle {
+                2 => {
+                    p.addr = self.ld8_pc_up(mem) as u16;
+                    false
+                }
+                3 => {
+                    self.ld8_pc_up(mem);
+                    false
+                }
+                _ => {
+                    mem.ld8(0xFF00 | p.addr);
+                    p.cycle == 8
+                }
+            },
+        }
+    }
+
+    fn decode(&self, op: u8) -> Instr {
+        if self.variant.cmos() {
+            self.decode_cmos(op)
+        } else {
+            Cpu::decode_nmos(op)
+        }
+    }
+
+    // The 65C02 opcodes that do something different from the NMOS ones,
+    // everything else runs the same apart from the addressing cycles
+    fn decode_cmos(&self, op: u8) -> Instr {
+        match op {
+            // Only INC and DEC always take the cycle to fix the high byte
+            ASL_ABSX => Instr::Modify(Mode::AbsX, Cpu::asl),
+            LSR_ABSX => Instr::Modify(Mode::AbsX, Cpu::lsr),
+            ROL_ABSX => Instr::Modify(Mode::AbsX, Cpu::rol),
+            ROR_ABSX => Instr::Modify(Mode::AbsX, Cpu::ror),
+
+            ORA_ZPIND => Instr::Read(Mode::ZPInd, Cpu::ora),
+            AND_ZPIND => Instr::Read(Mode::ZPInd, Cpu::and),
+            EOR_ZPIND => Instr::Read(Mode::ZPInd, Cpu::eor),
+            ADC_ZPIND => Instr::Arithmetic(Mode::ZPInd, Cpu::adc),
+            STA_ZPIND => Instr::Write(Mode::ZPInd, Cpu::sta),
+            LDA_ZPIND => Instr::Read(Mode::ZPInd, Cpu::lda),
+            CMP_ZPIND => Instr::Read(Mode::ZPInd, Cpu::cmp),
+            SBC_ZPIND => Instr::Arithmetic(Mode::ZPInd, Cpu::sbc),
+
+            STZ_ZP => Instr::Write(Mode::ZP, Cpu::stz),
+            STZ_ZPX => Instr::Write(Mode::ZPX, Cpu::stz),
+            STZ_ABS => Instr::Write(Mode::Abs, Cpu::stz),
+            STZ_ABSX => Instr::Write(Mode::NoPBAbsX, Cpu::stz),
+            TSB_ZP => Instr::Modify(Mode::ZP, Cpu::tsb),
+            TSB_ABS => Instr::Modify(Mode::Abs, Cpu::tsb),
+            TRB_ZP => Instr::Modify(Mode::ZP, Cpu::trb),
+            TRB_ABS => Instr::Modify(Mode::Abs, Cpu::trb),
+            BIT_ZPX => Instr::Read(Mode::ZPX, Cpu::bit),
+            BIT_ABSX => Instr::Read(Mode::AbsX, Cpu::bit),
+            // Immediate BIT only sets Z
+            BIT_IMM => Instr::Read(Mode::Imm, |cpu, val| {
+                cpu.regs.flags.set_zero(val & cpu.regs.acc == 0)
+            }),
+            JMP_ABSX_IND => Instr::JmpAbsIndX,
+            BRA => Instr::Branch(|_| true),
+
+            INC_ACC => Instr::Implied(Cpu::inc_acc),
+            DEC_ACC => Instr::Implied(Cpu::dec_acc),
+            PHX => Instr::Push(|cpu| cpu.regs.x),
+            PHY => Instr::Push(|cpu| cpu.regs.y),
+            PLX => Instr::Pull(Cpu::ldx),
+            PLY => Instr::Pull(Cpu::ldy),
+
+            WAI if self.variant == Variant::Wdc65C02 => Instr::Halt(Halt::Wait),
+            STP if self.variant == Variant::Wdc65C02 => Instr::Halt(Halt::Stop),
+            _ if op & 0x0F == 0x07 => Instr::SetBit,
+            _ if op & 0x0F == 0x0F => Instr::BranchOnBit,
+
+            // The undefined opcodes are NOPs of different lengths
+            _ if op & 0x07 == 0x03 => Instr::Skip,
+            0x02 | 0x22 | 0x42 | 0x62 | 0x82 | 0xC2 | 0xE2 => Instr::Read(Mode::Imm, Cpu::nop_read),
+            0x44 => Instr::Read(Mode::ZP, Cpu::nop_read),
+            0x54 | 0xD4 | 0xF4 => Instr::Read(Mode::ZPX, Cpu::nop_read),
+            0xDC | 0xFC => Instr::Read(Mode::Abs, Cpu::nop_read),
+            0x5C => Instr::LongNop,
+            _ => Cpu::decode_nmos(op),
+        }
+    }
+
+    fn decode_nmos(op: u8) -> Instr {
+        match op {
+            INC_ABSX => Instr::Modify(Mode::NoPBAbsX, Cpu::inc),
+            DEC_ABSX => Instr::Modify(Mode::NoPBAbsX, Cpu::dec),
+            STA_ABSX => Instr::Write(Mode::NoPBAbsX, Cpu::sta),
+            ROR_ABSX => Instr::Modify(Mode::NoPBAbsX, Cpu::ror),
+            LSR_ABSX => Instr::Modify(Mode::NoPBAbsX, Cpu::lsr),
+            ROL_ABSX => Instr::Modify(Mode::NoPBAbsX, Cpu::rol),
+            0xDF => Instr::Modify(Mode::NoPBAbsX, Cpu::dcp),
+            ASL_ABSX => Instr::Modify(Mode::NoPBAbsX, Cpu::asl),
+            0x7F => Instr::Modify(Mode::NoPBAbsX, Cpu::rra),
+            0x3F => Instr::Modify(Mode::NoPBAbsX, Cpu::rla),
+            0x1F => Instr::Modify(Mode::NoPBAbsX, Cpu::slo),
+            0xFF => Instr::Modify(Mode::NoPBAbsX, Cpu::isc),
+            0x5F => Instr::Modify(Mode::NoPBAbsX, Cpu::sre),
+
+            0xDB => Instr::Modify(Mode::NoPBAbsY, Cpu::dcp),
+            STA_ABSY => Instr::Write(Mode::NoPBAbsY, Cpu::sta),
+            0xFB => Instr::Modify(Mode::NoPBAbsY, Cpu::isc),
+            0x1B => Instr::Modify(Mode::NoPBAbsY, Cpu::slo),
+            0x3B => Instr::Modify(Mode::NoPBAbsY, Cpu::rla),
+            0x5B => Instr::Modify(Mode::NoPBAbsY, Cpu::sre),
+            0x7B => Instr::Modify(Mode::NoPBAbsY, Cpu::rra),
+
+            INC_ZPX => Instr::Modify(Mode::ZPX, Cpu::inc),
+            SBC_ZPX => Instr::Arithmetic(Mode::ZPX, Cpu::sbc),
+            DEC_ZPX => Instr::Modify(Mode::ZPX, Cpu::dec),
+            CMP_ZPX => Instr::Read(Mode::ZPX, Cpu::cmp),
+            LDA_ZPX => Instr::Read(Mode::ZPX, Cpu::lda),
+            LDY_ZPX => Instr::Read(Mode::ZPX, Cpu::ldy),
+            STA_ZPX => Instr::Write(Mode::ZPX, Cpu::sta),
+            STY_ZPX => Instr::Write(Mode::ZPX, Cpu::sty),
+            ADC_ZPX => Instr::Arithmetic(Mode::ZPX, Cpu::adc),
+            ROR_ZPX => Instr::Modify(Mode::ZPX, Cpu::ror),
+            EOR_ZPX => Instr::Read(Mode::ZPX, Cpu::eor),
+            LSR_ZPX => Instr::Modify(Mode::ZPX, Cpu::lsr),
+            ROL_ZPX => Instr::Modify(Mode::ZPX, Cpu::rol),
+            AND_ZPX => Instr::Read(Mode::ZPX, Cpu::and),
+            ORA_ZPX => Instr::Read(Mode::ZPX, Cpu::ora),
+            ASL_ZPX => Instr::Modify(Mode::ZPX, Cpu::asl),
+            0xD7 => Instr::Modify(Mode::ZPX, Cpu::dcp),
+            0xF7 => Instr::Modify(Mode::ZPX, Cpu::isc),
+            0x17 => Instr::Modify(Mode::ZPX, Cpu::slo),
+            0x37 => Instr::Modify(Mode::ZPX, Cpu::rla),
+            0x57 => Instr::Modify(Mode::ZPX, Cpu::sre),
+            0x77 => Instr::Modify(Mode::ZPX, Cpu::rra),
+
+            INC_ABS => Instr::Modify(Mode::Abs, Cpu::inc),
+            SBC_ABS => Instr::Arithmetic(Mode::Abs, Cpu::sbc),
+            CPX_ABS => Instr::Read(Mode::Abs, Cpu::cpx),
+            LDX_ABS => Instr::Read(Mode::Abs, Cpu::ldx),
+            DEC_ABS => Instr::Modify(Mode::Abs, Cpu::dec),
+            CMP_ABS => Instr::Read(Mode::Abs, Cpu::cmp),
+            CPY_ABS => Instr::Read(Mode::Abs, Cpu::cpy),
+            LDA_ABS => Instr::Read(Mode::Abs, Cpu::lda),
+            LDY_ABS => Instr::Read(Mode::Abs, Cpu::ldy),
+            STA_ABS => Instr::Write(Mode::Abs, Cpu::sta),
+            STX_ABS => Instr::Write(Mode::Abs, Cpu::stx),
+            STY_ABS => Instr::Write(Mode::Abs, Cpu::sty),
+            ADC_ABS => Instr::Arithmetic(Mode::Abs, Cpu::adc),
+            ROR_ABS => Instr::Modify(Mode::Abs, Cpu::ror),
+            EOR_ABS => Instr::Read(Mode::Abs, Cpu::eor),
+            LSR_ABS => Instr::Modify(Mode::Abs, Cpu::lsr),
+            JMP_ABS => Instr::JmpAbs,
+            ROL_ABS => Instr::Modify(Mode::Abs, Cpu::rol),
+            AND_ABS => Instr::Read(Mode::Abs, Cpu::and),
+            BIT_ABS => Instr::Read(Mode::Abs, Cpu::bit),
+            ORA_ABS => Instr::Read(Mode::Abs, Cpu::ora),
+            ASL_ABS => Instr::Modify(Mode::Abs, Cpu::asl),
+            0x8F => Instr::Write(Mode::Abs, Cpu::aax),
+            0xAF => Instr::Read(Mode::Abs, Cpu::lax),
+            0xCF => Instr::Modify(Mode::Abs, Cpu::dcp),
+            0xEF => Instr::Modify(Mode::Abs, Cpu::isc),
+            0x0F => Instr::Modify(Mode::Abs, Cpu::slo),
+            0x2F => Instr::Modify(Mode::Abs, Cpu::rla),
+            0x4F => Instr::Modify(Mode::Abs, Cpu::sre),
+            0x6F => Instr::Modify(Mode::Abs, Cpu::rra),
+            // TOP: Triple NOP, which still reads the address
+            0x0C => Instr::Read(Mode::Abs, Cpu::nop_read),
+
+            EOR_ZP => Instr::Read(Mode::ZP, Cpu::eor),
+            ROR_ZP => Instr::Modify(Mode::ZP, Cpu::ror),
+            LSR_ZP => Instr::Modify(Mode::ZP, Cpu::lsr),
+            ROL_ZP => Instr::Modify(Mode::ZP, Cpu::rol),
+            AND_ZP => Instr::Read(Mode::ZP, Cpu::and),
+            BIT_ZP => Instr::Read(Mode::ZP, Cpu::bit),
+            ORA_ZP => Instr::Read(Mode::ZP, Cpu::ora),
+            ASL_ZP => Instr::Modify(Mode::ZP, Cpu::asl),
+            0x87 => Instr::Write(Mode::ZP, Cpu::aax),
+            0xA7 => Instr::Read(Mode::ZP, Cpu::lax),
+            0xC7 => Instr::Modify(Mode::ZP, Cpu::dcp),
+            0xE7 => Instr::Modify(Mode::ZP, Cpu::isc),
+            0x07 => Instr::Modify(Mode::ZP, Cpu::slo),
+            0x27 => Instr::Modify(Mode::ZP, Cpu::rla),
+            0x47 => Instr::Modify(Mode::ZP, Cpu::sre),
+            0x67 => Instr::Modify(Mode::ZP, Cpu::rra),
+            INC_ZP => Instr::Modify(Mode::ZP, Cpu::inc),
+            CPX_ZP => Instr::Read(Mode::ZP, Cpu::cpx),
+            LDX_ZP => Instr::Read(Mode::ZP, Cpu::ldx),
+            DEC_ZP => Instr::Modify(Mode::ZP, Cpu::dec),
+            CMP_ZP => Instr::Read(Mode::ZP, Cpu::cmp),
+            CPY_ZP => Instr::Read(Mode::ZP, Cpu::cpy),
+            LDA_ZP => Instr::Read(Mode::ZP, Cpu::lda),
+            LDY_ZP => Instr::Read(Mode::ZP, Cpu::ldy),
+            STA_ZP => Instr::Write(Mode::ZP, Cpu::sta),
+            STX_ZP => Instr::Write(Mode::ZP, Cpu::stx),
+            STY_ZP => Instr::Write(Mode::ZP, Cpu::sty),
+            ADC_ZP => Instr::Arithmetic(Mode::ZP, Cpu::adc),
+            SBC_ZP => Instr::Arithmetic(Mode::ZP, Cpu::sbc),
+
+            LDY_IMM => Instr::Read(Mode::Imm, Cpu::ldy),
+            SBC_IMM | 0xEB => Instr::Arithmetic(Mode::Imm, Cpu::sbc),
+            0x6B => Instr::Read(Mode::Imm, Cpu::arr),
+            0xCB => Instr::Read(Mode::Imm, Cpu::axs),
+            ADC_IMM => Instr::Arithmetic(Mode::Imm, Cpu::adc),
+            0x4B => Instr::Read(Mode::Imm, Cpu::alr),
+            EOR_IMM => Instr::Read(Mode::Imm, Cpu::eor),
+            0xAB => Instr::Read(Mode::Imm, Cpu::lxa),
+            0x8B => Instr::Read(Mode::Imm, Cpu::xaa),
+            AND_IMM => Instr::Read(Mode::Imm, Cpu::and),
+            ORA_IMM => Instr::Read(Mode::Imm, Cpu::ora),
+            CPX_IMM => Instr::Read(Mode::Imm, Cpu::cpx),
+            LDX_IMM => Instr::Read(Mode::Imm, Cpu::ldx),
+            0x0B | 0x2B => Instr::Read(Mode::Imm, Cpu::aac),
+            CMP_IMM => Instr::Read(Mode::Imm, Cpu::cmp),
+            CPY_IMM => Instr::Read(Mode::Imm, Cpu::cpy),
+            LDA_IMM => Instr::Read(Mode::Imm, Cpu::lda),
+
+            SBC_ABSX => Instr::Arithmetic(Mode::AbsX, Cpu::sbc),
+            CMP_ABSX => Instr::Read(Mode::AbsX, Cpu::cmp),
+            LDA_ABSX => Instr::Read(Mode::AbsX, Cpu::lda),
+            LDY_ABSX => Instr::Read(Mode::AbsX, Cpu::ldy),
+            ADC_ABSX => Instr::Arithmetic(Mode::AbsX, Cpu::adc),
+            EOR_ABSX => Instr::Read(Mode::AbsX, Cpu::eor),
+            AND_ABSX => Instr::Read(Mode::AbsX, Cpu::and),
+            ORA_ABSX => Instr::Read(Mode::AbsX, Cpu::ora),
+            0x9C => Instr::StoreHigh(Mode::NoPBAbsX, Cpu::shy),
+            // These read the address like any other absolute,X read, taking
+            // the extra cycle when indexing crosses a page
+            0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => Instr::Read(Mode::AbsX, Cpu::nop_read),
+
+            SBC_ABSY => Instr::Arithmetic(Mode::AbsY, Cpu::sbc),
+            LDX_ABSY => Instr::Read(Mode::AbsY, Cpu::ldx),
+            CMP_ABSY => Instr::Read(Mode::AbsY, Cpu::cmp),
+            LDA_ABSY => Instr::Read(Mode::AbsY, Cpu::lda),
+            ADC_ABSY => Instr::Arithmetic(Mode::AbsY, Cpu::adc),
+            EOR_ABSY => Instr::Read(Mode::AbsY, Cpu::eor),
+            AND_ABSY => Instr::Read(Mode::AbsY, Cpu::and),
+            ORA_ABSY => Instr::Read(Mode::AbsY, Cpu::ora),
+            0xBF => Instr::Read(Mode::AbsY, Cpu::lax),
+            0x9E => Instr::StoreHigh(Mode::NoPBAbsY, Cpu::shx),
+            0x9F => Instr::StoreHigh(Mode::NoPBAbsY, Cpu::sha),
+            0x9B => Instr::StoreHigh(Mode::NoPBAbsY, Cpu::tas),
+            0xBB => Instr::Read(Mode::AbsY, Cpu::las),
+
+            EOR_INDY => Instr::Read(Mode::IndY, Cpu::eor),
+            AND_INDY => Instr::Read(Mode::IndY, Cpu::and),
+            ORA_INDY => Instr::Read(Mode::IndY, Cpu::ora),
+            SBC_INDY => Instr::Arithmetic(Mode::IndY, Cpu::sbc),
+            CMP_INDY => Instr::Read(Mode::IndY, Cpu::cmp),
+            LDA_INDY => Instr::Read(Mode::IndY, Cpu::lda),
+            ADC_INDY => Instr::Arithmetic(Mode::IndY, Cpu::adc),
+            0xB3 => Instr::Read(Mode::IndY, Cpu::lax),
+
+            SBC_INDX => Instr::Arithmetic(Mode::IndX, Cpu::sbc),
+            CMP_INDX => Instr::Read(Mode::IndX, Cpu::cmp),
+            LDA_INDX => Instr::Read(Mode::IndX, Cpu::lda),
+            STA_INDX => Instr::Write(Mode::IndX, Cpu::sta),
+            ADC_INDX => Instr::Arithmetic(Mode::IndX, Cpu::adc),
+            EOR_INDX => Instr::Read(Mode::IndX, Cpu::eor),
+            AND_INDX => Instr::Read(Mode::IndX, Cpu::and),
+            ORA_INDX => Instr::Read(Mode::IndX, Cpu::ora),
+            0x83 => Instr::Write(Mode::IndX, Cpu::aax),
+            0xA3 => Instr::Read(Mode::IndX, Cpu::lax),
+            0xC3 => Instr::Modify(Mode::IndX, Cpu::dcp),
+            0xE3 => Instr::Modify(Mode::IndX, Cpu::isc),
+            0x03 => Instr::Modify(Mode::IndX, Cpu::slo),
+            0x23 => Instr::Modify(Mode::IndX, Cpu::rla),
+            0x43 => Instr::Modify(Mode::IndX, Cpu::sre),
+            0x63 => Instr::Modify(Mode::IndX, Cpu::rra),
+
+            LDX_ZPY => Instr::Read(Mode::ZPY, Cpu::ldx),
+            STX_ZPY => Instr::Write(Mode::ZPY, Cpu::stx),
+            0x97 => Instr::Write(Mode::ZPY, Cpu::aax),
+            0xB7 => Instr::Read(Mode::ZPY, Cpu::lax),
+
+            STA_INDY => Instr::Write(Mode::NoPBIndY, Cpu::sta),
+            0xD3 => Instr::Modify(Mode::NoPBIndY, Cpu::dcp),
+            0xF3 => Instr::Modify(Mode::NoPBIndY, Cpu::isc),
+            0x13 => Instr::Modify(Mode::NoPBIndY, Cpu::slo),
+            0x33 => Instr::Modify(Mode::NoPBIndY, Cpu::rla),
+            0x53 => Instr::Modify(Mode::NoPBIndY, Cpu::sre),
+            0x73 => Instr::Modify(Mode::NoPBIndY, Cpu::rra),
+            0x93 => Instr::StoreHigh(Mode::NoPBIndY, Cpu::sha),
+
+            ROR_ACC => Instr::Implied(Cpu::ror_acc),
+            LSR_ACC => Instr::Implied(Cpu::lsr_acc),
+            ROL_ACC => Instr::Implied(Cpu::rol_acc),
+            ASL_ACC => Instr::Implied(Cpu::asl_acc),
+            RTS => Instr::Rts,
+            RTI => Instr::Rti,
+            SED => Instr::Implied(|cpu| cpu.regs.flags.set_dec(true)),
+            CLC => Instr::Implied(|cpu| cpu.regs.flags.set_carry(false)),
+            SEC => Instr::Implied(|cpu| cpu.regs.flags.set_carry(true)),
+            CLI => Instr::Implied(|cpu| cpu.regs.flags.set_itr(false)),
+            SEI => Instr::Implied(|cpu| cpu.regs.flags.set_itr(true)),
+            CLV => Instr::Implied(|cpu| cpu.regs.flags.set_overflow(false)),
+            CLD => Instr::Implied(|cpu| cpu.regs.flags.set_dec(false)),
+            NOP | 0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => Instr::Implied(|_| ()),
+            // DOP: Double NOP, the zero page ones read their address
+            0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 => Instr::Read(Mode::Imm, Cpu::nop_read),
+            0x04 | 0x44 | 0x64 => Instr::Read(Mode::ZP, Cpu::nop_read),
+            0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4 => Instr::Read(Mode::ZPX, Cpu::nop_read),
+            BRK => Instr::Brk,
+            TAX => Instr::Implied(Cpu::tax),
+            TXA => Instr::Implied(Cpu::txa),
+            TAY => Instr::Implied(Cpu::tay),
+            TYA => Instr::Implied(Cpu::tya),
+            DEX => Instr::Implied(Cpu::dex),
+            INX => Instr::Implied(Cpu::inx),
+            DEY => Instr::Implied(Cpu::dey),
+            INY => Instr::Implied(Cpu::iny),
+            TSX => Instr::Implied(Cpu::tsx),
+            TXS => Instr::Implied(|cpu| cpu.regs.sp = cpu.regs.x),
+            PHA => Instr::Push(|cpu| cpu.regs.acc),
+            PLA => Instr::Pull(Cpu::lda),
+            PHP => Instr::Push(|cpu| cpu.regs.flags.as_byte() | 0b10000),
+            PLP => Instr::Pull(Cpu::set_status),
+            BVS => Instr::Branch(|cpu| cpu.regs.flags.overflow()),
+            BVC => Instr::Branch(|cpu| !cpu.regs.flags.overflow()),
+            BMI => Instr::Branch(|cpu| cpu.regs.flags.neg()),
+            BPL => Instr::Branch(|cpu| !cpu.regs.flags.neg()),
+            BNE => Instr::Branch(|cpu| !cpu.regs.flags.zero()),
+            BEQ => Instr::Branch(|cpu| cpu.regs.flags.zero()),
+            BCS => Instr::Branch(|cpu| cpu.regs.flags.carry()),
+            BCC => Instr::Branch(|cpu| !cpu.regs.flags.carry()),
+            JSR => Instr::Jsr,
+            JMP_IND => Instr::JmpInd,
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => {
+                Instr::Jam
             }
-            BVS => self.generic_branch(self.regs.flags.overflow(), mem),
-            BVC => self.generic_branch(!self.regs.flags.overflow(), mem),
-            BMI => self.generic_branch(self.regs.flags.neg(), mem),
-            BPL => self.generic_branch(!self.regs.flags.neg(), mem),
-            BNE => self.generic_branch(!self.regs.flags.zero(), mem),
-            BEQ => self.generic_branch(self.regs.flags.zero(), mem),
-            BCS => self.generic_branch(self.regs.flags.carry(), mem),
-            BCC => self.generic_branch(!self.regs.flags.carry(), mem),
-            JSR => self.jsr(mem),
-            JMP_IND => self.jmp(Mode::JmpIndir, mem),
-            _ => panic!("Unsupported op {:X} {:?}", op, self.regs),
         }
     }
 }
@@ -164,3 +164,37 @@ pub static CYCLES: [u8; 256] = [
 pub const NMI_VEC: u16 = 0xFFFA;
 pub const RESET_VEC: u16 = 0xFFFC;
 pub const IRQ_VEC: u16 = 0xFFFE;
+
+// 65C02 additions
+pub const BRA: u8 = 0x80;
+pub const PHX: u8 = 0xDA;
+pub const PLX: u8 = 0xFA;
+pub const PHY: u8 = 0x5A;
+pub const PLY: u8 = 0x7A;
+pub const INC_ACC: u8 = 0x1A;
+pub const DEC_ACC: u8 = 0x3A;
+pub const STZ_ZP: u8 = 0x64;
+pub const STZ_ZPX: u8 = 0x74;
+pub const STZ_ABS: u8 = 0x9C;
+pub const STZ_ABSX: u8 = 0x9E;
+pub const TSB_ZP: u8 = 0x04;
+pub const TSB_ABS: u8 = 0x0C;
+pub const TRB_ZP: u8 = 0x14;
+pub const TRB_ABS: u8 = 0x1C;
+pub const BIT_IMM: u8 = 0x89;
+pub const BIT_ZPX: u8 = 0x34;
+pub const BIT_ABSX: u8 = 0x3C;
+pub const JMP_ABSX_IND: u8 = 0x7C;
+pub const ORA_ZPIND: u8 = 0x12;
+pub const AND_ZPIND: u8 = 0x32;
+pub const EOR_ZPIND: u8 = 0x52;
+pub const ADC_ZPIND: u8 = 0x72;
+pub const STA_ZPIND: u8 = 0x92;
+pub const LDA_ZPIND: u8 = 0xB2;
+pub const CMP_ZPIND: u8 = 0xD2;
+pub const SBC_ZPIND: u8 = 0xF2;
+pub const WAI: u8 = 0xCB;
+pub const STP: u8 = 0xDB;
+
+// What LXA and XAA OR into A before the AND
+pub const UNSTABLE_MAGIC: u8 = 0xEE;
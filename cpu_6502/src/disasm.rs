@@ -0,0 +1,270 @@
+use crate::Memory;
+use crate::cpu::Registers;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrMode {
+    Imp,
+    Acc,
+    Imm,
+    ZP,
+    ZPX,
+    ZPY,
+    Abs,
+    AbsX,
+    AbsY,
+    Ind,
+    IndX,
+    IndY,
+    Rel,
+}
+
+impl AddrMode {
+    // Instruction size in bytes, opcode included
+    pub fn size(&self) -> u16 {
+        match self {
+            AddrMode::Imp | AddrMode::Acc => 1,
+            AddrMode::Imm
+            | AddrMode::ZP
+            | AddrMode::ZPX
+            | AddrMode::ZPY
+            | AddrMode::IndX
+            | AddrMode::IndY
+            | AddrMode::Rel => 2,
+            AddrMode::Abs | AddrMode::AbsX | AddrMode::AbsY | AddrMode::Ind => 3,
+        }
+    }
+}
+
+use AddrMode::*;
+
+// Every opcode, undocumented ones marked with a *. The names of the
+// undocumented opcodes follow https://www.nesdev.org/wiki/CPU_unofficial_opcodes
+#[rustfmt::skip]
+static OPCODES: [(&str, AddrMode); 256] = [
+    // 0x00
+    ("BRK", Imp), ("ORA", IndX), ("*JAM", Imp), ("*SLO", IndX),
+    ("*NOP", ZP), ("ORA", ZP), ("ASL", ZP), ("*SLO", ZP),
+    ("PHP", Imp), ("ORA", Imm), ("ASL", Acc), ("*ANC", Imm),
+    ("*NOP", Abs), ("ORA", Abs), ("ASL", Abs), ("*SLO", Abs),
+    // 0x10
+    ("BPL", Rel), ("ORA", IndY), ("*JAM", Imp), ("*SLO", IndY),
+    ("*NOP", ZPX), ("ORA", ZPX), ("ASL", ZPX), ("*SLO", ZPX),
+    ("CLC", Imp), ("ORA", AbsY), ("*NOP", Imp), ("*SLO", AbsY),
+    ("*NOP", AbsX), ("ORA", AbsX), ("ASL", AbsX), ("*SLO", AbsX),
+    // 0x20
+    ("JSR", Abs), ("AND", IndX), ("*JAM", Imp), ("*RLA", IndX),
+    ("BIT", ZP), ("AND", ZP), ("ROL", ZP), ("*RLA", ZP),
+    ("PLP", Imp), ("AND", Imm), ("ROL", Acc), ("*ANC", Imm),
+    ("BIT", Abs), ("AND", Abs), ("ROL", Abs), ("*RLA", Abs),
+    // 0x30
+    ("BMI", Rel), ("AND", IndY), ("*JAM", Imp), ("*RLA", IndY),
+    ("*NOP", ZPX), ("AND", ZPX), ("ROL", ZPX), ("*RLA", ZPX),
+    ("SEC", Imp), ("AND", AbsY), ("*NOP", Imp), ("*RLA", AbsY),
+    ("*NOP", AbsX), ("AND", AbsX), ("ROL", AbsX), ("*RLA", AbsX),
+    // 0x40
+    ("RTI", Imp), ("EOR", IndX), ("*JAM", Imp), ("*SRE", IndX),
+    ("*NOP", ZP), ("EOR", ZP), ("LSR", ZP), ("*SRE", ZP),
+    ("PHA", Imp), ("EOR", Imm), ("LSR", Acc), ("*ALR", Imm),
+    ("JMP", Abs), ("EOR", Abs), ("LSR", Abs), ("*SRE", Abs),
+    // 0x50
+    ("BVC", Rel), ("EOR", IndY), ("*JAM", Imp), ("*SRE", IndY),
+    ("*NOP", ZPX), ("EOR", ZPX), ("LSR", ZPX), ("*SRE", ZPX),
+    ("CLI", Imp), ("EOR", AbsY), ("*NOP", Imp), ("*SRE", AbsY),
+    ("*NOP", AbsX), ("EOR", AbsX), ("LSR", AbsX), ("*SRE", AbsX),
+    // 0x60
+    ("RTS", Imp), ("ADC", IndX), ("*JAM", Imp), ("*RRA", IndX),
+    ("*NOP", ZP), ("ADC", ZP), ("ROR", ZP), ("*RRA", ZP),
+    ("PLA", Imp), ("ADC", Imm), ("ROR", Acc), ("*ARR", Imm),
+    ("JMP", Ind), ("ADC", Abs), ("ROR", Abs), ("*RRA", Abs),
+    // 0x70
+    ("BVS", Rel), ("ADC", IndY), ("*JAM", Imp), ("*RRA", IndY),
+    ("*NOP", ZPX), ("ADC", ZPX), ("ROR", ZPX), ("*RRA", ZPX),
+    ("SEI", Imp), ("ADC", AbsY), ("*NOP", Imp), ("*RRA", AbsY),
+    ("*NOP", AbsX), ("ADC", AbsX), ("ROR", AbsX), ("*RRA", AbsX),
+    // 0x80
+    ("*NOP", Imm), ("STA", IndX), ("*NOP", Imm), ("*SAX", IndX),
+    ("STY", ZP), ("STA", ZP), ("STX", ZP), ("*SAX", ZP),
+    ("DEY", Imp), ("*NOP", Imm), ("TXA", Imp), ("*XAA", Imm),
+    ("STY", Abs), ("STA", Abs), ("STX", Abs), ("*SAX", Abs),
+    // 0x90
+    ("BCC", Rel), ("STA", IndY), ("*JAM", Imp), ("*AHX", IndY),
+    ("STY", ZPX), ("STA", ZPX), ("STX", ZPY), ("*SAX", ZPY),
+    ("TYA", Imp), ("STA", AbsY), ("TXS", Imp), ("*TAS", AbsY),
+    ("*SHY", AbsX), ("STA", AbsX), ("*SHX", AbsY), ("*AHX", AbsY),
+    // 0xA0
+    ("LDY", Imm), ("LDA", IndX), ("LDX", Imm), ("*LAX", IndX),
+    ("LDY", ZP), ("LDA", ZP), ("LDX", ZP), ("*LAX", ZP),
+    ("TAY", Imp), ("LDA", Imm), ("TAX", Imp), ("*ATX", Imm),
+    ("LDY", Abs), ("LDA", Abs), ("LDX", Abs), ("*LAX", Abs),
+    // 0xB0
+    ("BCS", Rel), ("LDA", IndY), ("*JAM", Imp), ("*LAX", IndY),
+    ("LDY", ZPX), ("LDA", ZPX), ("LDX", ZPY), ("*LAX", ZPY),
+    ("CLV", Imp), ("LDA", AbsY), ("TSX", Imp), ("*LAS", AbsY),
+    ("LDY", AbsX), ("LDA", AbsX), ("LDX", AbsY), ("*LAX", AbsY),
+    // 0xC0
+    ("CPY", Imm), ("CMP", IndX), ("*NOP", Imm), ("*DCP", IndX),
+    ("CPY", ZP), ("CMP", ZP), ("DEC", ZP), ("*DCP", ZP),
+    ("INY", Imp), ("CMP", Imm), ("DEX", Imp), ("*AXS", Imm),
+    ("CPY", Abs), ("CMP", Abs), ("DEC", Abs), ("*DCP", Abs),
+    // 0xD0
+    ("BNE", Rel), ("CMP", IndY), ("*JAM", Imp), ("*DCP", IndY),
+    ("*NOP", ZPX), ("CMP", ZPX), ("DEC", ZPX), ("*DCP", ZPX),
+    ("CLD", Imp), ("CMP", AbsY), ("*NOP", Imp), ("*DCP", AbsY),
+    ("*NOP", AbsX), ("CMP", AbsX), ("DEC", AbsX), ("*DCP", AbsX),
+    // 0xE0
+    ("CPX", Imm), ("SBC", IndX), ("*NOP", Imm), ("*ISC", IndX),
+    ("CPX", ZP), ("SBC", ZP), ("INC", ZP), ("*ISC", ZP),
+    ("INX", Imp), ("SBC", Imm), ("NOP", Imp), ("*SBC", Imm),
+    ("CPX", Abs), ("SBC", Abs), ("INC", Abs), ("*ISC", Abs),
+    // 0xF0
+    ("BEQ", Rel), ("SBC", IndY), ("*JAM", Imp), ("*ISC", IndY),
+    ("*NOP", ZPX), ("SBC", ZPX), ("INC", ZPX), ("*ISC", ZPX),
+    ("SED", Imp), ("SBC", AbsY), ("*NOP", Imp), ("*ISC", AbsY),
+    ("*NOP", AbsX), ("SBC", AbsX), ("INC", AbsX), ("*ISC", AbsX),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    // Where the opcode is
+    pub addr: u16,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+    pub mode: AddrMode,
+    // The operand bytes as a little endian value, 0 when there are none
+    pub operand: u16,
+    // Documented by MOS, the others are side effects of the decoder
+    pub official: bool,
+}
+
+impl Instruction {
+    fn new(addr: u16, opcode: u8, operand: u16) -> Instruction {
+        let (name, mode) = OPCODES[opcode as usize];
+        let mnemonic = name.trim_start_matches('*');
+        Instruction {
+            addr,
+            opcode,
+            mnemonic,
+            mode,
+            operand,
+            official: mnemonic.len() == name.len(),
+        }
+    }
+
+    pub fn size(&self) -> u16 {
+        self.mode.size()
+    }
+
+    // Where a taken branch goes
+    pub fn branch_target(&self) -> Option<u16> {
+        match self.mode {
+            AddrMode::Rel => Some(
+                self.addr
+                    .wrapping_add(2)
+                    .wrapping_add(self.operand as u8 as i8 as u16),
+            ),
+            _ => None,
+        }
+    }
+
+    // The address the instruction would access with these registers. The
+    // indirect modes read their pointer through mem, so mem should be a
+    // view of the bus without side effects. None for modes that don't
+    // access memory.
+    pub fn effective_address<M: Memory>(&self, regs: &Registers, mem: &mut M) -> Option<u16> {
+        let zp_pointer = |mem: &mut M, ptr: u8| {
+            let lo = mem.ld8(ptr as u16) as u16;
+            let hi = mem.ld8(ptr.wrapping_add(1) as u16) as u16;
+            hi << 8 | lo
+        };
+        match self.mode {
+            AddrMode::Imp | AddrMode::Acc | AddrMode::Imm => None,
+            AddrMode::Rel => self.branch_target(),
+            AddrMode::ZP => Some(self.operand),
+            AddrMode::ZPX => Some((self.operand as u8).wrapping_add(regs.x) as u16),
+            AddrMode::ZPY => Some((self.operand as u8).wrapping_add(regs.y) as u16),
+            AddrMode::Abs => Some(self.operand),
+            AddrMode::AbsX => Some(self.operand.wrapping_add(regs.x as u16)),
+            AddrMode::AbsY => Some(self.operand.wrapping_add(regs.y as u16)),
+            // The pointer's high byte doesn't carry into the next page
+            AddrMode::Ind => {
+                let lo = mem.ld8(self.operand) as u16;
+                let hi_addr = (self.operand & 0xFF00) | (self.operand.wrapping_add(1) & 0xFF);
+                let hi = mem.ld8(hi_addr) as u16;
+                Some(hi << 8 | lo)
+            }
+            AddrMode::IndX => Some(zp_pointer(mem, (self.operand as u8).wrapping_add(regs.x))),
+            AddrMode::IndY => Some(zp_pointer(mem, self.operand as u8).wrapping_add(regs.y as u16)),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.mnemonic;
+        let operand = self.operand;
+        match self.mode {
+            AddrMode::Imp => write!(f, "{}", name),
+            AddrMode::Acc => write!(f, "{} A", name),
+            AddrMode::Imm => write!(f, "{} #${:02X}", name, operand),
+            AddrMode::ZP => write!(f, "{} ${:02X}", name, operand),
+            AddrMode::ZPX => write!(f, "{} ${:02X},X", name, operand),
+            AddrMode::ZPY => write!(f, "{} ${:02X},Y", name, operand),
+            AddrMode::Abs => write!(f, "{} ${:04X}", name, operand),
+            AddrMode::AbsX => write!(f, "{} ${:04X},X", name, operand),
+            AddrMode::AbsY => write!(f, "{} ${:04X},Y", name, operand),
+            AddrMode::Ind => write!(f, "{} (${:04X})", name, operand),
+            AddrMode::IndX => write!(f, "{} (${:02X},X)", name, operand),
+            AddrMode::IndY => write!(f, "{} (${:02X}),Y", name, operand),
+            AddrMode::Rel => write!(f, "{} ${:04X}", name, self.branch_target().unwrap_or(0)),
+        }
+    }
+}
+
+// Decodes the instruction at the start of bytes, which are located at addr.
+// None if bytes ends before the instruction does.
+pub fn decode(addr: u16, bytes: &[u8]) -> Option<Instruction> {
+    let opcode = *bytes.first()?;
+    let len = OPCODES[opcode as usize].1.size() as usize;
+    let operand = bytes
+        .get(1..len)?
+        .iter()
+        .rev()
+        .fold(0, |operand, byte| operand << 8 | *byte as u16);
+    Some(Instruction::new(addr, opcode, operand))
+}
+
+// Decodes back to back instructions, leaving out one cut off at the end
+pub fn disassemble(addr: u16, bytes: &[u8]) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while let Some(instruction) = decode(addr.wrapping_add(offset as u16), &bytes[offset..]) {
+        offset += instruction.size() as usize;
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+// Reads through ld8, mem should be a view of the bus without side effects
+pub fn decode_memory<M: Memory>(mem: &mut M, addr: u16) -> Instruction {
+    let opcode = mem.ld8(addr);
+    let bytes: Vec<u8> = (0..OPCODES[opcode as usize].1.size())
+        .map(|i| match i {
+            0 => opcode,
+            i => mem.ld8(addr.wrapping_add(i)),
+        })
+        .collect();
+    decode(addr, &bytes).expect("Every byte of the instruction was read")
+}
+
+// Decodes the instructions starting from start up to and including end
+pub fn disassemble_memory<M: Memory>(mem: &mut M, start: u16, end: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut addr = start as u32;
+    while addr <= end as u32 {
+        let instruction = decode_memory(mem, addr as u16);
+        addr += instruction.size() as u32;
+        instructions.push(instruction);
+    }
+    instructions
+}
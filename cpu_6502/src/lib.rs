@@ -5,9 +5,45 @@ extern crate serde;
 extern crate bitfield;
 pub mod cpu;
 pub mod cpu_const;
+pub mod disasm;
 
 pub trait Memory {
     fn ld8(&mut self, addr: u16) -> u8;
     fn ld16(&mut self, addr: u16) -> u16;
     fn store(&mut self, addr: u16, val: u8);
+
+    // Interrupt lines driven by the devices behind the memory, the CPU
+    // samples them after every read and write
+    fn irq_line(&self) -> bool {
+        false
+    }
+
+    fn nmi_line(&self) -> bool {
+        false
+    }
+
+    // A DMA that wants to halt the CPU, as the page to copy and the address
+    // to copy it to. The CPU halts on its next read and runs the copy, then
+    // calls dma_done.
+    fn dma_request(&self) -> Option<(u8, u16)> {
+        None
+    }
+
+    fn dma_done(&mut self) {}
+
+    // A DMA for a single byte, as the address to read it from. The CPU halts
+    // on its next read and waits a cycle, then reads it on a get cycle and
+    // hands it over with byte_dma_done. It can also take a get cycle from a
+    // page copy that's already running.
+    fn byte_dma_request(&self) -> Option<u16> {
+        None
+    }
+
+    fn byte_dma_done(&mut self, _val: u8) {}
+
+    // DMA reads have to land on get cycles, so halting on one costs the copy
+    // an extra cycle
+    fn is_get_cycle(&self) -> bool {
+        false
+    }
 }
@@ -0,0 +1,232 @@
+use cpu_6502::Memory;
+use cpu_6502::cpu::Cpu;
+use cpu_6502::cpu::Halt;
+use cpu_6502::cpu::Variant;
+use utilities::test_opcodes;
+
+extern crate utilities;
+
+// The program at $8000 with the IRQ handler at $9000, keeping track of the
+// addresses that got written to
+struct WriteMem {
+    mem: Box<[u8]>,
+    writes: Vec<u16>,
+    irq: bool,
+}
+
+impl WriteMem {
+    fn new(program: &[u8]) -> WriteMem {
+        let mut mem = Box::new([0; 0x10000]);
+        mem[0x8000..0x8000 + program.len()].copy_from_slice(program);
+        mem[0xFFFC..].copy_from_slice(&[0x00, 0x80, 0x00, 0x90]);
+        WriteMem {
+            mem,
+            writes: Vec::new(),
+            irq: false,
+        }
+    }
+}
+
+impl Memory for WriteMem {
+    fn ld8(&mut self, addr: u16) -> u8 {
+        self.mem[addr as usize]
+    }
+
+    fn ld16(&mut self, addr: u16) -> u16 {
+        let low = self.ld8(addr);
+        let high = self.ld8(addr.wrapping_add(1));
+        (high as u16) << 8 | low as u16
+    }
+
+    fn store(&mut self, addr: u16, val: u8) {
+        self.writes.push(addr);
+        self.mem[addr as usize] = val;
+    }
+
+    fn irq_line(&self) -> bool {
+        self.irq
+    }
+}
+
+fn run(variant: Variant, program: &[u8], steps: usize) -> (Cpu, WriteMem) {
+    let mut memory = WriteMem::new(program);
+    let mut cpu = Cpu::with_variant(&mut memory, variant);
+    for _ in 0..steps {
+        cpu.step(&mut memory);
+    }
+    (cpu, memory)
+}
+
+fn pc(cpu: &Cpu) -> u16 {
+    cpu.regs.pc.get_addr()
+}
+
+#[test]
+fn jmp_indirect_bug_is_fixed() {
+    let program = [0x6C, 0xFF, 0x10]; // JMP ($10FF)
+    let mut memory = WriteMem::new(&program);
+    memory.mem[0x10FF] = 0x34;
+    memory.mem[0x1100] = 0x12;
+    memory.mem[0x1000] = 0x56;
+    let mut cpu = Cpu::with_variant(&mut memory, Variant::Nmos6502);
+    assert_eq!(5, cpu.step(&mut memory));
+    assert_eq!(0x5634, pc(&cpu));
+
+    let mut cpu = Cpu::with_variant(&mut memory, Variant::Wdc65C02);
+    assert_eq!(6, cpu.step(&mut memory));
+    assert_eq!(0x1234, pc(&cpu));
+}
+
+#[test]
+fn new_instructions() {
+    let program = [
+        0xA9, 0xF0, // LDA #$F0
+        0xA2, 0x05, // LDX #$05
+        0x64, 0x10, // STZ $10
+        0x04, 0x11, // TSB $11
+        0x14, 0x12, // TRB $12
+        0xB2, 0x20, // LDA ($20)
+        0x1A, // INC A
+        0xDA, // PHX
+        0x7A, // PLY
+        0x80, 0x7F, // BRA +$7F
+    ];
+    let mut memory = WriteMem::new(&program);
+    memory.mem[0x10] = 0xAA;
+    memory.mem[0x11] = 0x0F;
+    memory.mem[0x12] = 0xFF;
+    memory.mem[0x20..0x22].copy_from_slice(&[0x00, 0x03]);
+    memory.mem[0x0300] = 0x41;
+    let mut cpu = Cpu::with_variant(&mut memory, Variant::Rockwell65C02);
+    for _ in 0..5 {
+        cpu.step(&mut memory);
+    }
+    assert_eq!([0x00, 0xFF, 0x0F], memory.mem[0x10..0x13]);
+    // TRB found bits in common
+    assert_eq!(0, cpu.regs.flags.as_byte() & 0b10);
+
+    cpu.step(&mut memory);
+    assert_eq!(0x41, cpu.regs.acc);
+    cpu.step(&mut memory);
+    assert_eq!(0x42, cpu.regs.acc);
+    cpu.step(&mut memory);
+    cpu.step(&mut memory);
+    assert_eq!(0x05, cpu.regs.y);
+    assert_eq!(3, cpu.step(&mut memory));
+    assert_eq!(0x8011 + 0x7F, pc(&cpu));
+}
+
+#[test]
+fn bit_instructions() {
+    let program = [
+        0x97, 0x10, // SMB1 $10
+        0x07, 0x10, // RMB0 $10
+        0x0F, 0x10, 0x10, // BBR0 $10,+$10
+    ];
+    let (mut cpu, mut memory) = run(Variant::Wdc65C02, &program, 2);
+    assert_eq!(0b10, memory.mem[0x10]);
+    assert_eq!(6, cpu.step(&mut memory));
+    assert_eq!(0x8017, pc(&cpu));
+
+    // BBS1 on the same byte doesn't branch
+    let (cpu, _) = run(Variant::Wdc65C02, &[0x9F, 0x10, 0x10], 1);
+    assert_eq!(0x8003, pc(&cpu));
+}
+
+#[test]
+fn read_modify_write_reads_twice() {
+    let program = [0xEE, 0x00, 0x02]; // INC $0200
+    let (_, memory) = run(Variant::Nmos6502, &program, 1);
+    assert_eq!(vec![0x0200, 0x0200], memory.writes);
+    let (_, memory) = run(Variant::Wdc65C02, &program, 1);
+    assert_eq!(vec![0x0200], memory.writes);
+    assert_eq!(1, memory.mem[0x0200]);
+}
+
+#[test]
+fn shifts_skip_the_fix_up_cycle() {
+    // ASL $0200,X without and with a page cross
+    let program = [0xA2, 0x01, 0x1E, 0x00, 0x02, 0x1E, 0xFF, 0x02];
+    let mut memory = WriteMem::new(&program);
+    let mut cpu = Cpu::with_variant(&mut memory, Variant::Wdc65C02);
+    cpu.step(&mut memory);
+    assert_eq!(6, cpu.step(&mut memory));
+    assert_eq!(7, cpu.step(&mut memory));
+}
+
+#[test]
+fn undefined_opcodes_are_nops() {
+    let program = [
+        0x03, // 1 byte, 1 cycle
+        0x02, 0x00, // 2 bytes, 2 cycles
+        0x5C, 0x00, 0x00, // 3 bytes, 8 cycles
+    ];
+    let mut memory = WriteMem::new(&program);
+    let mut cpu = Cpu::with_variant(&mut memory, Variant::Rockwell65C02);
+    assert_eq!(1, cpu.step(&mut memory));
+    assert_eq!(2, cpu.step(&mut memory));
+    assert_eq!(8, cpu.step(&mut memory));
+    assert_eq!(0x8006, pc(&cpu));
+
+    // WAI is only on the WDC chip
+    let (cpu, _) = run(Variant::Rockwell65C02, &[0xCB], 1);
+    assert_eq!(None, cpu.halted());
+    assert_eq!(0x8001, pc(&cpu));
+}
+
+#[test]
+fn wai_waits_for_an_interrupt() {
+    let program = [0x58, 0xCB, 0xEA]; // CLI, WAI, NOP
+    let (mut cpu, mut memory) = run(Variant::Wdc65C02, &program, 2);
+    assert_eq!(Some(Halt::Wait), cpu.halted());
+    for _ in 0..4 {
+        assert_eq!(1, cpu.step(&mut memory));
+        assert!(cpu.tick(&mut memory));
+    }
+    assert_eq!(0x8002, pc(&cpu));
+
+    memory.irq = true;
+    cpu.step(&mut memory);
+    assert_eq!(None, cpu.halted());
+    assert_eq!(7, cpu.step(&mut memory));
+    assert_eq!(0x9000, pc(&cpu));
+
+    // With I set the IRQ only wakes it up
+    let (mut cpu, mut memory) = run(Variant::Wdc65C02, &program[1..], 1);
+    memory.irq = true;
+    cpu.step(&mut memory);
+    assert_eq!(None, cpu.halted());
+    cpu.step(&mut memory);
+    assert_eq!(0x8002, pc(&cpu));
+}
+
+#[test]
+fn stp_waits_for_a_reset() {
+    let (mut cpu, mut memory) = run(Variant::Wdc65C02, &[0xDB], 1);
+    memory.irq = true;
+    cpu.step(&mut memory);
+    assert_eq!(Some(Halt::Stop), cpu.halted());
+    cpu.reset(&mut memory);
+    assert_eq!(None, cpu.halted());
+}
+
+#[test]
+fn interrupts_clear_decimal() {
+    let program = [0xF8, 0x00]; // SED, BRK
+    let (cpu, _) = run(Variant::Wdc65C02, &program, 2);
+    assert_eq!(0, cpu.regs.flags.as_byte() & 0b1000);
+    let (cpu, _) = run(Variant::Nmos6502, &program, 2);
+    assert_eq!(0b1000, cpu.regs.flags.as_byte() & 0b1000);
+}
+
+#[test]
+#[ignore = "needs the 65x02 suite, run cpu_6502/fetch_65x02.sh"]
+fn wdc_suite() {
+    test_opcodes("wdc65c02", Variant::Wdc65C02, &[]);
+}
+
+#[test]
+#[ignore = "needs the 65x02 suite, run cpu_6502/fetch_65x02.sh"]
+fn rockwell_suite() {
+    test_opcodes("rockwell65c02", Variant::Rockwell65C02, &[]);
+}
@@ -0,0 +1,141 @@
+use cpu_6502::Memory;
+use cpu_6502::cpu::Cpu;
+use utilities::TestMem;
+
+extern crate utilities;
+
+// Memory with a reset vector pointing at the program at $8000
+fn test_mem(program: &[u8]) -> TestMem {
+    let mut memory = TestMem {
+        mem: Box::new([0; 0x10000]),
+        cycle_logs: Vec::new(),
+        cycle: 0,
+    };
+    memory.mem[0x8000..0x8000 + program.len()].copy_from_slice(program);
+    memory.mem[0xFFFC] = 0x00;
+    memory.mem[0xFFFD] = 0x80;
+    memory
+}
+
+#[test]
+fn step_returns_cycle_deltas() {
+    let mut memory = test_mem(&[
+        0xA9, 0x10, // LDA #$10
+        0xA2, 0x80, // LDX #$80
+        0xBD, 0x00, 0x02, // LDA $0200,X
+        0xBD, 0x80, 0x02, // LDA $0280,X crosses a page
+        0x9D, 0x80, 0x02, // STA $0280,X
+        0xFE, 0x00, 0x03, // INC $0300,X
+        0x20, 0x20, 0x80, // JSR $8020
+    ]);
+    let mut cpu = Cpu::new(&mut memory);
+    assert_eq!(7, cpu.cycles());
+
+    let deltas: Vec<u32> = (0..7).map(|_| cpu.step(&mut memory)).collect();
+    assert_eq!(vec![2, 2, 4, 5, 5, 7, 6], deltas);
+    assert_eq!(7 + 31, cpu.cycles());
+}
+
+#[test]
+fn interrupts_count() {
+    let mut memory = test_mem(&[0x58, 0xEA]); // CLI, NOP
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.set_irq(1, true);
+    cpu.step(&mut memory);
+    cpu.step(&mut memory);
+    assert_eq!(7, cpu.step(&mut memory));
+    assert_eq!(7 + 2 + 2 + 7, cpu.cycles());
+}
+
+// A write to trigger starts a DMA of page 2 to $2004, logging every access
+// as the address and whether it was a write
+struct DmaMem {
+    mem: TestMem,
+    trigger: u16,
+    dma: Option<u8>,
+    log: Vec<(u16, bool)>,
+}
+
+impl Memory for DmaMem {
+    fn ld8(&mut self, addr: u16) -> u8 {
+        self.log.push((addr, false));
+        self.mem.ld8(addr)
+    }
+
+    fn ld16(&mut self, addr: u16) -> u16 {
+        let low = self.ld8(addr);
+        let high = self.ld8(addr.wrapping_add(1));
+        (high as u16) << 8 | low as u16
+    }
+
+    fn store(&mut self, addr: u16, val: u8) {
+        if addr == self.trigger {
+            self.dma = Some(0x02);
+        }
+        self.log.push((addr, true));
+        self.mem.store(addr, val);
+    }
+
+    fn dma_request(&self) -> Option<(u8, u16)> {
+        self.dma.map(|page| (page, 0x2004))
+    }
+
+    fn dma_done(&mut self) {
+        self.dma = None;
+    }
+
+    fn is_get_cycle(&self) -> bool {
+        self.mem.cycle % 2 == 1
+    }
+}
+
+// Runs JSR $8020 with a DMA triggered by its first push, returning the
+// cycles it took and the bus log from the JSR on
+fn jsr_with_dma(align: bool) -> (u32, Vec<(u16, bool)>) {
+    let mut memory = DmaMem {
+        mem: test_mem(&[0x20, 0x20, 0x80]), // JSR $8020
+        trigger: 0x01FD,
+        dma: None,
+        log: Vec::new(),
+    };
+    let mut cpu = Cpu::new(&mut memory);
+    // The reset and the first five cycles of the JSR leave the halt on a put
+    // cycle, one more puts it on a get cycle and the copy has to wait
+    if align {
+        memory.mem.cycle += 1;
+    }
+    memory.log.clear();
+    (cpu.step(&mut memory), memory.log)
+}
+
+#[test]
+fn dma_halts_on_the_next_read() {
+    for (align, cycles) in [(false, 6 + 513), (true, 6 + 514)] {
+        let (taken, log) = jsr_with_dma(align);
+        assert_eq!(cycles, taken);
+        // The halt waits out the second push and takes over the fetch of
+        // the high byte, which gets read again once the copy is done
+        let halt = (0x8002, false);
+        assert_eq!((0x01FC, true), log[4]);
+        assert_eq!(halt, log[5]);
+        let copy = if align {
+            assert_eq!(halt, log[6]);
+            &log[7..]
+        } else {
+            &log[6..]
+        };
+        for (byte, pair) in copy.chunks(2).take(256).enumerate() {
+            assert_eq!([(0x0200 + byte as u16, false), (0x2004, true)], pair);
+        }
+        assert_eq!(Some(&halt), log.last());
+    }
+}
+
+#[test]
+fn counts_from_registers() {
+    let mut memory = test_mem(&[0xEA]); // NOP
+    let mut cpu = Cpu::from_registers(Cpu::new(&mut memory).regs);
+    assert_eq!(0, cpu.cycles());
+    assert_eq!(2, cpu.step(&mut memory));
+    assert_eq!(2, cpu.cycles());
+}
@@ -0,0 +1,104 @@
+use cpu_6502::cpu::Cpu;
+use cpu_6502::cpu::Variant;
+use utilities::JAMS;
+use utilities::TestMem;
+use utilities::test_opcodes;
+
+extern crate utilities;
+
+// Memory with a reset vector pointing at the program at $8000
+fn test_mem(program: &[u8]) -> TestMem {
+    let mut memory = TestMem {
+        mem: Box::new([0; 0x10000]),
+        cycle_logs: Vec::new(),
+        cycle: 0,
+    };
+    memory.mem[0x8000..0x8000 + program.len()].copy_from_slice(program);
+    memory.mem[0xFFFC] = 0x00;
+    memory.mem[0xFFFD] = 0x80;
+    memory
+}
+
+// Runs SED, then the carry instruction, LDA #acc and the op with an
+// immediate operand. Returns the CPU and the cycles the op took.
+fn run(variant: Variant, carry: u8, acc: u8, op: u8, val: u8) -> (Cpu, u32) {
+    let mut memory = test_mem(&[0xF8, carry, 0xA9, acc, op, val]);
+    let mut cpu = Cpu::with_variant(&mut memory, variant);
+    for _ in 0..3 {
+        cpu.step(&mut memory);
+    }
+    let cycles = cpu.step(&mut memory);
+    (cpu, cycles)
+}
+
+const CLC: u8 = 0x18;
+const SEC: u8 = 0x38;
+const ADC: u8 = 0x69;
+const SBC: u8 = 0xE9;
+
+fn flags(cpu: &Cpu) -> (bool, bool, bool) {
+    let flags = cpu.regs.flags.as_byte();
+    (flags & 0x80 != 0, flags & 0x02 != 0, flags & 0x01 != 0)
+}
+
+#[test]
+fn bcd_arithmetic() {
+    for variant in [Variant::Nmos6502, Variant::Wdc65C02] {
+        let (cpu, _) = run(variant, CLC, 0x15, ADC, 0x27);
+        assert_eq!(0x42, cpu.regs.acc);
+        assert!(!flags(&cpu).2);
+
+        let (cpu, _) = run(variant, CLC, 0x58, ADC, 0x46);
+        assert_eq!(0x04, cpu.regs.acc);
+        assert!(flags(&cpu).2);
+
+        let (cpu, _) = run(variant, SEC, 0x42, SBC, 0x15);
+        assert_eq!(0x27, cpu.regs.acc);
+        assert!(flags(&cpu).2);
+
+        let (cpu, _) = run(variant, SEC, 0x00, SBC, 0x01);
+        assert_eq!(0x99, cpu.regs.acc);
+        assert!(!flags(&cpu).2);
+    }
+}
+
+#[test]
+fn nes_ignores_decimal_flag() {
+    let (cpu, cycles) = run(Variant::Ricoh2A03, CLC, 0x15, ADC, 0x27);
+    assert_eq!(0x3C, cpu.regs.acc);
+    assert_eq!(2, cycles);
+    let (cpu, _) = run(Variant::Ricoh2A03, SEC, 0x42, SBC, 0x15);
+    assert_eq!(0x2D, cpu.regs.acc);
+}
+
+#[test]
+fn nmos_and_cmos_flags_differ() {
+    // 99 + 0 + 1, the binary sum is $9A and the unadjusted one $A0
+    let (cpu, cycles) = run(Variant::Nmos6502, SEC, 0x99, ADC, 0x00);
+    assert_eq!(0x00, cpu.regs.acc);
+    assert_eq!((true, false, true), flags(&cpu));
+    assert_eq!(2, cycles);
+
+    // The 65C02 gets N and Z from the result but takes a cycle longer
+    let (cpu, cycles) = run(Variant::Wdc65C02, SEC, 0x99, ADC, 0x00);
+    assert_eq!(0x00, cpu.regs.acc);
+    assert_eq!((false, true, true), flags(&cpu));
+    assert_eq!(3, cycles);
+}
+
+#[test]
+fn invalid_bcd_digits() {
+    // The NMOS and CMOS adjustments differ for digits above 9
+    let (cpu, _) = run(Variant::Nmos6502, SEC, 0x0A, SBC, 0x0B);
+    assert_eq!(0x99, cpu.regs.acc);
+    let (cpu, _) = run(Variant::Wdc65C02, SEC, 0x0A, SBC, 0x0B);
+    assert_eq!(0x99, cpu.regs.acc);
+    let (cpu, _) = run(Variant::Nmos6502, CLC, 0x0F, ADC, 0x0F);
+    assert_eq!(0x14, cpu.regs.acc);
+}
+
+#[test]
+#[ignore = "needs the 65x02 suite, run cpu_6502/fetch_65x02.sh"]
+fn nmos_suite() {
+    test_opcodes("6502", Variant::Nmos6502, &JAMS);
+}
@@ -0,0 +1,118 @@
+use cpu_6502::cpu::Registers;
+use cpu_6502::disasm::AddrMode;
+use cpu_6502::disasm::decode;
+use cpu_6502::disasm::disassemble;
+use cpu_6502::disasm::disassemble_memory;
+use utilities::TestMem;
+
+extern crate utilities;
+
+fn test_mem(at: u16, bytes: &[u8]) -> TestMem {
+    let mut memory = TestMem {
+        mem: Box::new([0; 0x10000]),
+        cycle_logs: Vec::new(),
+        cycle: 0,
+    };
+    memory.mem[at as usize..at as usize + bytes.len()].copy_from_slice(bytes);
+    memory
+}
+
+#[test]
+fn decodes_every_opcode() {
+    for opcode in 0..=255u8 {
+        let instruction = decode(0x8000, &[opcode, 0x34, 0x12]).expect("Expected an instruction");
+        assert_eq!(opcode, instruction.opcode);
+        assert_eq!(3, instruction.mnemonic.len(), "{:02X}", opcode);
+        assert!((1..=3).contains(&instruction.size()));
+    }
+}
+
+#[test]
+fn formats_addressing_modes() {
+    let program = [
+        0xA9, 0x10, // LDA #$10
+        0xB5, 0x20, // LDA $20,X
+        0xB6, 0x20, // LDX $20,Y
+        0xBD, 0x34, 0x12, // LDA $1234,X
+        0x6C, 0xFF, 0x02, // JMP ($02FF)
+        0xA1, 0x40, // LDA ($40,X)
+        0xB1, 0x40, // LDA ($40),Y
+        0x0A, // ASL A
+        0xD0, 0xFC, // BNE $800F
+        0x60, // RTS
+    ];
+    let text: Vec<String> = disassemble(0x8000, &program)
+        .iter()
+        .map(|instruction| instruction.to_string())
+        .collect();
+    assert_eq!(
+        vec![
+            "LDA #$10",
+            "LDA $20,X",
+            "LDX $20,Y",
+            "LDA $1234,X",
+            "JMP ($02FF)",
+            "LDA ($40,X)",
+            "LDA ($40),Y",
+            "ASL A",
+            "BNE $800F",
+            "RTS",
+        ],
+        text
+    );
+}
+
+#[test]
+fn undocumented_opcodes() {
+    let program = [
+        0xA7, 0x10, 0xDB, 0x00, 0x02, 0xEB, 0x01, 0x1A, 0x6B, 0x0F, 0xCB, 0x05,
+    ];
+    let instructions = disassemble(0, &program);
+    let names: Vec<&str> = instructions.iter().map(|i| i.mnemonic).collect();
+    assert_eq!(vec!["LAX", "DCP", "SBC", "NOP", "ARR", "AXS"], names);
+    assert!(instructions.iter().all(|i| !i.official));
+    assert_eq!(AddrMode::AbsY, instructions[1].mode);
+    assert!(decode(0, &[0xE9, 0x01]).unwrap().official);
+}
+
+#[test]
+fn cut_off_instructions() {
+    assert_eq!(None, decode(0, &[]));
+    assert_eq!(None, decode(0, &[0xAD, 0x00]));
+    assert_eq!(1, disassemble(0, &[0xEA, 0xAD, 0x00]).len());
+}
+
+#[test]
+fn disassembles_memory() {
+    let mut memory = test_mem(0xFFFC, &[0xA9, 0x01, 0xEA, 0x4C]);
+    let instructions = disassemble_memory(&mut memory, 0xFFFC, 0xFFFF);
+    let text: Vec<String> = instructions.iter().map(|i| i.to_string()).collect();
+    // The last operand wraps around to $0000
+    assert_eq!(vec!["LDA #$01", "NOP", "JMP $0000"], text);
+}
+
+#[test]
+fn effective_addresses() {
+    let mut memory = test_mem(0x00FF, &[0x34, 0x56]);
+    memory.mem[0x0000] = 0x12;
+    memory.mem[0x0040] = 0xF0;
+    memory.mem[0x0041] = 0x12;
+    memory.mem[0x02FF] = 0x00;
+    memory.mem[0x0200] = 0x90;
+    let regs = Registers::from_values(0, 0x05, 0x20, 0x8000, 0xFD, 0x24);
+    let address = |bytes: &[u8], memory: &mut TestMem| {
+        decode(0x8000, bytes)
+            .unwrap()
+            .effective_address(&regs, memory)
+    };
+
+    assert_eq!(None, address(&[0xA9, 0x10], &mut memory));
+    assert_eq!(Some(0x0004), address(&[0xB5, 0xFF], &mut memory));
+    assert_eq!(Some(0x1239), address(&[0xBD, 0x34, 0x12], &mut memory));
+    assert_eq!(Some(0x1310), address(&[0xB1, 0x40], &mut memory));
+    // The pointer at $FF wraps to $00 for the high byte
+    assert_eq!(Some(0x1234), address(&[0xA1, 0xFA], &mut memory));
+    // JMP ($02FF) takes its high byte from $0200
+    assert_eq!(Some(0x9000), address(&[0x6C, 0xFF, 0x02], &mut memory));
+    assert_eq!(Some(0x7FF2), address(&[0x10, 0xF0], &mut memory));
+}
@@ -0,0 +1,133 @@
+use cpu_6502::cpu::Cpu;
+use cpu_6502::cpu::Halt;
+use cpu_6502::cpu::Registers;
+use cpu_6502::cpu::Variant;
+use utilities::TestMem;
+
+extern crate utilities;
+
+// Runs the instruction at $8000 with the given registers and memory
+fn run(program: &[u8], regs: Registers, ram: &[(u16, u8)]) -> (Cpu, TestMem, u32) {
+    let mut memory = TestMem {
+        mem: Box::new([0; 0x10000]),
+        cycle_logs: Vec::new(),
+        cycle: 0,
+    };
+    memory.mem[0x8000..0x8000 + program.len()].copy_from_slice(program);
+    for (addr, val) in ram {
+        memory.mem[*addr as usize] = *val;
+    }
+    let mut cpu = Cpu::from_registers(regs);
+    let cycles = cpu.step(&mut memory);
+    (cpu, memory, cycles)
+}
+
+fn regs(acc: u8, x: u8, y: u8) -> Registers {
+    Registers::from_values(acc, x, y, 0x8000, 0xFD, 0x24)
+}
+
+#[test]
+fn jam_halts_until_reset() {
+    let (mut cpu, mut memory, cycles) = run(&[0x02], regs(0, 0, 0), &[(0xFFFD, 0x90)]);
+    assert_eq!(2, cycles);
+    assert_eq!(Some(Halt::Jam), cpu.halted());
+
+    // Interrupts don't get it going again, it just keeps reading $FFFF
+    cpu.set_nmi(true);
+    cpu.set_irq(1, true);
+    for _ in 0..10 {
+        assert_eq!(1, cpu.step(&mut memory));
+        assert!(cpu.tick(&mut memory));
+    }
+    assert_eq!(Some(Halt::Jam), cpu.halted());
+    assert_eq!(0x8001, cpu.regs.pc.get_addr());
+    assert_eq!(2 + 20, memory.cycle_logs.len());
+
+    cpu.reset(&mut memory);
+    assert_eq!(None, cpu.halted());
+    assert_eq!(0x9000, cpu.regs.pc.get_addr());
+}
+
+#[test]
+fn jams_are_nops_on_the_65c02() {
+    let mut memory = TestMem {
+        mem: Box::new([0x02; 0x10000]),
+        cycle_logs: Vec::new(),
+        cycle: 0,
+    };
+    let mut cpu = Cpu::from_registers(regs(0, 0, 0));
+    cpu.set_variant(Variant::Wdc65C02);
+    assert_eq!(2, cpu.step(&mut memory));
+    assert_eq!(None, cpu.halted());
+}
+
+#[test]
+fn lxa_and_xaa_use_the_magic_constant() {
+    // LXA #$FF
+    let (cpu, _, _) = run(&[0xAB, 0xFF], regs(0x01, 0, 0), &[]);
+    assert_eq!((0xEF, 0xEF), (cpu.regs.acc, cpu.regs.x));
+
+    // XAA #$F3
+    let (cpu, _, _) = run(&[0x8B, 0xF3], regs(0x00, 0x3F, 0), &[]);
+    assert_eq!(0x22, cpu.regs.acc);
+    assert_eq!(0x3F, cpu.regs.x);
+}
+
+#[test]
+fn las_ands_with_the_stack_pointer() {
+    // LAS $1000,Y
+    let (cpu, _, cycles) = run(&[0xBB, 0x00, 0x10], regs(0, 0, 0x01), &[(0x1001, 0x7C)]);
+    assert_eq!(4, cycles);
+    assert_eq!((0x7C, 0x7C, 0x7C), (cpu.regs.acc, cpu.regs.x, cpu.regs.sp));
+}
+
+#[test]
+fn sh_stores_and_with_the_high_byte() {
+    // SHX $1000,Y stays on the page and ANDs X with $11
+    let (_, memory, cycles) = run(&[0x9E, 0x00, 0x10], regs(0, 0xFF, 0x10), &[]);
+    assert_eq!(5, cycles);
+    assert_eq!(0x11, memory.mem[0x1010]);
+
+    // SHY $10F0,X crosses into $11xx, so the value replaces the high byte
+    let (_, memory, _) = run(&[0x9C, 0xF0, 0x10], regs(0, 0x20, 0x0F), &[]);
+    assert_eq!(0x01, memory.mem[0x0110]);
+
+    // SHA ($10),Y
+    let (_, memory, cycles) = run(
+        &[0x93, 0x10],
+        regs(0xF0, 0x3F, 0x02),
+        &[(0x10, 0x00), (0x11, 0x20)],
+    );
+    assert_eq!(6, cycles);
+    assert_eq!(0x21 & 0x30, memory.mem[0x2002]);
+}
+
+#[test]
+fn tas_sets_the_stack_pointer() {
+    // TAS $2000,Y
+    let (cpu, memory, _) = run(&[0x9B, 0x00, 0x20], regs(0xF7, 0x7F, 0x05), &[]);
+    assert_eq!(0x77, cpu.regs.sp);
+    assert_eq!(0x77 & 0x21, memory.mem[0x2005]);
+}
+
+#[test]
+fn nop_reads_take_their_addressing_cycles() {
+    // The opcode and operand bytes, the cycles and the address the NOP reads
+    let nops: [(&[u8], u32, u16); 6] = [
+        (&[0x04, 0x44], 3, 0x0044),
+        (&[0x14, 0x44], 4, 0x0054),
+        (&[0x0C, 0x34, 0x12], 4, 0x1234),
+        (&[0x1C, 0x34, 0x12], 4, 0x1244),
+        // Crossing a page takes the cycle to fix the high byte
+        (&[0x3C, 0xF8, 0x12], 5, 0x1308),
+        (&[0x80, 0x44], 2, 0x8001),
+    ];
+    for (program, cycles, addr) in nops {
+        let (cpu, memory, taken) = run(program, regs(0, 0x10, 0), &[]);
+        assert_eq!(cycles, taken, "{:02X}", program[0]);
+        assert_eq!(cycles as usize, memory.cycle_logs.len());
+        let last = format!("{:?}", memory.cycle_logs.last());
+        assert!(last.contains(&format!("addr: {:02X} ", addr)), "{}", last);
+        assert_eq!(0x8000 + program.len() as u16, cpu.regs.pc.get_addr());
+    }
+}
@@ -1,68 +0,0 @@
-use cpu_6502::cpu::Cpu;
-use utilities::TestData;
-use utilities::TestMem;
-use utilities::test_op;
-
-extern crate utilities;
-
-test_op!(
-    (test_6a, "6a"),
-    (test_4a, "4a"),
-    (test_2a, "2a"),
-    (test_0a, "0a"),
-    (test_60, "60"),
-    (test_40, "40"),
-    (test_f8, "f8"),
-    (test_18, "18"),
-    (test_38, "38"),
-    (test_58, "58"),
-    (test_78, "78"),
-    (test_b8, "b8"),
-    (test_d8, "d8"),
-    (test_ea, "ea"),
-    (test_1a, "1a"),
-    (test_3a, "3a"),
-    (test_5a, "5a"),
-    (test_7a, "7a"),
-    (test_da, "da"),
-    (test_fa, "fa"),
-    // (test_14, "14"),
-    // (test_34, "34"),
-    // (test_44, "44"),
-    // (test_54, "54"),
-    // (test_64, "64"),
-    // (test_74, "74"),
-    (test_80, "80"),
-    (test_82, "82"),
-    (test_89, "89"),
-    (test_c2, "c2"),
-    // (test_d4, "d4"),
-    // (test_e2, "e2"),
-    // (test_f4, "f4"),
-    // (test_04, "04"),
-    (test_00, "00"),
-    (test_aa, "aa"),
-    (test_8a, "8a"),
-    (test_a8, "a8"),
-    (test_98, "98"),
-    (test_ca, "ca"),
-    (test_e8, "e8"),
-    (test_88, "88"),
-    (test_c8, "c8"),
-    (test_ba, "ba"),
-    (test_9a, "9a"),
-    (test_48, "48"),
-    (test_68, "68"),
-    (test_08, "08"),
-    (test_28, "28"),
-    (test_70, "70"),
-    (test_50, "50"),
-    (test_30, "30"),
-    (test_10, "10"),
-    (test_d0, "d0"),
-    (test_f0, "f0"),
-    (test_b0, "b0"),
-    (test_90, "90"),
-    (test_20, "20"),
-    (test_6c, "6c")
-);
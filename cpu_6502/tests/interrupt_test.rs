@@ -0,0 +1,240 @@
+use cpu_6502::Memory;
+use cpu_6502::cpu::Cpu;
+use cpu_6502::cpu::Interrupt;
+
+const NMI_HANDLER: u16 = 0x9000;
+const IRQ_HANDLER: u16 = 0xA000;
+
+// NOPs everywhere except the program at $8000, with lines that turn on once
+// the bus has seen a given number of cycles
+struct LineMem {
+    mem: Box<[u8]>,
+    cycle: u64,
+    irq_from: Option<u64>,
+    nmi_from: Option<u64>,
+}
+
+impl LineMem {
+    fn new(program: &[u8]) -> LineMem {
+        let mut mem = Box::new([0xEA; 0x10000]);
+        mem[0x8000..0x8000 + program.len()].copy_from_slice(program);
+        mem[0xFFFA..].copy_from_slice(&[0x00, 0x90, 0x00, 0x80, 0x00, 0xA0]);
+        LineMem {
+            mem,
+            cycle: 0,
+            irq_from: None,
+            nmi_from: None,
+        }
+    }
+
+    fn stack(&self, cpu: &Cpu, offset: u8) -> u8 {
+        self.mem[0x100 | cpu.regs.sp.wrapping_add(offset) as usize]
+    }
+
+    fn return_address(&self, cpu: &Cpu) -> u16 {
+        (self.stack(cpu, 3) as u16) << 8 | self.stack(cpu, 2) as u16
+    }
+}
+
+impl Memory for LineMem {
+    fn ld8(&mut self, addr: u16) -> u8 {
+        self.cycle += 1;
+        self.mem[addr as usize]
+    }
+
+    fn ld16(&mut self, addr: u16) -> u16 {
+        let low = self.ld8(addr);
+        let high = self.ld8(addr.wrapping_add(1));
+        (high as u16) << 8 | low as u16
+    }
+
+    fn store(&mut self, addr: u16, val: u8) {
+        self.cycle += 1;
+        self.mem[addr as usize] = val;
+    }
+
+    fn irq_line(&self) -> bool {
+        self.irq_from.is_some_and(|cycle| self.cycle >= cycle)
+    }
+
+    fn nmi_line(&self) -> bool {
+        self.nmi_from.is_some_and(|cycle| self.cycle >= cycle)
+    }
+}
+
+fn pc(cpu: &Cpu) -> u16 {
+    cpu.regs.pc.get_addr()
+}
+
+#[test]
+fn cli_delays_irq_by_an_instruction() {
+    let mut memory = LineMem::new(&[0x58]); // CLI
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.set_irq(1, true);
+
+    cpu.step(&mut memory);
+    assert_eq!(None, cpu.pending_interrupt());
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Irq), cpu.pending_interrupt());
+    assert_eq!(7, cpu.step(&mut memory));
+    assert_eq!(IRQ_HANDLER, pc(&cpu));
+    assert_eq!(0x8002, memory.return_address(&cpu));
+    // The pushed status has I clear and B clear
+    assert_eq!(0, memory.stack(&cpu, 1) & 0b10100);
+    assert_eq!(0b100, cpu.regs.flags.as_byte() & 0b100);
+}
+
+#[test]
+fn sei_lets_an_irq_through() {
+    let mut memory = LineMem::new(&[0x58, 0xEA, 0x78]); // CLI, NOP, SEI
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.step(&mut memory);
+    cpu.step(&mut memory);
+    cpu.set_irq(1, true);
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Irq), cpu.pending_interrupt());
+    cpu.step(&mut memory);
+    assert_eq!(0x8003, memory.return_address(&cpu));
+}
+
+#[test]
+fn plp_delays_and_rti_does_not() {
+    // PLP pulls a status with I clear
+    let mut memory = LineMem::new(&[0x28]);
+    let mut cpu = Cpu::new(&mut memory);
+    memory.mem[0x100 | cpu.regs.sp.wrapping_add(1) as usize] = 0x20;
+    cpu.set_irq(1, true);
+    cpu.step(&mut memory);
+    assert_eq!(None, cpu.pending_interrupt());
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Irq), cpu.pending_interrupt());
+
+    // RTI returns to $8000 with I clear
+    let mut memory = LineMem::new(&[0x40]);
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.regs.sp = 0xF0;
+    for (i, val) in [0x20, 0x00, 0x80].iter().enumerate() {
+        memory.mem[0x100 | cpu.regs.sp.wrapping_add(i as u8 + 1) as usize] = *val;
+    }
+    cpu.set_irq(1, true);
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Irq), cpu.pending_interrupt());
+}
+
+#[test]
+fn taken_branch_delays_irq() {
+    // CLI takes cycles 8 and 9, BNE +0 cycles 10 to 12
+    let program = [0x58, 0xD0, 0x00];
+    let mut memory = LineMem::new(&program);
+    memory.irq_from = Some(11);
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.step(&mut memory);
+    assert_eq!(3, cpu.step(&mut memory));
+    assert_eq!(None, cpu.pending_interrupt());
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Irq), cpu.pending_interrupt());
+
+    // Asserted before the operand fetch it gets polled
+    let mut memory = LineMem::new(&program);
+    memory.irq_from = Some(10);
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.step(&mut memory);
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Irq), cpu.pending_interrupt());
+}
+
+#[test]
+fn nmi_triggers_on_edges() {
+    let mut memory = LineMem::new(&[]);
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.set_nmi(true);
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Nmi), cpu.pending_interrupt());
+    cpu.step(&mut memory);
+    assert_eq!(NMI_HANDLER, pc(&cpu));
+
+    // Holding the line doesn't trigger it again
+    for _ in 0..4 {
+        cpu.step(&mut memory);
+        assert_eq!(None, cpu.pending_interrupt());
+    }
+    cpu.set_nmi(false);
+    cpu.step(&mut memory);
+    cpu.set_nmi(true);
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Nmi), cpu.pending_interrupt());
+}
+
+#[test]
+fn nmi_wins_over_irq() {
+    let mut memory = LineMem::new(&[0x58, 0xEA]); // CLI, NOP
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.step(&mut memory);
+    cpu.set_irq(1, true);
+    cpu.set_nmi(true);
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Nmi), cpu.pending_interrupt());
+}
+
+#[test]
+fn nmi_hijacks_brk() {
+    // BRK runs from cycle 8 to 14, the status push is on cycle 12
+    let mut memory = LineMem::new(&[0x00]);
+    memory.nmi_from = Some(11);
+    let mut cpu = Cpu::new(&mut memory);
+    assert_eq!(7, cpu.step(&mut memory));
+    assert_eq!(NMI_HANDLER, pc(&cpu));
+    // It still pushes the address after the padding byte and the B flag
+    assert_eq!(0x8002, memory.return_address(&cpu));
+    assert_eq!(0b10000, memory.stack(&cpu, 1) & 0b10000);
+    // The NMI was serviced by the hijack
+    cpu.step(&mut memory);
+    assert_eq!(None, cpu.pending_interrupt());
+
+    // Too late to hijack, the NMI runs after the first handler instruction
+    let mut memory = LineMem::new(&[0x00]);
+    memory.nmi_from = Some(12);
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.step(&mut memory);
+    assert_eq!(IRQ_HANDLER, pc(&cpu));
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Nmi), cpu.pending_interrupt());
+}
+
+#[test]
+fn nmi_hijacks_irq() {
+    // CLI on cycles 8 and 9, NOP on 10 and 11, the IRQ from 12 to 18
+    let mut memory = LineMem::new(&[0x58]);
+    memory.irq_from = Some(0);
+    memory.nmi_from = Some(14);
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.step(&mut memory);
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Irq), cpu.pending_interrupt());
+    cpu.step(&mut memory);
+    assert_eq!(NMI_HANDLER, pc(&cpu));
+    assert_eq!(0, memory.stack(&cpu, 1) & 0b10000);
+    assert_eq!(0x8002, memory.return_address(&cpu));
+}
+
+#[test]
+fn shared_irq_line() {
+    let mut memory = LineMem::new(&[0x58]);
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.set_irq(0b01, true);
+    cpu.set_irq(0b10, true);
+    cpu.set_irq(0b01, false);
+    assert_eq!(0b10, cpu.irq_sources());
+    cpu.step(&mut memory);
+    cpu.step(&mut memory);
+    assert_eq!(Some(Interrupt::Irq), cpu.pending_interrupt());
+
+    // Acknowledging the last source drops the line before the poll
+    let mut memory = LineMem::new(&[0x58]);
+    let mut cpu = Cpu::new(&mut memory);
+    cpu.set_irq(0b10, true);
+    cpu.step(&mut memory);
+    cpu.set_irq(0b10, false);
+    cpu.step(&mut memory);
+    assert_eq!(None, cpu.pending_interrupt());
+}
@@ -0,0 +1,13 @@
+use cpu_6502::cpu::Variant;
+use utilities::JAMS;
+use utilities::test_opcodes;
+
+extern crate utilities;
+
+// Every opcode in the 2A03 suite, both a whole instruction at a time and
+// cycle stepped
+#[test]
+#[ignore = "needs the 65x02 suite, run cpu_6502/fetch_65x02.sh"]
+fn nes_suite() {
+    test_opcodes("nes6502", Variant::Ricoh2A03, &JAMS);
+}
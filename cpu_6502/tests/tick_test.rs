@@ -0,0 +1,133 @@
+use cpu_6502::Memory;
+use cpu_6502::cpu::Cpu;
+use cpu_6502::cpu::Registers;
+use cpu_6502::cpu::Variant;
+use utilities::TestMem;
+
+extern crate utilities;
+
+// The same pseudo random memory for every seed
+fn random_bytes(seed: u32) -> Box<[u8]> {
+    let mut state = seed.wrapping_mul(2654435761) | 1;
+    (0..0x10000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+fn test_mem(bytes: &[u8], op: u8) -> TestMem {
+    let mut memory = TestMem {
+        mem: bytes.into(),
+        cycle_logs: Vec::new(),
+        cycle: 0,
+    };
+    memory.mem[0x8000] = op;
+    memory
+}
+
+fn random_mem(op: u8, seed: u32) -> TestMem {
+    test_mem(&random_bytes(seed), op)
+}
+
+fn random_regs(seed: u32) -> Registers {
+    let byte = |shift: u32| (seed.wrapping_mul(0x9E3779B9) >> shift) as u8;
+    Registers::from_values(byte(0), byte(8), byte(16), 0x8000, 0xF0, byte(24) | 0x20)
+}
+
+#[test]
+fn ticks_match_steps() {
+    let seeds: Vec<Box<[u8]>> = (1..10).map(random_bytes).collect();
+    let variants = [
+        Variant::Ricoh2A03,
+        Variant::Nmos6502,
+        Variant::Rockwell65C02,
+        Variant::Wdc65C02,
+    ];
+    for (op, variant) in (0..=255u8).flat_map(|op| variants.map(|variant| (op, variant))) {
+        for (seed, bytes) in (1..).zip(seeds.iter()) {
+            let mut stepped_mem = test_mem(bytes, op);
+            let mut stepped = Cpu::from_registers(random_regs(seed));
+            stepped.set_variant(variant);
+            let cycles = stepped.step(&mut stepped_mem);
+
+            let mut ticked_mem = test_mem(bytes, op);
+            let mut ticked = Cpu::from_registers(random_regs(seed));
+            ticked.set_variant(variant);
+            let mut ticks = 0;
+            loop {
+                let before = ticked_mem.cycle_logs.len();
+                let done = ticked.tick(&mut ticked_mem);
+                ticks += 1;
+                // Every tick is exactly one bus cycle
+                assert_eq!(
+                    before + 1,
+                    ticked_mem.cycle_logs.len(),
+                    "{:02X} {:?}",
+                    op,
+                    variant
+                );
+                if done {
+                    break;
+                }
+                assert!(ticked.mid_instruction());
+            }
+
+            assert_eq!(cycles, ticks, "{:02X} {:?}", op, variant);
+            assert_eq!(stepped.cycles(), ticked.cycles());
+            assert_eq!(
+                format!("{:?}", stepped_mem.cycle_logs),
+                format!("{:?}", ticked_mem.cycle_logs),
+                "{:02X} {:?}",
+                op,
+                variant
+            );
+            assert_eq!(format!("{:?}", stepped.regs), format!("{:?}", ticked.regs));
+            assert_eq!(stepped.halted(), ticked.halted());
+            assert!(!ticked.mid_instruction());
+        }
+    }
+}
+
+#[test]
+fn step_finishes_a_ticked_instruction() {
+    // LDA $1234
+    let mut memory = random_mem(0xAD, 1);
+    memory.mem[0x8001] = 0x34;
+    memory.mem[0x8002] = 0x12;
+    memory.mem[0x1234] = 0x42;
+    let mut cpu = Cpu::from_registers(random_regs(1));
+    // PC moves on with every byte of the instruction that gets fetched
+    assert!(!cpu.tick(&mut memory));
+    assert_eq!(0x8001, cpu.regs.pc.get_addr());
+    assert!(!cpu.tick(&mut memory));
+    assert_eq!(0x8002, cpu.regs.pc.get_addr());
+    assert_eq!(2, cpu.step(&mut memory));
+    assert_eq!(0x42, cpu.regs.acc);
+    assert_eq!(0x8003, cpu.regs.pc.get_addr());
+    assert_eq!(4, memory.cycle_logs.len());
+}
+
+#[test]
+fn ticked_interrupts() {
+    let mut memory = random_mem(0xEA, 1);
+    memory.mem[0xFFFE] = 0x00;
+    memory.mem[0xFFFF] = 0x90;
+    let mut regs = random_regs(1);
+    regs.flags.set_byte(0x20);
+    let mut cpu = Cpu::from_registers(regs);
+    cpu.set_irq(1, true);
+    assert!(!cpu.tick(&mut memory));
+    assert!(cpu.tick(&mut memory));
+    assert!(cpu.pending_interrupt().is_some());
+    let mut ticks = 1;
+    while !cpu.tick(&mut memory) {
+        ticks += 1;
+    }
+    assert_eq!(7, ticks);
+    assert_eq!(0x9000, cpu.regs.pc.get_addr());
+    assert_eq!(0x8001, memory.ld16(0x100 | (cpu.regs.sp as u16 + 2)));
+}
@@ -1,10 +1,15 @@
 extern crate cpu_6502;
 use cpu_6502::{
     Memory,
-    cpu::{Flags, ProgramCounter, Registers},
+    cpu::{Cpu, Flags, ProgramCounter, Registers, Variant},
 };
 use serde::{Deserialize, de};
 use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::panic;
+use std::path::Path;
+use std::thread;
 
 #[derive(PartialEq, PartialOrd, Debug)]
 enum InstructionType {
@@ -154,91 +159,167 @@ impl Memory for TestMem {
     }
 }
 
-#[macro_export]
-macro_rules! test_op {
-    ( $(($test_name:ident, $path:literal)),* ) => {
-        $(
-            #[test]
-            #[ignore]
-            fn $test_name() {
-                let path = format!("./tests/65x02/nes6502/v1/{}.json", $path);
-                println!("{}", path);
-                let test_json = ::std::path::Path::new(&path);
-                let tests: Vec<TestData> =
-                    serde_json::from_reader(::std::fs::File::open(test_json).expect("File should exist"))
-                        .expect("Serde failed");
-                for test in tests.iter() {
-                    let mut memory = TestMem {
-                        mem: Box::new([0; 0x10000]),
-                        cycle_logs: Vec::with_capacity(test.cycles.len()),
-                        cycle: 0,
-                    };
-
-                    for entry in test.initial.ram.iter() {
-                        let (addr, val) = entry;
-                        memory.mem[*addr] = *val;
-                    }
-
-                    let mut cpu = Cpu::from_registers(test.initial.turn_into());
-                    cpu.step(&mut memory);
-
-                    // Check if the registers are in a healthy state
-                    assert_eq!(
-                        test.ending.pc,
-                        cpu.regs.pc.get_addr(),
-                        "{} failed, PC: expected {}, got {}",
-                        test.name,
-                        test.ending.pc,
-                        cpu.regs.pc.get_addr(),
-                    );
-                    assert_eq!(
-                        test.ending.s, cpu.regs.sp,
-                        "{} failed, SP: expected {}, got {}",
-                        test.name, test.ending.s, cpu.regs.sp
-                    );
-                    assert_eq!(
-                        test.ending.a, cpu.regs.acc,
-                        "{} failed, ACC: expected {}, got {}",
-                        test.name, test.ending.a, cpu.regs.acc
-                    );
-                    assert_eq!(
-                        test.ending.x, cpu.regs.x,
-                        "{} failed, X: expected {}, got {}",
-                        test.name, test.ending.x, cpu.regs.x
-                    );
-                    assert_eq!(
-                        test.ending.y, cpu.regs.y,
-                        "{} failed, Y: expected {}, got {}",
-                        test.name, test.ending.y, cpu.regs.y
-                    );
-                    assert_eq!(
-                        test.ending.p, cpu.regs.flags.0,
-                        "{} failed, FLAGS: expected {}, got {}",
-                        test.name, test.ending.p, cpu.regs.flags.0
-                    );
-
-                    // Check all cycles
-                    for (i, cycle) in test.cycles.iter().enumerate() {
-                        println!("{:?} {:?}\t{:?}", cpu.regs, *cycle, memory.cycle_logs[i]);
-                        assert_eq!(
-                            memory.cycle_logs[i], *cycle,
-                            "{} failed, cycle {}, expected {:?}, got {:?}",
-                            test.name, i, test.cycles, memory.cycle_logs
-                        );
-                    }
-                    println!();
-
-                    // Check all RAM entries
-                    for entry in test.ending.ram.iter() {
-                        let (addr, val) = entry;
-                        assert_eq!(
-                            memory.mem[*addr], *val,
-                            "{} failed, addr: {} expected {}, got val: {}",
-                            test.name, addr, val, memory.mem[*addr]
-                        );
-                    }
-                }
+impl TestMem {
+    fn from_state(state: &CpuState) -> TestMem {
+        let mut memory = TestMem {
+            mem: Box::new([0; 0x10000]),
+            cycle_logs: Vec::new(),
+            cycle: 0,
+        };
+        for (addr, val) in state.ram.iter() {
+            memory.mem[*addr] = *val;
+        }
+        memory
+    }
+}
+
+// Where the 65x02 test suite from https://github.com/SingleStepTests/65x02
+// gets cloned to
+pub const SUITE_DIR: &str = "./tests/65x02";
+
+// The NMOS opcodes that lock up the CPU, which the suite has no tests for
+pub const JAMS: [u8; 12] = [
+    0x02, 0x12, 0x22, 0x32, 0x42, 0x52, 0x62, 0x72, 0x92, 0xB2, 0xD2, 0xF2,
+];
+
+// Runs a single test, a whole instruction at a time or cycle stepped, and
+// describes the first thing that went wrong
+pub fn run_test(test: &TestData, variant: Variant, cycle_stepped: bool) -> Result<(), String> {
+    let mut memory = TestMem::from_state(&test.initial);
+    let mut cpu = Cpu::from_registers(test.initial.turn_into());
+    cpu.set_variant(variant);
+    let cycles = if cycle_stepped {
+        let mut cycles = 0;
+        loop {
+            cycles += 1;
+            let done = cpu.tick(&mut memory);
+            if cycles != memory.cycle_logs.len() {
+                return Err("a tick did more than one cycle".to_string());
+            }
+            if done {
+                break cycles;
             }
-        )*
+        }
+    } else {
+        cpu.step(&mut memory) as usize
+    };
+
+    let check = |what: &str, expected: usize, got: usize| {
+        if expected == got {
+            Ok(())
+        } else {
+            Err(format!("{}: expected {:X}, got {:X}", what, expected, got))
+        }
     };
+    let ending = &test.ending;
+    check("cycles", test.cycles.len(), cycles)?;
+    check("PC", ending.pc as usize, cpu.regs.pc.get_addr() as usize)?;
+    check("SP", ending.s as usize, cpu.regs.sp as usize)?;
+    check("ACC", ending.a as usize, cpu.regs.acc as usize)?;
+    check("X", ending.x as usize, cpu.regs.x as usize)?;
+    check("Y", ending.y as usize, cpu.regs.y as usize)?;
+    check("FLAGS", ending.p as usize, cpu.regs.flags.0 as usize)?;
+    if memory.cycle_logs != test.cycles {
+        return Err(format!(
+            "bus: expected {:?}, got {:?}",
+            test.cycles, memory.cycle_logs
+        ));
+    }
+    for (addr, val) in ending.ram.iter() {
+        check(
+            &format!("addr {:04X}", addr),
+            *val as usize,
+            memory.mem[*addr] as usize,
+        )?;
+    }
+    Ok(())
+}
+
+// Runs every test for an opcode in both modes, returning the first failure
+pub fn run_opcode(suite: &str, variant: Variant, op: u8) -> Result<(), String> {
+    let path = format!("{}/{}/v1/{:02x}.json", SUITE_DIR, suite, op);
+    let file = File::open(&path).map_err(|e| format!("can't open {}: {}", path, e))?;
+    let tests: Vec<TestData> =
+        serde_json::from_reader(BufReader::new(file)).map_err(|e| format!("{}: {}", path, e))?;
+    for test in tests.iter() {
+        for cycle_stepped in [false, true] {
+            // Unsupported opcodes panic, which counts as a failure too
+            let result = panic::catch_unwind(|| run_test(test, variant, cycle_stepped))
+                .unwrap_or_else(|_| Err("panicked".to_string()));
+            if let Err(e) = result {
+                let mode = if cycle_stepped {
+                    " (cycle stepped)"
+                } else {
+                    ""
+                };
+                return Err(format!("{}{} failed, {}", test.name, mode, e));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Runs the suite for all 256 opcodes apart from skip, then prints a matrix
+// with a row for each high nibble and a column for each low one. Panics
+// listing the failures if there are any. The suite isn't checked in, so the
+// tests calling this are ignored by default and fetch_65x02.sh clones it.
+pub fn test_opcodes(suite: &str, variant: Variant, skip: &[u8]) {
+    let dir = format!("{}/{}", SUITE_DIR, suite);
+    assert!(
+        Path::new(&dir).is_dir(),
+        "The {} suite is missing from {}, run cpu_6502/fetch_65x02.sh to clone it",
+        suite,
+        dir
+    );
+    let results: Vec<Option<Result<(), String>>> = thread::scope(|scope| {
+        let rows: Vec<_> = (0..16u8)
+            .map(|high| {
+                scope.spawn(move || {
+                    (0..16u8)
+                        .map(|low| {
+                            let op = high << 4 | low;
+                            (!skip.contains(&op)).then(|| run_opcode(suite, variant, op))
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        rows.into_iter()
+            .flat_map(|row| row.join().expect("Rows catch their own panics"))
+            .collect()
+    });
+
+    println!("{} ({:?})", suite, variant);
+    let header: String = (0..16)
+        .map(|low| format!(" {:>4}", format!("x{:X}", low)))
+        .collect();
+    println!("  {}", header);
+    for (high, row) in results.chunks(16).enumerate() {
+        let cells: String = row
+            .iter()
+            .map(|result| match result {
+                None => "   -",
+                Some(Ok(())) => "  ok",
+                Some(Err(_)) => "FAIL",
+            })
+            .map(|cell| format!(" {}", cell))
+            .collect();
+        println!("{:X}x{}", high, cells);
+    }
+
+    let failures: Vec<String> = results
+        .iter()
+        .enumerate()
+        .filter_map(|(op, result)| match result {
+            Some(Err(e)) => Some(format!("{:02X}: {}", op, e)),
+            _ => None,
+        })
+        .collect();
+    assert!(
+        failures.is_empty(),
+        "{} of {} opcodes failed\n{}",
+        failures.len(),
+        256 - skip.len(),
+        failures.join("\n")
+    );
 }
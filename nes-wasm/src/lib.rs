@@ -20,6 +20,12 @@ pub struct BufferStruct {
     pub length: usize,
 }
 
+#[wasm_bindgen]
+pub struct AudioBufferStruct {
+    pub pointer: *const f32,
+    pub length: usize,
+}
+
 #[wasm_bindgen]
 #[derive(PartialEq, PartialOrd, Eq, Hash)]
 pub struct KeyCode(usize);
@@ -68,16 +74,35 @@ impl EmuInterface {
         // button_map_one.insert(KeyCode(84), Button::Start);
         // button_map_one.insert(KeyCode(89), Button::Select);
 
+        let nes_emu = match NesEmulator::new(rom) {
+            Ok(nes_emu) => nes_emu,
+            Err(e) => return Err(JsValue::from_str(&e.to_string())),
+        };
+
         Ok(EmuInterface {
-            nes_emu: NesEmulator::new(rom),
+            nes_emu,
             ctrl0: button_map_one,
             //ctrl1: ,
         })
     }
 
-    pub fn get_frame(&mut self) -> BufferStruct {
-        let buffer = self.nes_emu.next_frame();
-        BufferStruct { pointer: buffer.as_ptr(), length: buffer.len() }
+    // Faults come back as an exception with the registers and PPU position
+    pub fn get_frame(&mut self) -> Result<BufferStruct, JsValue> {
+        if let Err(e) = self.nes_emu.next_frame() {
+            return Err(JsValue::from_str(&e.to_string()));
+        }
+        let buffer = self.nes_emu.get_pixel_buffer();
+        Ok(BufferStruct { pointer: buffer.as_ptr(), length: buffer.len() })
+    }
+
+    // Samples produced by the last call to get_frame
+    pub fn get_audio(&self) -> AudioBufferStruct {
+        let buffer = self.nes_emu.get_audio_buffer();
+        AudioBufferStruct { pointer: buffer.as_ptr(), length: buffer.len() }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.nes_emu.set_sample_rate(sample_rate);
     }
 
     pub fn set_button(&mut self, key: KeyCode, state: bool) {
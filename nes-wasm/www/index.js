@@ -8,6 +8,8 @@ const COLOR_CHANNELS = 3;
 
 var nes_fe = null;
 let animationId = null;
+let audioCtx = null;
+let nextAudioTime = 0;
 
 const playPauseButton = document.getElementById("play-pause");
 const canvas = document.getElementById("nes-wasm-canvas");
@@ -71,13 +73,42 @@ const drawFrameBuff = (frameBuffPtr, length) => {
     ctx.drawImage(ctx.canvas, 0, 0, SCREEN_WIDTH, SCREEN_HEIGHT, 0, 0, canvas.width, canvas.height);
 };
 
+const playAudioBuff = (audioBuffPtr, length) => {
+    if (length === 0) {
+        return;
+    }
+    const samples = new Float32Array(memory.buffer, audioBuffPtr, length);
+    const buffer = audioCtx.createBuffer(1, length, audioCtx.sampleRate);
+    buffer.copyToChannel(samples, 0);
+    const source = audioCtx.createBufferSource();
+    source.buffer = buffer;
+    source.connect(audioCtx.destination);
+    // Queue each frame right after the previous one. If we fell behind (or
+    // were paused) start again slightly ahead of the current time.
+    if (nextAudioTime < audioCtx.currentTime) {
+        nextAudioTime = audioCtx.currentTime + 0.05;
+    }
+    source.start(nextAudioTime);
+    nextAudioTime += buffer.duration;
+};
+
 const isPaused = () => {
   return animationId === null;
 };
 
 const renderLoop = () => {
-  const bufferStruct = nes_fe.get_frame();
+  let bufferStruct;
+  try {
+    bufferStruct = nes_fe.get_frame();
+  } catch (e) {
+    // The game can't carry on by itself, stop instead of spinning on it
+    console.error(e);
+    animationId = null;
+    return;
+  }
   drawFrameBuff(bufferStruct.pointer, bufferStruct.length);
+  const audioStruct = nes_fe.get_audio();
+  playAudioBuff(audioStruct.pointer, audioStruct.length);
   animationId = requestAnimationFrame(renderLoop);
 };
 
@@ -86,7 +117,17 @@ document.querySelector("#file-input").addEventListener('change', function() {
     reader.onload = function() {
         const romBuffer = new Uint8Array(this.result);
 
-        nes_fe = EmuInterface.new(romBuffer);
+        try {
+            nes_fe = EmuInterface.new(romBuffer);
+        } catch (e) {
+            console.error(e);
+            return;
+        }
+        // Created here since browsers only allow audio after user input
+        if (audioCtx === null) {
+            audioCtx = new AudioContext();
+        }
+        nes_fe.set_sample_rate(audioCtx.sampleRate);
         requestAnimationFrame(renderLoop);
     }
 
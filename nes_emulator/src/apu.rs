@@ -1,46 +1,295 @@
+use crate::apu::dmc::Dmc;
+use crate::apu::noise::Noise;
+use crate::apu::pulse::Pulse;
+use crate::apu::resampler::DEFAULT_SAMPLE_RATE;
+use crate::apu::resampler::Resampler;
+use crate::apu::triangle::Triangle;
+use crate::timing::Timing;
 use serde::Deserialize;
 use serde::Serialize;
 
-#[derive(Serialize, Deserialize, Default)]
+pub mod dmc;
+pub mod envelope;
+pub mod noise;
+pub mod pulse;
+pub mod resampler;
+pub mod triangle;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+enum SequencerMode {
+    FourStep,
+    FiveStep,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct FrameCounter {
+    mode: SequencerMode,
+    irq_inhibit: bool,
+    irq: bool,
+    cycle: u16,
+    // Writes to $4017 restart the sequencer after a 3 or 4 cycle delay
+    reset_delay: u8,
+}
+
+impl Default for FrameCounter {
+    fn default() -> FrameCounter {
+        FrameCounter {
+            mode: SequencerMode::FourStep,
+            irq_inhibit: false,
+            irq: false,
+            cycle: 0,
+            reset_delay: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Apu {
-    pulse1: u8,
-    pulse2: u8,
-    triangle: u8,
-    noise: u8,
-    dmc: u8,
-    control: u8,
-    status: u8,
-    frame_counter: u8,
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    pub dmc: Dmc,
+    frame_counter: FrameCounter,
+    timing: Timing,
+    // The pulse timers only tick on every other CPU cycle
+    odd_cycle: bool,
+    #[serde(skip)]
+    resampler: Resampler,
+}
+
+impl Default for Apu {
+    fn default() -> Apu {
+        Apu::new(Timing::Ntsc)
+    }
 }
 
 impl Apu {
-    pub fn load(&mut self, addr: u16) -> u8 {
-        match addr {
-            0x15 => self.read_status(),
-            _ => panic!(
-                "No other addresses are mapped to reading from the APU {:X}",
-                addr
+    pub fn new(timing: Timing) -> Apu {
+        Apu {
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::default(),
+            noise: Noise::new(timing.noise_periods()),
+            dmc: Dmc::new(timing.dmc_rates()),
+            frame_counter: FrameCounter::default(),
+            timing,
+            odd_cycle: false,
+            resampler: Resampler::new(
+                timing.cpu_clock_speed() as f32,
+                DEFAULT_SAMPLE_RATE,
             ),
         }
     }
-    pub fn store(&mut self, addr: u16, _val: u8) {
+
+    // On reset the channels are silenced and the frame counter behaves as if
+    // $4017 was rewritten with its last value
+    pub fn reset(&mut self) {
+        self.write_status(0);
+        let mut last_write = self.frame_counter.irq_inhibit as u8 * 0x40;
+        if self.frame_counter.mode == SequencerMode::FiveStep {
+            last_write |= 0x80;
+        }
+        self.write_frame_counter(last_write);
+    }
+
+    // Only the status register can be read, the rest leave the bus alone
+    pub fn load(&mut self, addr: u16) -> Option<u8> {
         match addr {
-            0x00..=0x03 => {}
-            0x04..=0x07 => {}
-            0x08..=0x0B => {}
-            0x0C..=0x0F => {}
-            0x10..=0x13 => {}
-            0x15 => {}
-            0x17 => {}
-            _ => {
-                ()
-                // ???? what panic!("APU: Can't get here");
-            }
+            0x15 => Some(self.read_status()),
+            _ => None,
+        }
+    }
+
+    pub fn store(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x00..=0x03 => self.pulse1.store(addr, val),
+            0x04..=0x07 => self.pulse2.store(addr - 0x04, val),
+            0x08..=0x0B => self.triangle.store(addr - 0x08, val),
+            0x0C..=0x0F => self.noise.store(addr - 0x0C, val),
+            0x10..=0x13 => self.dmc.store(addr - 0x10, val),
+            0x15 => self.write_status(val),
+            0x17 => self.write_frame_counter(val),
+            _ => log::debug!("APU: unmapped write {:X} {:X}", addr, val),
         }
     }
 
+    // Reading the status acknowledges the frame interrupt
     pub fn read_status(&mut self) -> u8 {
-        //TODO: this is a placeholder
-        self.status
+        let frame_irq = self.frame_counter.irq;
+        self.frame_counter.irq = false;
+        (self.dmc.irq as u8) << 7
+            | (frame_irq as u8) << 6
+            | (self.dmc.active() as u8) << 4
+            | (self.noise.length.active() as u8) << 3
+            | (self.triangle.length.active() as u8) << 2
+            | (self.pulse2.length.active() as u8) << 1
+            | self.pulse1.length.active() as u8
+    }
+
+    fn write_status(&mut self, val: u8) {
+        self.pulse1.length.set_enabled(val & 0b00001 != 0);
+        self.pulse2.length.set_enabled(val & 0b00010 != 0);
+        self.triangle.length.set_enabled(val & 0b00100 != 0);
+        self.noise.length.set_enabled(val & 0b01000 != 0);
+        self.dmc.set_enabled(val & 0b10000 != 0);
+        self.dmc.irq = false;
+    }
+
+    fn write_frame_counter(&mut self, val: u8) {
+        self.frame_counter.mode = if val & 0x80 != 0 {
+            SequencerMode::FiveStep
+        } else {
+            SequencerMode::FourStep
+        };
+        self.frame_counter.irq_inhibit = val & 0x40 != 0;
+        if self.frame_counter.irq_inhibit {
+            self.frame_counter.irq = false;
+        }
+        // Writes that land on an APU cycle take effect 3 cycles later, writes
+        // in between APU cycles take one extra cycle
+        self.frame_counter.reset_delay = if self.odd_cycle { 3 } else { 4 };
+    }
+
+    // The level of the APU's contribution to the CPU's IRQ line
+    pub fn irq(&self) -> bool {
+        self.frame_counter.irq || self.dmc.irq
+    }
+
+    fn quarter_frame(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    fn half_frame(&mut self) {
+        self.pulse1.length.clock();
+        self.pulse1.clock_sweep();
+        self.pulse2.length.clock();
+        self.pulse2.clock_sweep();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+    }
+
+    fn set_frame_irq(&mut self) {
+        if !self.frame_counter.irq_inhibit {
+            self.frame_counter.irq = true;
+        }
+    }
+
+    fn step_frame_counter(&mut self) {
+        if self.frame_counter.reset_delay > 0 {
+            self.frame_counter.reset_delay -= 1;
+            if self.frame_counter.reset_delay == 0 {
+                self.frame_counter.cycle = 0;
+                // The 5 step sequence clocks everything immediately
+                if self.frame_counter.mode == SequencerMode::FiveStep {
+                    self.quarter_frame();
+                    self.half_frame();
+                }
+                return;
+            }
+        }
+
+        self.frame_counter.cycle += 1;
+        let steps = self.timing.frame_steps();
+        let cycle = self.frame_counter.cycle;
+        match self.frame_counter.mode {
+            _ if cycle == steps.quarter_frame_1
+                || cycle == steps.quarter_frame_3 =>
+            {
+                self.quarter_frame()
+            }
+            _ if cycle == steps.half_frame_1 => {
+                self.quarter_frame();
+                self.half_frame();
+            }
+            SequencerMode::FourStep if cycle == steps.four_step_irq => {
+                self.set_frame_irq()
+            }
+            SequencerMode::FourStep if cycle == steps.four_step_half_frame => {
+                self.quarter_frame();
+                self.half_frame();
+                self.set_frame_irq();
+            }
+            SequencerMode::FourStep if cycle == steps.four_step_len => {
+                self.set_frame_irq();
+                self.frame_counter.cycle = 0;
+            }
+            SequencerMode::FiveStep if cycle == steps.five_step_half_frame => {
+                self.quarter_frame();
+                self.half_frame();
+            }
+            SequencerMode::FiveStep if cycle == steps.five_step_len => {
+                self.frame_counter.cycle = 0;
+            }
+            _ => (),
+        }
+    }
+
+    // Advances every channel by one CPU cycle. Called from the same catch up
+    // path that drives the PPU, so the APU never runs ahead of the CPU.
+    pub fn step(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+        self.step_frame_counter();
+        let output = self.output();
+        self.resampler.push(output);
+    }
+
+    // Loads the emulated state from a save state, the resampler keeps its
+    // settings since they belong to the frontend
+    pub fn set_state(&mut self, apu: Apu) {
+        let resampler = std::mem::take(&mut self.resampler);
+        *self = apu;
+        self.resampler = resampler;
+        self.resampler.clear();
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.resampler =
+            Resampler::new(self.resampler.cpu_clock(), sample_rate);
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.resampler.sample_rate()
+    }
+
+    // Filtered PCM produced since the last call to clear_samples
+    pub fn samples(&self) -> &[f32] {
+        self.resampler.samples()
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.resampler.clear();
+    }
+
+    // Non linear mixer, see https://www.nesdev.org/wiki/APU_Mixer
+    // The result is in the range 0.0 to roughly 1.0
+    pub fn output(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let tnd = self.triangle.output() as f32 / 8227.0
+            + self.noise.output() as f32 / 12241.0
+            + self.dmc.output() as f32 / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out
     }
 }
@@ -0,0 +1,144 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct Dmc {
+    pub irq: bool,
+    irq_enabled: bool,
+    looping: bool,
+    // Measured in CPU cycles, these differ between NTSC and PAL
+    rates: [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    level: u8,
+    // Memory reader
+    sample_addr: u16,
+    sample_len: u16,
+    cur_addr: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    // Output unit
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+}
+
+impl Dmc {
+    pub fn new(rates: [u16; 16]) -> Dmc {
+        Dmc {
+            irq: false,
+            irq_enabled: false,
+            looping: false,
+            rates,
+            timer_period: rates[0],
+            timer: 0,
+            level: 0,
+            sample_addr: 0xC000,
+            sample_len: 1,
+            cur_addr: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+        }
+    }
+
+    pub fn store(&mut self, reg: u16, val: u8) {
+        match reg & 0b11 {
+            0 => {
+                self.irq_enabled = val & 0x80 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.looping = val & 0x40 != 0;
+                self.timer_period = self.rates[(val & 0x0F) as usize];
+            }
+            1 => self.level = val & 0x7F,
+            // Sample address is %11AAAAAA.AA000000
+            2 => self.sample_addr = 0xC000 | ((val as u16) << 6),
+            // Sample length is %LLLL.LLLL0001
+            _ => self.sample_len = ((val as u16) << 4) | 1,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.cur_addr = self.sample_addr;
+        self.bytes_remaining = self.sample_len;
+    }
+
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    // The address the memory reader wants to fetch next, if the sample buffer
+    // has been emptied and there are still bytes left in the sample
+    pub fn pending_fetch(&self) -> Option<u16> {
+        if self.buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.cur_addr)
+        } else {
+            None
+        }
+    }
+
+    pub fn fill(&mut self, val: u8) {
+        self.buffer = Some(val);
+        // The address wraps around to 0x8000, not 0x0000
+        self.cur_addr = if self.cur_addr == 0xFFFF {
+            0x8000
+        } else {
+            self.cur_addr + 1
+        };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+
+        if !self.silence {
+            if self.shift & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(sample) => {
+                    self.silence = false;
+                    self.shift = sample;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.level
+    }
+}
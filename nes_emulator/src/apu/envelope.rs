@@ -0,0 +1,96 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24,
+    18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+// Shared by the pulse and noise channels. The loop flag doubles as the length
+// counter halt flag, so the owning channel is responsible for forwarding it.
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
+pub struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    period: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    pub fn store(&mut self, val: u8) {
+        self.looping = val & 0x20 != 0;
+        self.constant = val & 0x10 != 0;
+        self.period = val & 0x0F;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    // Clocked by the frame counter on every quarter frame
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.period;
+        } else if self.divider == 0 {
+            self.divider = self.period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub fn volume(&self) -> u8 {
+        if self.constant {
+            self.period
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
+pub struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub fn set_halt(&mut self, halt: bool) {
+        self.halt = halt;
+    }
+
+    // The top 5 bits of the channel's last register index the length table.
+    // Writes while the channel is disabled are ignored.
+    pub fn load(&mut self, val: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(val >> 3) as usize];
+        }
+    }
+
+    // Clocked by the frame counter on every half frame
+    pub fn clock(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
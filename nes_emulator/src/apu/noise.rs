@@ -0,0 +1,68 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::envelope::LengthCounter;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct Noise {
+    short_mode: bool,
+    shift: u16,
+    // Measured in CPU cycles, these differ between NTSC and PAL
+    periods: [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Noise {
+    pub fn new(periods: [u16; 16]) -> Noise {
+        Noise {
+            short_mode: false,
+            // The shift register is loaded with 1 on power up
+            shift: 1,
+            periods,
+            timer_period: periods[0],
+            timer: 0,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    pub fn store(&mut self, reg: u16, val: u8) {
+        match reg & 0b11 {
+            0 => {
+                self.length.set_halt(val & 0x20 != 0);
+                self.envelope.store(val);
+            }
+            1 => (),
+            2 => {
+                self.short_mode = val & 0x80 != 0;
+                self.timer_period = self.periods[(val & 0x0F) as usize];
+            }
+            _ => {
+                self.length.load(val);
+                self.envelope.restart();
+            }
+        }
+    }
+
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 1 != 0 {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
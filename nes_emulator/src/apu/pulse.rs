@@ -0,0 +1,126 @@
+use crate::apu::envelope::Envelope;
+use crate::apu::envelope::LengthCounter;
+use serde::Deserialize;
+use serde::Serialize;
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
+struct Sweep {
+    enabled: bool,
+    negate: bool,
+    reload: bool,
+    period: u8,
+    shift: u8,
+    divider: u8,
+}
+
+impl Sweep {
+    fn store(&mut self, val: u8) {
+        self.enabled = val & 0x80 != 0;
+        self.period = (val >> 4) & 0b111;
+        self.negate = val & 0x08 != 0;
+        self.shift = val & 0b111;
+        self.reload = true;
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
+pub struct Pulse {
+    // Pulse 1 negates with one's complement, pulse 2 with two's complement
+    ones_complement: bool,
+    duty: u8,
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    sweep: Sweep,
+    pub envelope: Envelope,
+    pub length: LengthCounter,
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Pulse {
+        Pulse {
+            ones_complement,
+            ..Default::default()
+        }
+    }
+
+    pub fn store(&mut self, reg: u16, val: u8) {
+        match reg & 0b11 {
+            0 => {
+                self.duty = val >> 6;
+                self.length.set_halt(val & 0x20 != 0);
+                self.envelope.store(val);
+            }
+            1 => self.sweep.store(val),
+            2 => self.timer_period = (self.timer_period & 0x700) | val as u16,
+            _ => {
+                self.timer_period =
+                    (self.timer_period & 0xFF) | ((val as u16 & 0b111) << 8);
+                self.length.load(val);
+                self.step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    // Clocked once every APU cycle, which is every other CPU cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.step = (self.step + 1) & 0b111;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = (self.timer_period >> self.sweep.shift) as i32;
+        let period = self.timer_period as i32;
+        let target = if self.sweep.negate {
+            period - change - self.ones_complement as i32
+        } else {
+            period + change
+        };
+        target.max(0) as u16
+    }
+
+    // The sweep unit mutes the channel even when it is disabled
+    fn muted(&self) -> bool {
+        self.timer_period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    pub fn clock_sweep(&mut self) {
+        if self.sweep.divider == 0
+            && self.sweep.enabled
+            && self.sweep.shift > 0
+            && !self.muted()
+        {
+            self.timer_period = self.sweep_target();
+        }
+
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length.active()
+            || self.muted()
+            || DUTY_TABLE[self.duty as usize][self.step as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
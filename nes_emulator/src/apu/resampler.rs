@@ -0,0 +1,189 @@
+use crate::timing::Timing;
+use std::f32::consts::PI;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+// Output samples each step is spread over, and how many positions between
+// two output samples the step gets worked out for
+const TAPS: usize = 32;
+const PHASES: usize = 32;
+// Where the steps get band-limited to, as a fraction of the sample rate. The
+// window rolls off from there to well past half the sample rate.
+const CUTOFF: f32 = 0.4;
+
+// First order IIR filters, see https://www.nesdev.org/wiki/APU_Mixer
+#[derive(Clone, Copy)]
+enum Pass {
+    High,
+    Low,
+}
+
+#[derive(Clone, Copy)]
+struct Filter {
+    pass: Pass,
+    alpha: f32,
+    prev_in: f32,
+    prev_out: f32,
+}
+
+impl Filter {
+    fn new(pass: Pass, cutoff: f32, sample_rate: f32) -> Filter {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        let alpha = match pass {
+            Pass::High => rc / (rc + dt),
+            Pass::Low => dt / (rc + dt),
+        };
+        Filter {
+            pass,
+            alpha,
+            prev_in: 0.0,
+            prev_out: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let out = match self.pass {
+            Pass::High => self.alpha * (self.prev_out + input - self.prev_in),
+            Pass::Low => self.prev_out + self.alpha * (input - self.prev_out),
+        };
+        self.prev_in = input;
+        self.prev_out = out;
+        out
+    }
+}
+
+// The impulse response of a windowed sinc low pass, worked out for every
+// phase a step can land at between two output samples. Each row adds up to 1
+// so a step settles at its full height.
+fn step_kernel() -> Vec<[f32; TAPS]> {
+    (0..=PHASES)
+        .map(|phase| {
+            let centre = (TAPS / 2 - 1) as f32 + phase as f32 / PHASES as f32;
+            let mut row = [0.0; TAPS];
+            for (n, tap) in row.iter_mut().enumerate() {
+                let x = n as f32 - centre;
+                let arg = 2.0 * PI * CUTOFF * x;
+                let sinc = if x == 0.0 { 1.0 } else { arg.sin() / arg };
+                // Blackman, which is 0 half the row either side of centre
+                let angle = 2.0 * PI * x / TAPS as f32;
+                let window =
+                    0.42 + 0.5 * angle.cos() + 0.08 * (2.0 * angle).cos();
+                *tap = sinc * window;
+            }
+            let sum: f32 = row.iter().sum();
+            row.map(|tap| tap / sum)
+        })
+        .collect()
+}
+
+// Takes the mixer output once per CPU cycle and produces PCM at the host's
+// sample rate with band-limited steps, see
+// http://www.slack.net/~ant/bl-synth/. The mixer output only ever changes in
+// steps, so every change adds a band-limited impulse to the deltas of the
+// next TAPS output samples, lined up with the fraction of a sample it
+// happened at. Adding the deltas up gives the output with everything above
+// the cutoff removed before it gets sampled, so the square waves' harmonics
+// don't alias. The NES output stage filters are applied afterwards.
+#[derive(Clone)]
+pub struct Resampler {
+    cpu_clock: f32,
+    sample_rate: u32,
+    cycles_per_sample: f32,
+    // How far into the current output sample we are, measured in CPU cycles
+    phase: f32,
+    // The last input, the next step is the change from it
+    input: f32,
+    kernel: Vec<[f32; TAPS]>,
+    // Ring buffer of the changes to the next TAPS output samples, the next
+    // one out is at head
+    deltas: [f32; TAPS],
+    head: usize,
+    // The sum of every delta so far
+    level: f32,
+    filters: [Filter; 3],
+    samples: Vec<f32>,
+}
+
+impl Default for Resampler {
+    fn default() -> Resampler {
+        Resampler::new(
+            Timing::Ntsc.cpu_clock_speed() as f32,
+            DEFAULT_SAMPLE_RATE,
+        )
+    }
+}
+
+impl Resampler {
+    pub fn new(cpu_clock: f32, sample_rate: u32) -> Resampler {
+        let rate = sample_rate as f32;
+        Resampler {
+            cpu_clock,
+            sample_rate,
+            cycles_per_sample: cpu_clock / rate,
+            phase: 0.0,
+            input: 0.0,
+            kernel: step_kernel(),
+            deltas: [0.0; TAPS],
+            head: 0,
+            level: 0.0,
+            filters: [
+                Filter::new(Pass::High, 90.0, rate),
+                Filter::new(Pass::High, 440.0, rate),
+                Filter::new(Pass::Low, 14000.0, rate),
+            ],
+            samples: Vec::with_capacity(sample_rate as usize / 50),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn cpu_clock(&self) -> f32 {
+        self.cpu_clock
+    }
+
+    pub fn push(&mut self, input: f32) {
+        let delta = input - self.input;
+        if delta != 0.0 {
+            self.input = input;
+            self.add_step(delta);
+        }
+        self.phase += 1.0;
+        if self.phase < self.cycles_per_sample {
+            return;
+        }
+        self.phase -= self.cycles_per_sample;
+
+        self.level += self.deltas[self.head];
+        self.deltas[self.head] = 0.0;
+        self.head = (self.head + 1) % TAPS;
+        let mut sample = self.level;
+        for filter in self.filters.iter_mut() {
+            sample = filter.process(sample);
+        }
+        self.samples.push(sample);
+    }
+
+    // Spreads a step at the current phase over the next TAPS output samples,
+    // interpolating between the two closest rows of the kernel
+    fn add_step(&mut self, delta: f32) {
+        let pos = self.phase / self.cycles_per_sample * PHASES as f32;
+        let row = (pos as usize).min(PHASES - 1);
+        let frac = pos - row as f32;
+        let before = &self.kernel[row];
+        let after = &self.kernel[row + 1];
+        for (n, (a, b)) in before.iter().zip(after).enumerate() {
+            let tap = a + (b - a) * frac;
+            self.deltas[(self.head + n) % TAPS] += delta * tap;
+        }
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
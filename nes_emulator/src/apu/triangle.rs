@@ -0,0 +1,75 @@
+use crate::apu::envelope::LengthCounter;
+use serde::Deserialize;
+use serde::Serialize;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6,
+    7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
+pub struct Triangle {
+    control: bool,
+    linear_reload: bool,
+    linear_period: u8,
+    linear_counter: u8,
+    step: u8,
+    timer_period: u16,
+    timer: u16,
+    pub length: LengthCounter,
+}
+
+impl Triangle {
+    pub fn store(&mut self, reg: u16, val: u8) {
+        match reg & 0b11 {
+            0 => {
+                self.control = val & 0x80 != 0;
+                self.length.set_halt(self.control);
+                self.linear_period = val & 0x7F;
+            }
+            1 => (),
+            2 => self.timer_period = (self.timer_period & 0x700) | val as u16,
+            _ => {
+                self.timer_period =
+                    (self.timer_period & 0xFF) | ((val as u16 & 0b111) << 8);
+                self.length.load(val);
+                self.linear_reload = true;
+            }
+        }
+    }
+
+    // Unlike the other channels, the triangle timer runs at the CPU clock
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length.active() {
+                self.step = (self.step + 1) & 0x1F;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_period;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    // The sequencer simply stops when silenced, so the channel holds its last
+    // output level instead of dropping to zero. Periods below 2 are
+    // ultrasonic and get flattened to the midpoint to avoid popping.
+    pub fn output(&self) -> u8 {
+        if self.timer_period < 2 {
+            7
+        } else {
+            SEQUENCE[self.step as usize]
+        }
+    }
+}
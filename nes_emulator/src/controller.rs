@@ -1,4 +1,5 @@
-const SIG_BYTE: u8 = 0x40;
+use serde::Deserialize;
+use serde::Serialize;
 
 #[derive(Copy, Clone)]
 #[repr(u8)]
@@ -13,29 +14,58 @@ pub enum Button {
     Right = 0b1000_0000,
 }
 
-#[derive(Default)]
+#[derive(Serialize, Deserialize, Default, Clone)]
 pub struct Controller {
     ctrl_state: u8,
     strobe: bool,
     shift: usize,
+    // Since the last call to clear_polls
+    reads: u32,
+    strobes: u32,
+}
+
+// How the game used the controllers during a frame. A strobe is a write
+// that ends the strobe, which is when the buttons get latched.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InputPolls {
+    pub reads: [u32; 2],
+    pub strobes: [u32; 2],
+}
+
+impl InputPolls {
+    // Lag frames are frames where the game didn't look at the controllers
+    pub fn is_lag(&self) -> bool {
+        self.reads == [0, 0]
+    }
 }
 
 impl Controller {
     pub fn ld8(&mut self) -> u8 {
-        let val = if self.shift < 8 {
-            (self.ctrl_state >> self.shift) & 1
-        } else {
-            1
-        };
+        self.reads += 1;
+        let val = self.peek();
 
         if !self.strobe {
             self.shift += 1;
         }
-        // Required by some games. Check nesdev controllers for more info
-        SIG_BYTE | val
+        // Only the low bits get driven, the MMU fills in the rest from the
+        // data bus. Some games rely on that being $40, the high byte of
+        // $4016.
+        val
+    }
+
+    // The bit the next read returns, without clocking the shift register
+    pub fn peek(&self) -> u8 {
+        if self.shift < 8 {
+            (self.ctrl_state >> self.shift) & 1
+        } else {
+            1
+        }
     }
 
     pub fn store(&mut self, val: u8) {
+        if self.strobe && val & 1 == 0 {
+            self.strobes += 1;
+        }
         self.strobe = val & 1 != 0;
         if self.strobe {
             self.shift = 0;
@@ -49,4 +79,26 @@ impl Controller {
             self.ctrl_state &= !(button as u8);
         }
     }
+
+    pub fn reads(&self) -> u32 {
+        self.reads
+    }
+
+    pub fn strobes(&self) -> u32 {
+        self.strobes
+    }
+
+    pub fn clear_polls(&mut self) {
+        self.reads = 0;
+        self.strobes = 0;
+    }
+
+    // All of the buttons at once, one bit per button as in Button
+    pub fn buttons(&self) -> u8 {
+        self.ctrl_state
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.ctrl_state = buttons;
+    }
 }
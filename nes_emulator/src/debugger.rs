@@ -0,0 +1,605 @@
+use crate::Fault;
+use crate::NesEmulator;
+use anyhow::Result;
+use cpu_6502::cpu::Registers;
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+use thiserror::Error;
+
+const JSR: u8 = 0x20;
+const RTI: u8 = 0x40;
+const RTS: u8 = 0x60;
+
+const DOTS_PER_SCANLINE: u32 = 341;
+
+#[derive(Debug, Error)]
+pub enum DebuggerError {
+    #[error("Bad debugger command: {0}")]
+    BadCommand(String),
+    #[error("Unknown register {0}")]
+    UnknownRegister(String),
+    #[error("Bad number {0}")]
+    BadNumber(String),
+    #[error("No breakpoint or watchpoint numbered {0}")]
+    NotFound(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    Sp,
+    P,
+    Pc,
+}
+
+impl Register {
+    pub fn value(&self, regs: &Registers) -> u16 {
+        match self {
+            Register::A => regs.acc as u16,
+            Register::X => regs.x as u16,
+            Register::Y => regs.y as u16,
+            Register::Sp => regs.sp as u16,
+            Register::P => regs.flags.as_byte() as u16,
+            Register::Pc => regs.pc.get_addr(),
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = DebuggerError;
+
+    fn from_str(text: &str) -> Result<Register, DebuggerError> {
+        match text.to_ascii_lowercase().as_str() {
+            "a" | "acc" => Ok(Register::A),
+            "x" => Ok(Register::X),
+            "y" => Ok(Register::Y),
+            "s" | "sp" => Ok(Register::Sp),
+            "p" | "flags" => Ok(Register::P),
+            "pc" => Ok(Register::Pc),
+            _ => Err(DebuggerError::UnknownRegister(text.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Register::A => "A",
+            Register::X => "X",
+            Register::Y => "Y",
+            Register::Sp => "SP",
+            Register::P => "P",
+            Register::Pc => "PC",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+// Two character operators first, so "<=" isn't taken for "<"
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Eq),
+    ("!=", Comparison::Ne),
+    ("<=", Comparison::Le),
+    (">=", Comparison::Ge),
+    ("<", Comparison::Lt),
+    (">", Comparison::Gt),
+];
+
+// A register compared against a value, like "A == $10"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn new(
+        register: Register,
+        comparison: Comparison,
+        value: u16,
+    ) -> Condition {
+        Condition {
+            register,
+            comparison,
+            value,
+        }
+    }
+
+    pub fn matches(&self, regs: &Registers) -> bool {
+        let register = self.register.value(regs);
+        match self.comparison {
+            Comparison::Eq => register == self.value,
+            Comparison::Ne => register != self.value,
+            Comparison::Lt => register < self.value,
+            Comparison::Le => register <= self.value,
+            Comparison::Gt => register > self.value,
+            Comparison::Ge => register >= self.value,
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = DebuggerError;
+
+    fn from_str(text: &str) -> Result<Condition, DebuggerError> {
+        let text: String = text.split_whitespace().collect();
+        let (register, comparison, value) = COMPARISONS
+            .iter()
+            .find_map(|(op, comparison)| {
+                text.split_once(op)
+                    .map(|(register, value)| (register, *comparison, value))
+            })
+            .ok_or_else(|| DebuggerError::BadCommand(text.clone()))?;
+        Ok(Condition::new(
+            register.parse()?,
+            comparison,
+            parse_hex(value)?,
+        ))
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (op, _) = COMPARISONS
+            .iter()
+            .find(|(_, comparison)| *comparison == self.comparison)
+            .expect("Every comparison has an operator");
+        write!(f, "{} {} ${:X}", self.register, op, self.value)
+    }
+}
+
+// Stops before the instruction at pc runs. Without a pc the condition is
+// checked before every instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub pc: Option<u16>,
+    pub condition: Option<Condition>,
+}
+
+impl Breakpoint {
+    pub fn at(pc: u16) -> Breakpoint {
+        Breakpoint {
+            pc: Some(pc),
+            condition: None,
+        }
+    }
+
+    pub fn when(condition: Condition) -> Breakpoint {
+        Breakpoint {
+            pc: None,
+            condition: Some(condition),
+        }
+    }
+
+    pub fn with_condition(self, condition: Condition) -> Breakpoint {
+        Breakpoint {
+            condition: Some(condition),
+            ..self
+        }
+    }
+
+    pub fn hit(&self, regs: &Registers) -> bool {
+        self.pc.is_none_or(|pc| pc == regs.pc.get_addr())
+            && self.condition.is_none_or(|c| c.matches(regs))
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.pc, self.condition) {
+            (Some(pc), Some(condition)) => {
+                write!(f, "${:04X} if {}", pc, condition)
+            }
+            (Some(pc), None) => write!(f, "${:04X}", pc),
+            (None, Some(condition)) => write!(f, "if {}", condition),
+            (None, None) => write!(f, "every instruction"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+// Stops after the instruction that touched an address from start to end,
+// inclusive. Dummy reads and writes count, they happen on the real bus too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub access: Access,
+}
+
+impl Watchpoint {
+    pub fn new(start: u16, end: u16, access: Access) -> Watchpoint {
+        Watchpoint { start, end, access }
+    }
+
+    pub fn matches(&self, address: u16, write: bool) -> bool {
+        let access = match self.access {
+            Access::Read => !write,
+            Access::Write => write,
+            Access::ReadWrite => true,
+        };
+        access && (self.start..=self.end).contains(&address)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let access = match self.access {
+            Access::Read => "r",
+            Access::Write => "w",
+            Access::ReadWrite => "rw",
+        };
+        if self.start == self.end {
+            write!(f, "{} ${:04X}", access, self.start)
+        } else {
+            write!(f, "{} ${:04X}-${:04X}", access, self.start, self.end)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    // The watchpoint's number
+    pub id: usize,
+    pub address: u16,
+    pub value: u8,
+    pub write: bool,
+}
+
+// Numbered in the order they were added, numbers of deleted entries aren't
+// reused
+#[derive(Debug, Clone)]
+pub struct PointList<T> {
+    points: Vec<(usize, T)>,
+    next_id: usize,
+}
+
+impl<T> Default for PointList<T> {
+    fn default() -> PointList<T> {
+        PointList {
+            points: Vec::new(),
+            next_id: 0,
+        }
+    }
+}
+
+impl<T> PointList<T> {
+    pub fn add(&mut self, point: T) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.points.push((id, point));
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<T> {
+        let index = self.points.iter().position(|(i, _)| *i == id)?;
+        Some(self.points.remove(index).1)
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(usize, T)> {
+        self.points.iter()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunCommand {
+    // Until the end of the frame
+    Frame,
+    // One instruction, following jumps into subroutines
+    StepInto,
+    // One instruction, running a whole subroutine when it's a JSR
+    StepOver,
+    // Until the current subroutine or interrupt handler returns
+    StepOut,
+    // Until the PPU gets to or past the dot on the scanline
+    RunTo { scanline: u16, dot: u16 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    FrameEnd,
+    Breakpoint(usize),
+    Watchpoint(WatchHit),
+    Step,
+    Position,
+    // The CPU jammed or stopped, see EmulationError
+    Fault(Fault),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::FrameEnd => write!(f, "End of frame"),
+            StopReason::Breakpoint(id) => write!(f, "Breakpoint {}", id),
+            StopReason::Watchpoint(hit) => write!(
+                f,
+                "Watchpoint {}: {} ${:02X} {} ${:04X}",
+                hit.id,
+                if hit.write { "wrote" } else { "read" },
+                hit.value,
+                if hit.write { "to" } else { "from" },
+                hit.address
+            ),
+            StopReason::Step => write!(f, "Stepped"),
+            StopReason::Position => write!(f, "Reached position"),
+            StopReason::Fault(fault) => write!(f, "{}", fault),
+        }
+    }
+}
+
+impl NesEmulator {
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.add(breakpoint)
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> Option<Breakpoint> {
+        self.breakpoints.remove(id)
+    }
+
+    pub fn breakpoints(&self) -> &PointList<Breakpoint> {
+        &self.breakpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.mmu.watchpoints.add(watchpoint)
+    }
+
+    pub fn remove_watchpoint(&mut self, id: usize) -> Option<Watchpoint> {
+        self.mmu.watchpoints.remove(id)
+    }
+
+    pub fn watchpoints(&self) -> &PointList<Watchpoint> {
+        &self.mmu.watchpoints
+    }
+
+    // Emulates instruction by instruction until the command is done or a
+    // breakpoint, watchpoint or fault stops it. Running again from a
+    // breakpoint moves past it, after any other stop a breakpoint on the
+    // current instruction still gets hit.
+    // Stepping over and out give up at the end of the frame, so a routine
+    // that never returns can't hang the caller. Running to a position gives
+    // up after a whole frame when the position doesn't exist.
+    pub fn run(&mut self, command: RunCommand) -> StopReason {
+        let start = self.cpu.regs;
+        let return_addr = match command {
+            RunCommand::StepOver
+                if self.mmu.peek(start.pc.get_addr()) == JSR =>
+            {
+                Some(start.pc.get_addr().wrapping_add(3))
+            }
+            _ => None,
+        };
+        let frame_dots =
+            (self.timing.prerender_scanline() as u32 + 1) * DOTS_PER_SCANLINE;
+        let mut frame_ends = 0;
+        let mut resume_pc = self.resume_pc.take();
+        self.mmu.watch_hit = None;
+
+        loop {
+            if !self.mid_frame {
+                self.begin_frame();
+            }
+            let pc = self.cpu.regs.pc.get_addr();
+            if resume_pc.take() != Some(pc)
+                && let Some((id, _)) = self
+                    .breakpoints
+                    .iter()
+                    .find(|(_, breakpoint)| breakpoint.hit(&self.cpu.regs))
+            {
+                self.resume_pc = Some(pc);
+                return StopReason::Breakpoint(*id);
+            }
+
+            let opcode = self.mmu.peek(self.cpu.regs.pc.get_addr());
+            let before = self.ppu_position();
+            match self.step() {
+                Ok(true) => {
+                    self.end_frame();
+                    frame_ends += 1;
+                }
+                Ok(false) => (),
+                Err(e) => return StopReason::Fault(e.fault),
+            }
+            let after = self.ppu_position();
+            let regs = self.cpu.regs;
+
+            if let Some(hit) = self.mmu.watch_hit.take() {
+                return StopReason::Watchpoint(hit);
+            }
+            match command {
+                RunCommand::Frame if frame_ends > 0 => {
+                    return StopReason::FrameEnd;
+                }
+                RunCommand::StepInto => return StopReason::Step,
+                RunCommand::StepOver => match return_addr {
+                    Some(addr)
+                        if regs.pc.get_addr() != addr || regs.sp < start.sp => {
+                    }
+                    _ => return StopReason::Step,
+                },
+                RunCommand::StepOut
+                    if matches!(opcode, RTS | RTI) && regs.sp > start.sp =>
+                {
+                    return StopReason::Step;
+                }
+                RunCommand::RunTo { scanline, dot } => {
+                    let target =
+                        scanline as u32 * DOTS_PER_SCANLINE + dot as u32;
+                    let passed = if target >= frame_dots {
+                        false
+                    } else if before <= after {
+                        before < target && target <= after
+                    } else {
+                        before < target || target <= after
+                    };
+                    if passed {
+                        return StopReason::Position;
+                    }
+                    if frame_ends > 1 {
+                        return StopReason::FrameEnd;
+                    }
+                }
+                _ => (),
+            }
+            if frame_ends > 0
+                && matches!(command, RunCommand::StepOver | RunCommand::StepOut)
+            {
+                return StopReason::FrameEnd;
+            }
+        }
+    }
+
+    fn ppu_position(&self) -> u32 {
+        self.mmu.ppu.scanline() as u32 * DOTS_PER_SCANLINE
+            + self.mmu.ppu.dot() as u32
+    }
+
+    // The registers and where the PPU is, on one line
+    pub fn debug_status(&self) -> String {
+        format!(
+            "{:?} SL:{} DOT:{}",
+            self.cpu.regs,
+            self.mmu.ppu.scanline(),
+            self.mmu.ppu.dot()
+        )
+    }
+
+    // A text interface for frontend consoles. Numbers are hex, with or
+    // without a $ or 0x in front.
+    //   break ADDR [if COND]   b     stop before the instruction at ADDR
+    //   break if COND                stop whenever COND holds, like A == 10
+    //   delete N               d     remove breakpoint N
+    //   watch [r|w|rw] ADDR[-END]    stop after a read or write, rw if unset
+    //   unwatch N                    remove watchpoint N
+    //   list                   l     list breakpoints and watchpoints
+    //   step                   s     step into
+    //   next                   n     step over
+    //   finish                 f     step out
+    //   continue               c     run to the end of the frame
+    //   goto SCANLINE [DOT]          run to a PPU position, in decimal
+    //   regs                   r     show the registers
+    // Returns what should be printed back.
+    pub fn debug_command(&mut self, line: &str) -> Result<String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let bad_command = || DebuggerError::BadCommand(line.to_string());
+        let (&command, args) = words.split_first().ok_or_else(bad_command)?;
+
+        let run = match (command, args) {
+            ("break" | "b", [..]) => {
+                let (pc, condition) = match args {
+                    ["if", ..] => (None, &args[1..]),
+                    [addr, "if", ..] => (Some(parse_hex(addr)?), &args[2..]),
+                    [addr] => (Some(parse_hex(addr)?), &args[1..]),
+                    _ => return Err(bad_command().into()),
+                };
+                let condition = match condition {
+                    [] if pc.is_none() => return Err(bad_command().into()),
+                    [] => None,
+                    words => Some(words.concat().parse::<Condition>()?),
+                };
+                let breakpoint = Breakpoint { pc, condition };
+                let id = self.add_breakpoint(breakpoint);
+                return Ok(format!("Breakpoint {} at {}", id, breakpoint));
+            }
+            ("delete" | "d", [id]) => {
+                let id = parse_id(id)?;
+                self.remove_breakpoint(id)
+                    .ok_or(DebuggerError::NotFound(id))?;
+                return Ok(format!("Deleted breakpoint {}", id));
+            }
+            ("watch" | "w", [..]) => {
+                let (access, range) = match args {
+                    ["r", range] => (Access::Read, range),
+                    ["w", range] => (Access::Write, range),
+                    ["rw", range] | [range] => (Access::ReadWrite, range),
+                    _ => return Err(bad_command().into()),
+                };
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (parse_hex(start)?, parse_hex(end)?),
+                    None => (parse_hex(range)?, parse_hex(range)?),
+                };
+                let watchpoint = Watchpoint::new(start, end, access);
+                let id = self.add_watchpoint(watchpoint);
+                return Ok(format!("Watchpoint {} on {}", id, watchpoint));
+            }
+            ("unwatch", [id]) => {
+                let id = parse_id(id)?;
+                self.remove_watchpoint(id)
+                    .ok_or(DebuggerError::NotFound(id))?;
+                return Ok(format!("Deleted watchpoint {}", id));
+            }
+            ("list" | "l", []) => {
+                let mut text = String::new();
+                for (id, breakpoint) in self.breakpoints.iter() {
+                    let _ =
+                        writeln!(text, "Breakpoint {} at {}", id, breakpoint);
+                }
+                for (id, watchpoint) in self.mmu.watchpoints.iter() {
+                    let _ =
+                        writeln!(text, "Watchpoint {} on {}", id, watchpoint);
+                }
+                return Ok(text);
+            }
+            ("regs" | "r", []) => return Ok(self.debug_status()),
+            ("step" | "s", []) => RunCommand::StepInto,
+            ("next" | "n", []) => RunCommand::StepOver,
+            ("finish" | "f", []) => RunCommand::StepOut,
+            ("continue" | "c", []) => RunCommand::Frame,
+            ("goto", [scanline, dot @ ..]) if dot.len() <= 1 => {
+                let parse = |text: &str| {
+                    text.parse::<u16>()
+                        .map_err(|_| DebuggerError::BadNumber(text.to_string()))
+                };
+                RunCommand::RunTo {
+                    scanline: parse(scanline)?,
+                    dot: dot.first().map_or(Ok(0), |dot| parse(dot))?,
+                }
+            }
+            _ => return Err(bad_command().into()),
+        };
+        let reason = self.run(run);
+        Ok(format!("{}\n{}", reason, self.debug_status()))
+    }
+}
+
+fn parse_hex(text: &str) -> Result<u16, DebuggerError> {
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16)
+        .map_err(|_| DebuggerError::BadNumber(text.to_string()))
+}
+
+fn parse_id(text: &str) -> Result<usize, DebuggerError> {
+    text.parse()
+        .map_err(|_| DebuggerError::BadNumber(text.to_string()))
+}
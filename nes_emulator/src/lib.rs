@@ -1,30 +1,66 @@
 pub mod apu;
 pub mod controller;
+pub mod debugger;
 pub mod mapper;
 pub mod mmu;
+pub mod movie;
 pub mod ppu;
+pub mod rewind;
 pub mod rom;
 pub mod state;
+pub mod timing;
+pub mod trace;
 
-use crate::mmu::OAM_DATA;
+use anyhow::Result;
 use apu::Apu;
+use controller::InputPolls;
 use cpu_6502::cpu::Cpu;
-use cpu_6502::cpu_const::NMI_VEC;
-use mapper::Mapper;
+use cpu_6502::cpu::Halt;
+use cpu_6502::cpu::Registers;
+use debugger::Breakpoint;
+use debugger::PointList;
+use debugger::RunCommand;
+use debugger::StopReason;
+use mapper::Cartridge;
 use mmu::Mmu;
+use movie::Movie;
+use movie::MovieError;
+use movie::MovieFrame;
+use movie::MovieMode;
+use movie::MovieStart;
 use ppu::Ppu;
-use rom::Region;
+use rewind::Rewind;
+use rom::LoadRomError;
 use rom::Rom;
 use state::State;
+use state::StateFileError;
 use std::cell::RefCell;
+use std::io::Write;
 use std::rc::Rc;
-
-const _PAL_CPU_CLOCK_SPEED: usize = 1662607; // measured in hertz
-const NTSC_CPU_CLOCK_SPEED: usize = 1789773; // measured in hertz
+use thiserror::Error;
+use timing::Timing;
 
 pub struct NesEmulator {
     pub cpu: Cpu,
     pub mmu: Mmu,
+    timing: Timing,
+    // Frames emulated since power on
+    frame: u64,
+    // Frames the game didn't read the controllers in
+    lag_frames: u64,
+    input_polls: InputPolls,
+    // Nothing has run or been loaded since power on
+    at_power_on: bool,
+    rewind: Option<Rewind>,
+    movie: Option<MovieMode>,
+    // Stopped by the debugger before the frame was done
+    mid_frame: bool,
+    breakpoints: PointList<Breakpoint>,
+    // Where the last run stopped on a breakpoint, the next one runs past it
+    resume_pc: Option<u16>,
+    // Gets a nestest style line for every instruction
+    trace: Option<Box<dyn Write>>,
+    cpu_mode: CpuMode,
 }
 
 pub enum PlayerController {
@@ -32,83 +68,467 @@ pub enum PlayerController {
     Two,
 }
 
+// The CPU runs a bus cycle at a time either way, so both modes do the same
+// bus cycles in the same order. Instruction mode hands the CPU a whole
+// instruction at once, cycle mode goes through tick for every cycle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum CpuMode {
+    #[default]
+    Instruction,
+    Cycle,
+}
+
+// Something that stops the game from carrying on by itself
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    #[error("CPU jammed")]
+    Jam,
+    #[error("CPU stopped")]
+    Stop,
+}
+
+// A fault along with where the console was when it happened. The console
+// keeps running, so it can still be inspected, reset or have a state loaded.
+#[derive(Error, Debug, Clone, Copy)]
+#[error("{fault} at {regs:?} SL:{scanline} DOT:{dot}")]
+pub struct EmulationError {
+    pub fault: Fault,
+    pub regs: Registers,
+    pub scanline: u16,
+    pub dot: u16,
+}
+
 impl NesEmulator {
-    pub fn new(rom: Rom) -> NesEmulator {
-        let _ = match &rom.header.region {
-            Region::PAL => {
-                todo!("Unsupported ROM loaded, emulator doesn't support PAL!")
-            }
-            Region::NTSC => NTSC_CPU_CLOCK_SPEED,
-        };
-        let mapper = Rc::new(RefCell::new(Mapper::from_rom(rom)));
-        let mut mmu =
-            Mmu::new(Apu::default(), Ppu::new(mapper.clone()), mapper);
+    pub fn new(rom: Rom) -> Result<NesEmulator, LoadRomError> {
+        Ok(NesEmulator::from_cartridge(Cartridge::from_rom(rom)?))
+    }
+
+    // Takes an already built cartridge, so boards registered in a custom
+    // MapperRegistry can be used. The timing comes from the rom's header.
+    pub fn from_cartridge(cartridge: Cartridge) -> NesEmulator {
+        let timing = Timing::from_region(cartridge.rom.header.region);
+        NesEmulator::with_timing(cartridge, timing)
+    }
+
+    // Runs the cartridge on a specific console, ignoring the header. Useful
+    // for roms with missing or wrong region information.
+    pub fn with_timing(cartridge: Cartridge, timing: Timing) -> NesEmulator {
+        let cartridge = Rc::new(RefCell::new(cartridge));
+        let mut mmu = Mmu::new(
+            Apu::new(timing),
+            Ppu::new(cartridge.clone(), timing),
+            cartridge,
+        );
         let cpu = Cpu::new(&mut mmu);
 
-        // Creating a new CPU also loads the interrupt vector, which increments
-        // the cycle counter by 2, the ppu needs to catch up
+        // Creating a new CPU runs the 7 cycle reset sequence, the PPU and APU
+        // are clocked along with it through the bus
 
-        NesEmulator { mmu, cpu }
+        NesEmulator {
+            mmu,
+            cpu,
+            timing,
+            frame: 0,
+            lag_frames: 0,
+            input_polls: InputPolls::default(),
+            at_power_on: true,
+            rewind: None,
+            movie: None,
+            mid_frame: false,
+            resume_pc: None,
+            breakpoints: PointList::default(),
+            trace: None,
+            cpu_mode: CpuMode::default(),
+        }
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn cpu_mode(&self) -> CpuMode {
+        self.cpu_mode
+    }
+
+    pub fn set_cpu_mode(&mut self, cpu_mode: CpuMode) {
+        self.cpu_mode = cpu_mode;
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame
+    }
+
+    // CPU cycles since power on, DMA and DMC stalls included
+    pub fn cycles(&self) -> u64 {
+        self.mmu.cycles
+    }
+
+    // Controller reads and strobes during the last frame
+    pub fn input_polls(&self) -> InputPolls {
+        self.input_polls
+    }
+
+    // Whether the CPU ran into a JAM opcode. The rest of the console keeps
+    // running, but only a reset gets the CPU going again.
+    pub fn jammed(&self) -> bool {
+        self.cpu.halted() == Some(Halt::Jam)
+    }
+
+    pub fn lag_frame(&self) -> bool {
+        self.input_polls.is_lag()
+    }
+
+    pub fn lag_count(&self) -> u64 {
+        self.lag_frames
     }
 
     pub fn reset(&mut self) {
-        self.mmu.mapper.borrow_mut().reset();
+        if let Some(MovieMode::Recording { reset, .. }) = &mut self.movie {
+            *reset = true;
+        }
+        self.soft_reset();
+    }
+
+    fn soft_reset(&mut self) {
+        self.at_power_on = false;
+        self.resume_pc = None;
+        self.mmu.cartridge.borrow_mut().reset();
         self.cpu.reset(&mut self.mmu);
         self.mmu.ppu.reset();
+        self.mmu.apu.reset();
     }
 
-    pub fn get_state(&self) -> State {
-        State {
-            ppu_state: self.mmu.ppu.get_state(),
-            screen_mode: self.mmu.mapper.borrow().get_mirroring().clone(),
-            chr_ram: self.mmu.mapper.borrow().rom.chr_ram.clone(),
+    pub fn get_state(&self) -> Result<State> {
+        if self.cpu.mid_instruction() {
+            return Err(StateFileError::MidInstruction.into());
+        }
+        let cartridge = self.mmu.cartridge.borrow();
+        Ok(State {
+            rom_crc32: cartridge.rom.crc32,
+            timing: self.timing,
             cpu_regs: self.cpu.regs,
-            mapper: self.mmu.mapper.borrow().mem_type.clone(),
-            ram: self.mmu.ram.clone(),
+            cpu_interrupts: self.cpu.interrupts,
+            cpu_halted: self.cpu.halted(),
+            cpu_cycles: self.cpu.cycles(),
+            ppu_state: self.mmu.ppu.get_state(),
+            apu: self.mmu.apu.clone(),
+            mmu_state: self.mmu.get_state(),
+            cartridge_state: cartridge.get_state()?,
+            mid_frame: self.mid_frame,
+        })
+    }
+
+    // Fails without changing anything if the state belongs to another rom
+    // or doesn't fit this machine
+    pub fn load_state(&mut self, state: State) -> Result<()> {
+        let rom_crc32 = self.mmu.cartridge.borrow().rom.crc32;
+        if state.rom_crc32 != rom_crc32 {
+            return Err(StateFileError::WrongRom {
+                found: state.rom_crc32,
+                expected: rom_crc32,
+            }
+            .into());
+        }
+        if state.timing != self.timing {
+            return Err(StateFileError::Incompatible(format!(
+                "{:?} timing",
+                state.timing
+            ))
+            .into());
+        }
+
+        let backup = self.get_state()?;
+        if let Err(e) = self.apply_state(state) {
+            self.apply_state(backup)?;
+            return Err(e.into());
         }
+        self.at_power_on = false;
+        self.resume_pc = None;
+        Ok(())
     }
 
-    pub fn load_state(&mut self, state: State) {
-        self.mmu.ppu.set_state(state.ppu_state);
-        self.mmu.mapper.borrow_mut().rom.header.screen = state.screen_mode;
-        self.mmu.mapper.borrow_mut().rom.chr_ram = state.chr_ram;
+    fn apply_state(&mut self, state: State) -> Result<(), StateFileError> {
+        self.mmu
+            .cartridge
+            .borrow_mut()
+            .set_state(state.cartridge_state)?;
+        self.mmu.ppu.set_state(state.ppu_state)?;
+        self.mmu.set_state(state.mmu_state)?;
+        self.mmu.apu.set_state(state.apu);
         self.cpu.regs = state.cpu_regs;
-        self.mmu.mapper.borrow_mut().mem_type = state.mapper;
-        self.mmu.ram = state.ram;
+        self.cpu.interrupts = state.cpu_interrupts;
+        self.cpu.set_halted(state.cpu_halted);
+        self.cpu.set_cycles(state.cpu_cycles);
+        self.mid_frame = state.mid_frame;
+        Ok(())
     }
 
-    pub fn step(&mut self) -> bool {
-        self.cpu.step(&mut self.mmu);
-
-        if self.mmu.ppu.nmi_pending {
-            self.cpu.intr_handler(&mut self.mmu, NMI_VEC);
-            self.mmu.ppu.nmi_pending = false;
-        } else if self.mmu.ppu.queued_nmi {
-            self.cpu.step(&mut self.mmu);
-            self.cpu.intr_handler(&mut self.mmu, NMI_VEC);
-            self.mmu.ppu.nmi_pending = false;
-            self.mmu.ppu.queued_nmi = false;
-        }
+    // Runs an instruction, and the interrupt after it if there is one.
+    // Returns whether the PPU finished a frame. A fault is only reported
+    // by the step it happens in, a frame finished by that step is reported
+    // by the next one.
+    pub fn step(&mut self) -> Result<bool, EmulationError> {
+        self.run_instruction()?;
 
-        if let Some(val) = self.mmu.oam_dma {
-            if self.cpu.dma(val, &mut self.mmu, OAM_DATA) {
-                self.mmu.ppu.emulate_cycles(1);
-            }
-            self.mmu.oam_dma = None;
+        // The CPU polled its interrupt lines during the instruction, run
+        // the interrupt now so the next step starts in the handler
+        if self.cpu.pending_interrupt().is_some() {
+            self.run_instruction()?;
         }
 
         let draw_frame = self.mmu.ppu.frame_ready;
         if draw_frame {
             self.mmu.ppu.frame_ready = false;
-            draw_frame
+            Ok(draw_frame)
         } else {
-            false
+            Ok(false)
         }
     }
 
-    pub fn next_frame(&mut self) -> &[u8] {
-        while !self.step() {}
-        self.mmu.ppu.get_buffer()
+    // Runs a single CPU cycle and returns whether that finished the current
+    // instruction or interrupt. The CPU registers change on the cycle they
+    // do on the chip, and OAM DMA takes over the next read cycle after the
+    // write to $4014, one tick per DMA cycle.
+    pub fn tick(&mut self) -> bool {
+        self.trace_instruction();
+        self.cpu.tick(&mut self.mmu)
+    }
+
+    fn run_instruction(&mut self) -> Result<(), EmulationError> {
+        let halted = self.cpu.halted();
+        match self.cpu_mode {
+            CpuMode::Instruction => {
+                self.trace_instruction();
+                self.cpu.step(&mut self.mmu);
+            }
+            CpuMode::Cycle => while !self.tick() {},
+        }
+
+        // Waiting for an interrupt is business as usual
+        let fault = match (halted, self.cpu.halted()) {
+            (None, Some(Halt::Jam)) => Fault::Jam,
+            (None, Some(Halt::Stop)) => Fault::Stop,
+            _ => return Ok(()),
+        };
+        Err(self.emulation_error(fault))
+    }
+
+    fn emulation_error(&self, fault: Fault) -> EmulationError {
+        EmulationError {
+            fault,
+            regs: self.cpu.regs,
+            scanline: self.mmu.ppu.scanline(),
+            dot: self.mmu.ppu.dot(),
+        }
+    }
+
+    fn trace_instruction(&mut self) {
+        if self.trace.is_some()
+            && !self.cpu.mid_instruction()
+            && self.cpu.pending_interrupt().is_none()
+            && self.cpu.halted().is_none()
+        {
+            self.write_trace();
+        }
+    }
+
+    // Runs until the PPU finishes a frame. A breakpoint, watchpoint or fault
+    // stops it early, calling it again carries on with the rest of the frame.
+    // Returns FrameEnd once the frame is done and in get_pixel_buffer, or the
+    // breakpoint or watchpoint that stopped it.
+    pub fn next_frame(&mut self) -> Result<StopReason, EmulationError> {
+        match self.run(RunCommand::Frame) {
+            StopReason::Fault(fault) => Err(self.emulation_error(fault)),
+            reason => Ok(reason),
+        }
+    }
+
+    // Bookkeeping before the first instruction of a frame
+    fn begin_frame(&mut self) {
+        self.step_movie();
+        if let Some(rewind) = &mut self.rewind {
+            rewind.push_input(
+                self.frame,
+                [self.mmu.ctrl0.buttons(), self.mmu.ctrl1.buttons()],
+            );
+        }
+        self.mmu.apu.clear_samples();
+        self.mmu.ctrl0.clear_polls();
+        self.mmu.ctrl1.clear_polls();
+        self.mid_frame = true;
+    }
+
+    // Bookkeeping once the PPU is done with a frame
+    fn end_frame(&mut self) {
+        self.mid_frame = false;
+        self.frame += 1;
+        self.input_polls = InputPolls {
+            reads: [self.mmu.ctrl0.reads(), self.mmu.ctrl1.reads()],
+            strobes: [self.mmu.ctrl0.strobes(), self.mmu.ctrl1.strobes()],
+        };
+        if self.input_polls.is_lag() {
+            self.lag_frames += 1;
+        }
+        self.at_power_on = false;
+        self.take_snapshot();
+    }
+
+    // Records the buttons of both controllers from the next frame on. The
+    // movie starts at power on if nothing has run yet, otherwise it embeds
+    // the current state.
+    pub fn start_recording(&mut self) -> Result<()> {
+        let start = if self.at_power_on {
+            MovieStart::PowerOn
+        } else {
+            MovieStart::State(Box::new(self.get_state()?))
+        };
+        self.movie = Some(MovieMode::Recording {
+            movie: Movie::new(start, self.timing == Timing::Pal),
+            start_frame: self.frame,
+            reset: false,
+        });
+        Ok(())
+    }
+
+    // Takes over the controllers until the movie runs out. Power on movies
+    // need a freshly loaded rom.
+    pub fn play_movie(&mut self, movie: Movie) -> Result<()> {
+        if movie.pal != (self.timing == Timing::Pal) {
+            let timing = if movie.pal { "PAL" } else { "NTSC" };
+            return Err(MovieError::WrongTiming(timing).into());
+        }
+        match &movie.start {
+            MovieStart::PowerOn if !self.at_power_on => {
+                return Err(MovieError::NotAtPowerOn.into());
+            }
+            MovieStart::PowerOn => (),
+            MovieStart::State(state) => self.load_state(*state.clone())?,
+        }
+        self.movie = Some(MovieMode::Playing {
+            movie,
+            start_frame: self.frame,
+        });
+        Ok(())
+    }
+
+    // Stops recording or playing, returning the movie
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        match self.movie.take()? {
+            MovieMode::Recording { movie, .. } => Some(movie),
+            MovieMode::Playing { movie, .. } => Some(movie),
+        }
+    }
+
+    // True while a movie is playing and has frames left
+    pub fn movie_playing(&self) -> bool {
+        match &self.movie {
+            Some(MovieMode::Playing { movie, start_frame }) => {
+                self.frame >= *start_frame
+                    && ((self.frame - start_frame) as usize)
+                        < movie.frames.len()
+            }
+            _ => false,
+        }
+    }
+
+    // Movie frames are indexed by the frame count, so rewinding while
+    // recording overwrites the frames that got rewound
+    fn step_movie(&mut self) {
+        let frame = self.frame;
+        match &mut self.movie {
+            Some(MovieMode::Recording {
+                movie,
+                start_frame,
+                reset,
+            }) if frame >= *start_frame => {
+                let index = (frame - *start_frame) as usize;
+                if index < movie.frames.len() {
+                    movie.frames.truncate(index);
+                    movie.rerecords += 1;
+                }
+                movie.frames.push(MovieFrame {
+                    ports: [self.mmu.ctrl0.buttons(), self.mmu.ctrl1.buttons()],
+                    reset: std::mem::take(reset),
+                });
+            }
+            Some(MovieMode::Playing { movie, start_frame })
+                if frame >= *start_frame =>
+            {
+                let index = (frame - *start_frame) as usize;
+                if let Some(input) = movie.frames.get(index).copied() {
+                    self.mmu.ctrl0.set_buttons(input.ports[0]);
+                    self.mmu.ctrl1.set_buttons(input.ports[1]);
+                    if input.reset {
+                        self.soft_reset();
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Starts keeping a snapshot every interval frames, up to capacity
+    // snapshots, for rewind_frame
+    pub fn enable_rewind(&mut self, interval: u64, capacity: usize) {
+        self.rewind = Some(Rewind::new(interval, capacity));
+        self.take_snapshot();
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    fn take_snapshot(&mut self) {
+        let frame = self.frame;
+        if !self
+            .rewind
+            .as_ref()
+            .is_some_and(|r| r.wants_snapshot(frame))
+        {
+            return;
+        }
+        match self.get_state().and_then(|state| Ok(state.to_bytes()?)) {
+            Ok(data) => {
+                if let Some(rewind) = &mut self.rewind {
+                    rewind.push(frame, data);
+                }
+            }
+            Err(e) => log::warn!("Failed to take a rewind snapshot: {}", e),
+        }
+    }
+
+    // Goes back one frame, returns false once there's no history left. The
+    // frame is recreated from the snapshot before it by replaying the
+    // buttons that were pressed at the time.
+    pub fn rewind_frame(&mut self) -> Result<bool> {
+        let target = match self.frame.checked_sub(1) {
+            Some(target) => target,
+            None => return Ok(false),
+        };
+        let seek = match self.rewind.as_mut().and_then(|r| r.seek(target)) {
+            Some(seek) => seek,
+            None => return Ok(false),
+        };
+
+        let rom_crc32 = self.mmu.cartridge.borrow().rom.crc32;
+        self.load_state(State::from_bytes(&seek.data, rom_crc32)?)?;
+        self.frame = seek.frame;
+        for [ctrl0, ctrl1] in seek.inputs {
+            self.mmu.ctrl0.set_buttons(ctrl0);
+            self.mmu.ctrl1.set_buttons(ctrl1);
+            self.replay_frame();
+        }
+        Ok(true)
+    }
+
+    // Runs a frame that already happened, the debugger shouldn't stop it
+    fn replay_frame(&mut self) {
+        let breakpoints = std::mem::take(&mut self.breakpoints);
+        let watchpoints = std::mem::take(&mut self.mmu.watchpoints);
+        // Any fault got reported the first time around
+        while let StopReason::Fault(_) = self.run(RunCommand::Frame) {}
+        self.breakpoints = breakpoints;
+        self.mmu.watchpoints = watchpoints;
     }
 
     pub fn cur_frame(&self) -> &[u8] {
@@ -119,6 +539,17 @@ impl NesEmulator {
         self.mmu.ppu.get_buffer()
     }
 
+    // Mono PCM in the range -1.0 to 1.0, produced while emulating the last
+    // frame. A frame is roughly 1/60th of a second of audio, but the exact
+    // number of samples varies from frame to frame.
+    pub fn get_audio_buffer(&self) -> &[f32] {
+        self.mmu.apu.samples()
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.mmu.apu.set_sample_rate(sample_rate);
+    }
+
     pub fn set_button(
         &mut self,
         button: crate::controller::Button,
@@ -130,7 +561,7 @@ impl NesEmulator {
                 self.mmu.ctrl0.set_button_state(button, state)
             }
             PlayerController::Two => {
-                self.mmu.ctrl0.set_button_state(button, state)
+                self.mmu.ctrl1.set_button_state(button, state)
             }
         }
     }
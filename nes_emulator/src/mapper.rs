@@ -4,10 +4,15 @@ use crate::mapper::nrom::*;
 use crate::mapper::sxrom::*;
 use crate::mapper::txrom::*;
 use crate::mapper::unrom::*;
+use crate::rom::LoadRomError;
 use crate::rom::Rom;
+use crate::rom::ScreenBank;
 use crate::rom::ScreenMode;
+use crate::state::StateFileError;
 use serde::Deserialize;
 use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
 
 pub mod axrom;
 pub mod cnrom;
@@ -16,137 +21,305 @@ pub mod sxrom;
 pub mod txrom;
 pub mod unrom;
 
-pub struct Mapper {
-    pub mem_type: MemType,
-    pub rom: Rom,
+// The logic a cartridge board adds on top of its ROM and RAM chips. The
+// memory itself lives in the Rom so boards only have to keep track of their
+// registers.
+pub trait Mapper {
+    fn ld_prg(&self, rom: &Rom, addr: u16) -> u8;
+
+    fn store_prg(&mut self, rom: &mut Rom, addr: u16, val: u8);
+
+    fn ld_chr(&self, rom: &Rom, addr: u16) -> u8;
+
+    fn store_chr(&mut self, rom: &mut Rom, addr: u16, val: u8);
+
+    // Boards without mirroring control are hard wired by the header
+    fn mirroring(&self, rom: &Rom) -> ScreenMode {
+        rom.header.screen
+    }
+
+    // Where a nametable address ($000-$FFF) ends up. Boards with their own
+    // nametable memory, for example in CHR ROM or mapper controlled RAM, can
+    // override this along with ld_nt and store_nt.
+    fn nametable(&self, rom: &Rom, addr: u16) -> Nametable {
+        mirror_nametable(self.mirroring(rom), addr)
+    }
+
+    // Nametable accesses resolved to Nametable::Cartridge. By default these
+    // go to the four screen RAM, which backs the third and fourth nametables.
+    fn ld_nt(&self, rom: &Rom, addr: u16) -> u8 {
+        rom.nt_ram[addr as usize & 0x7FF]
+    }
+
+    fn store_nt(&mut self, rom: &mut Rom, addr: u16, val: u8) {
+        rom.nt_ram[addr as usize & 0x7FF] = val;
+    }
+
+    fn reset(&mut self) {}
+
+    // The level of the board's contribution to the CPU's IRQ line
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // Called with every address the PPU puts on its bus
+    fn ppu_addr(&mut self, _addr: u16) {}
+
+    // Called once per CPU cycle (M2)
+    fn cpu_clock(&mut self) {}
+
+    // Board registers for save states, see encode_board and decode_board
+    fn save_state(&self) -> Result<Vec<u8>, StateFileError>;
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateFileError>;
 }
 
-#[derive(Clone, Serialize, Deserialize)]
-pub enum MemType {
-    Nrom(Nrom),
-    Sxrom(Sxrom),
-    Unrom(Unrom),
-    Axrom(Axrom),
-    Txrom(Txrom),
-    Cnrom(Cnrom),
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nametable {
+    // An index into the console's 2KB of nametable RAM
+    Ciram(usize),
+    // Handled by the board through Mapper::ld_nt and Mapper::store_nt
+    Cartridge,
 }
 
-impl Mapper {
-    pub fn from_rom(mut rom: Rom) -> Mapper {
-        let mem_type = match rom.header.mapper {
-            0 => {
-                let use_chr_ram = !rom.chr_ram.is_empty();
-                MemType::Nrom(Nrom::new(rom.prg_rom.len(), use_chr_ram))
-            }
-            1 => {
-                rom.fill_prg_ram();
-                let use_chr_ram = !rom.chr_ram.is_empty();
-                let last_page_start = rom.prg_rom.len() - 0x4000;
-                MemType::Sxrom(Sxrom::new(use_chr_ram, last_page_start))
-            }
-            2 => {
-                let last_page_start = rom.prg_rom.len() - 0x4000;
-                MemType::Unrom(Unrom::new(last_page_start))
-            }
-            3 => {
-                let use_chr_ram = !rom.chr_ram.is_empty();
-                MemType::Cnrom(Cnrom::new(rom.prg_rom.len(), use_chr_ram))
-            }
-            4 => {
-                let last_page_start = rom.prg_rom.len() - 0x4000;
-                let use_chr_ram = !rom.chr_ram.is_empty();
-                MemType::Txrom(Txrom::new(use_chr_ram, last_page_start))
-            }
-            7 => {
-                let last_page_start = rom.prg_rom.len() - 0x8000;
-                MemType::Axrom(Axrom::new(last_page_start))
+const NT_0_END: u16 = 0x3FF;
+const NT_1: u16 = 0x400;
+const NT_1_END: u16 = 0x7FF;
+const NT_2: u16 = 0x800;
+const NT_2_END: u16 = 0xBFF;
+const NT_3: u16 = 0xC00;
+const NT_3_END: u16 = 0xFFF;
+
+// Resolves the nametable mirroring for boards without their own nametable
+// logic. Four screen boards only wire the first two nametables to the
+// console's RAM.
+pub fn mirror_nametable(mode: ScreenMode, addr: u16) -> Nametable {
+    let addr = addr & 0xFFF;
+    match mode {
+        ScreenMode::Horizontal => match addr {
+            0..=NT_0_END => Nametable::Ciram(addr as usize),
+            NT_1..=NT_2_END => Nametable::Ciram((addr - 0x400) as usize),
+            NT_3..=NT_3_END => Nametable::Ciram((addr - 0x800) as usize),
+            _ => unreachable!(),
+        },
+        ScreenMode::Vertical => Nametable::Ciram((addr & 0x7FF) as usize),
+        ScreenMode::OneScreenSwap(bank) => {
+            let addr = (addr & 0x3FF) as usize;
+            match bank {
+                ScreenBank::Lower => Nametable::Ciram(addr),
+                ScreenBank::Upper => Nametable::Ciram(addr + 0x400),
             }
-            m => panic!("Mapper {} not supported", m),
-        };
-        Mapper { rom, mem_type }
+        }
+        ScreenMode::FourScreen => match addr {
+            0..=NT_1_END => Nametable::Ciram(addr as usize),
+            NT_2..=NT_3_END => Nametable::Cartridge,
+            _ => unreachable!(),
+        },
     }
+}
 
-    pub fn ld_prg(&self, addr: u16) -> u8 {
-        match self.mem_type {
-            MemType::Nrom(ref nrom) => nrom.ld_prg(addr, &self.rom.prg_rom),
-            MemType::Unrom(ref unrom) => unrom.ld_prg(addr, &self.rom.prg_rom),
-            MemType::Sxrom(ref sxrom) => {
-                sxrom.ld_prg(addr, &self.rom.prg_rom, &self.rom.prg_ram)
-            }
-            MemType::Axrom(ref axrom) => axrom.ld_prg(addr, &self.rom.prg_rom),
-            MemType::Txrom(ref _txrom) => panic!("Txrom not ready yet"),
-            MemType::Cnrom(ref cnrom) => cnrom.ld_prg(addr, &self.rom.prg_rom),
+pub fn encode_board<T: Serialize>(
+    board: &T,
+) -> Result<Vec<u8>, StateFileError> {
+    bincode::serde::encode_to_vec(board, bincode::config::standard())
+        .map_err(StateFileError::SaveState)
+}
+
+pub fn decode_board<T: DeserializeOwned>(
+    data: &[u8],
+) -> Result<T, StateFileError> {
+    match bincode::serde::decode_from_slice(data, bincode::config::standard()) {
+        Ok((board, _)) => Ok(board),
+        Err(e) => Err(StateFileError::LoadState(e)),
+    }
+}
+
+// Builds a board for a rom. It can also set up the rom's memory, for example
+// allocating PRG RAM.
+pub type MapperConstructor = fn(&mut Rom) -> Box<dyn Mapper>;
+
+// Maps iNES/NES 2.0 mapper numbers to boards. Boards registered without a
+// submapper are used for any submapper that doesn't have its own entry.
+pub struct MapperRegistry {
+    boards: HashMap<(u16, Option<u8>), MapperConstructor>,
+}
+
+impl Default for MapperRegistry {
+    fn default() -> MapperRegistry {
+        let mut registry = MapperRegistry::empty();
+        registry.register(0, None, |rom| {
+            rom.fill_prg_ram();
+            let use_chr_ram = !rom.chr_ram.is_empty();
+            Box::new(Nrom::new(rom.prg_rom.len(), use_chr_ram))
+        });
+        registry.register(1, None, |rom| {
+            rom.fill_prg_ram();
+            let use_chr_ram = !rom.chr_ram.is_empty();
+            let last_page_start = rom.prg_rom.len() - 0x4000;
+            Box::new(Sxrom::new(use_chr_ram, last_page_start))
+        });
+        registry.register(2, None, |rom| {
+            let last_page_start = rom.prg_rom.len() - 0x4000;
+            Box::new(Unrom::new(last_page_start))
+        });
+        registry.register(3, None, |rom| {
+            let use_chr_ram = !rom.chr_ram.is_empty();
+            Box::new(Cnrom::new(rom.prg_rom.len(), use_chr_ram))
+        });
+        registry.register(4, None, |rom| {
+            rom.fill_prg_ram();
+            let last_page_start = rom.prg_rom.len() - 0x4000;
+            let use_chr_ram = !rom.chr_ram.is_empty();
+            Box::new(Txrom::new(use_chr_ram, last_page_start))
+        });
+        registry.register(7, None, |_| Box::new(Axrom::default()));
+        registry
+    }
+}
+
+impl MapperRegistry {
+    pub fn empty() -> MapperRegistry {
+        MapperRegistry {
+            boards: HashMap::new(),
         }
     }
 
-    pub fn ld_chr(&self, addr: u16) -> u8 {
-        match self.mem_type {
-            MemType::Nrom(ref nrom) => {
-                nrom.ld_chr(addr, &self.rom.chr_rom, &self.rom.chr_ram)
-            }
-            MemType::Unrom(ref unrom) => unrom.ld_chr(addr, &self.rom.chr_ram),
-            MemType::Sxrom(ref sxrom) => {
-                sxrom.ld_chr(addr, &self.rom.chr_rom, &self.rom.chr_ram)
-            }
-            MemType::Axrom(ref axrom) => axrom.ld_chr(addr, &self.rom.chr_ram),
-            MemType::Txrom(ref _txrom) => panic!("Txrom not ready yet"),
-            MemType::Cnrom(ref cnrom) => {
-                cnrom.ld_chr(addr, &self.rom.chr_rom, &self.rom.chr_ram)
-            }
+    // Replaces any board already registered under the same numbers
+    pub fn register(
+        &mut self,
+        mapper: u16,
+        submapper: Option<u8>,
+        constructor: MapperConstructor,
+    ) {
+        self.boards.insert((mapper, submapper), constructor);
+    }
+
+    pub fn create(
+        &self,
+        rom: &mut Rom,
+    ) -> Result<Box<dyn Mapper>, LoadRomError> {
+        let mapper = rom.header.mapper;
+        let submapper = rom.header.submapper;
+        let constructor = self
+            .boards
+            .get(&(mapper, Some(submapper)))
+            .or_else(|| self.boards.get(&(mapper, None)))
+            .ok_or(LoadRomError::UnsupportedMapper(mapper, submapper))?;
+        Ok(constructor(rom))
+    }
+}
+
+// The board's registers plus the cartridge memory that can change
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CartridgeState {
+    #[serde(with = "serde_bytes")]
+    prg_ram: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    chr_ram: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    nt_ram: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    board: Vec<u8>,
+}
+
+pub struct Cartridge {
+    pub rom: Rom,
+    board: Box<dyn Mapper>,
+}
+
+impl Cartridge {
+    pub fn from_rom(rom: Rom) -> Result<Cartridge, LoadRomError> {
+        Cartridge::with_registry(rom, &MapperRegistry::default())
+    }
+
+    pub fn with_registry(
+        mut rom: Rom,
+        registry: &MapperRegistry,
+    ) -> Result<Cartridge, LoadRomError> {
+        // Every board banks PRG ROM in multiples of 16KB
+        if rom.prg_rom.is_empty() || !rom.prg_rom.len().is_multiple_of(0x4000) {
+            return Err(LoadRomError::Unsupported(format!(
+                "{} bytes of PRG ROM",
+                rom.prg_rom.len()
+            )));
         }
+        let board = registry.create(&mut rom)?;
+        Ok(Cartridge { rom, board })
+    }
+
+    pub fn ld_prg(&self, addr: u16) -> u8 {
+        self.board.ld_prg(&self.rom, addr)
+    }
+
+    pub fn ld_chr(&self, addr: u16) -> u8 {
+        self.board.ld_chr(&self.rom, addr)
     }
 
     pub fn store_prg(&mut self, addr: u16, val: u8) {
-        match self.mem_type {
-            MemType::Unrom(ref mut unrom) => unrom.store_prg(addr, val),
-            MemType::Sxrom(ref mut sxrom) => {
-                sxrom.store_prg(addr, val, &mut self.rom.prg_ram)
-            }
-            MemType::Nrom(ref nrom) => nrom.store_prg(addr, val),
-            MemType::Axrom(ref mut axrom) => axrom.store_prg(addr, val),
-            MemType::Txrom(ref _txrom) => panic!("Txrom not ready yet"),
-            MemType::Cnrom(ref mut cnrom) => cnrom.store_prg(addr, val),
-        }
+        self.board.store_prg(&mut self.rom, addr, val)
     }
 
     pub fn store_chr(&mut self, addr: u16, val: u8) {
-        match self.mem_type {
-            MemType::Unrom(ref mut unrom) => {
-                unrom.store_chr(addr, val, &mut self.rom.chr_ram)
-            }
-            MemType::Sxrom(ref mut sxrom) => {
-                sxrom.store_chr(addr, val, &mut self.rom.chr_ram)
-            }
-            MemType::Nrom(ref mut nrom) => {
-                nrom.store_chr(addr, val, &mut self.rom.chr_ram)
-            }
-            MemType::Axrom(ref mut axrom) => {
-                axrom.store_chr(addr, val, &mut self.rom.chr_ram)
-            }
-            MemType::Txrom(ref _txrom) => panic!("Txrom not ready yet"),
-            MemType::Cnrom(ref mut cnrom) => cnrom.store_prg(addr, val),
-        }
+        self.board.store_chr(&mut self.rom, addr, val)
     }
 
-    pub fn get_mirroring(&self) -> &ScreenMode {
-        match self.mem_type {
-            MemType::Unrom(_) | MemType::Nrom(_) | MemType::Cnrom(_) => {
-                &self.rom.header.screen
-            }
-            MemType::Sxrom(ref sxrom) => sxrom.get_mirroring(),
-            MemType::Axrom(ref axrom) => axrom.get_mirroring(),
-            MemType::Txrom(ref txrom) => txrom.get_mirroring(),
-        }
+    pub fn get_mirroring(&self) -> ScreenMode {
+        self.board.mirroring(&self.rom)
+    }
+
+    pub fn nametable(&self, addr: u16) -> Nametable {
+        self.board.nametable(&self.rom, addr)
+    }
+
+    pub fn ld_nt(&self, addr: u16) -> u8 {
+        self.board.ld_nt(&self.rom, addr)
+    }
+
+    pub fn store_nt(&mut self, addr: u16, val: u8) {
+        self.board.store_nt(&mut self.rom, addr, val)
     }
 
     pub fn reset(&mut self) {
-        match self.mem_type {
-            MemType::Nrom(_) => (),
-            MemType::Unrom(ref mut unrom) => unrom.reset(),
-            MemType::Sxrom(ref mut sxrom) => sxrom.reset(),
-            MemType::Axrom(ref mut axrom) => axrom.reset(),
-            MemType::Cnrom(ref mut cnrom) => cnrom.reset(),
-            MemType::Txrom(ref mut _txrom) => panic!("Txrom not ready yet"),
+        self.board.reset()
+    }
+
+    pub fn ppu_addr(&mut self, addr: u16) {
+        self.board.ppu_addr(addr)
+    }
+
+    pub fn cpu_clock(&mut self) {
+        self.board.cpu_clock()
+    }
+
+    pub fn irq(&self) -> bool {
+        self.board.irq()
+    }
+
+    pub fn get_state(&self) -> Result<CartridgeState, StateFileError> {
+        Ok(CartridgeState {
+            prg_ram: self.rom.prg_ram.clone(),
+            chr_ram: self.rom.chr_ram.clone(),
+            nt_ram: self.rom.nt_ram.clone(),
+            board: self.board.save_state()?,
+        })
+    }
+
+    pub fn set_state(
+        &mut self,
+        state: CartridgeState,
+    ) -> Result<(), StateFileError> {
+        if state.prg_ram.len() != self.rom.prg_ram.len()
+            || state.chr_ram.len() != self.rom.chr_ram.len()
+            || state.nt_ram.len() != self.rom.nt_ram.len()
+        {
+            return Err(StateFileError::Incompatible(
+                "cartridge RAM size".to_string(),
+            ));
         }
+        self.board.load_state(&state.board)?;
+        self.rom.prg_ram = state.prg_ram;
+        self.rom.chr_ram = state.chr_ram;
+        self.rom.nt_ram = state.nt_ram;
+        Ok(())
     }
 }
@@ -2,31 +2,27 @@
 // but then hang indefinitely. Almost certainly timing issues, as this mapper is
 // very simple. Marble Madness works fine.
 
+use crate::mapper::Mapper;
+use crate::mapper::decode_board;
+use crate::mapper::encode_board;
+use crate::rom::Rom;
 use crate::rom::ScreenBank;
 use crate::rom::ScreenMode;
+use crate::state::StateFileError;
 use log::*;
 use serde::Deserialize;
 use serde::Serialize;
 
 const THIRTY_TWO_KB: usize = 0x8000;
 
-#[derive(Serialize, Deserialize, Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
 pub struct Axrom {
     bank_select: u8,
     mirror_select: u8,
-    last_page_start: usize,
 }
 
-impl Axrom {
-    pub fn new(last_page_start: usize) -> Axrom {
-        Axrom {
-            bank_select: 0,
-            mirror_select: 0,
-            last_page_start,
-        }
-    }
-
-    pub fn store_prg(&mut self, address: u16, val: u8) {
+impl Mapper for Axrom {
+    fn store_prg(&mut self, _rom: &mut Rom, address: u16, val: u8) {
         if address >= 0x8000 {
             self.bank_select = val & 0b111;
             self.mirror_select = (val >> 4) & 1;
@@ -38,34 +34,45 @@ impl Axrom {
         }
     }
 
-    pub fn ld_prg(&self, address: u16, prg_rom: &[u8]) -> u8 {
+    fn ld_prg(&self, rom: &Rom, address: u16) -> u8 {
         if address < 0x8000 {
             info!("Reading from unmapped prg_rom address: {:X}", address);
             0
-        // Bank switched using 3 bits
+        // Bank switched using 3 bits. Smaller ROMs don't decode all of
+        // them, and a 16KB one shows up in both halves.
         } else {
-            prg_rom[(self.bank_select as usize * THIRTY_TWO_KB)
-                + (address as usize - 0x8000)]
+            let addr = self.bank_select as usize * THIRTY_TWO_KB
+                + (address as usize - 0x8000);
+            rom.prg_rom[addr % rom.prg_rom.len()]
         }
     }
 
-    pub fn ld_chr(&self, address: u16, chr_ram: &[u8]) -> u8 {
-        chr_ram[address as usize]
+    fn ld_chr(&self, rom: &Rom, address: u16) -> u8 {
+        rom.chr_ram[address as usize]
     }
 
-    pub fn store_chr(&mut self, address: u16, val: u8, chr_ram: &mut Vec<u8>) {
-        chr_ram[address as usize] = val;
+    fn store_chr(&mut self, rom: &mut Rom, address: u16, val: u8) {
+        rom.chr_ram[address as usize] = val;
     }
 
-    pub fn reset(&mut self) {
+    fn reset(&mut self) {
         self.bank_select = 0;
     }
 
-    pub fn get_mirroring(&self) -> &ScreenMode {
-        match self.mirror_select {
-            0 => &ScreenMode::OneScreenSwap(ScreenBank::Lower),
-            1 => &ScreenMode::OneScreenSwap(ScreenBank::Upper),
-            _ => panic!("2 bit number can't be greater than 1"),
+    fn mirroring(&self, _rom: &Rom) -> ScreenMode {
+        if self.mirror_select == 0 {
+            ScreenMode::OneScreenSwap(ScreenBank::Lower)
+        } else {
+            ScreenMode::OneScreenSwap(ScreenBank::Upper)
         }
     }
+
+    fn save_state(&self) -> Result<Vec<u8>, StateFileError> {
+        encode_board(self)
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateFileError> {
+        *self = decode_board(data)?;
+        Ok(())
+    }
 }
@@ -1,6 +1,11 @@
+use crate::mapper::Mapper;
+use crate::mapper::decode_board;
+use crate::mapper::encode_board;
+use crate::rom::Rom;
+use crate::state::StateFileError;
+use log::*;
 use serde::Deserialize;
 use serde::Serialize;
-use log::*;
 
 const UNMIRRORED_MASK: usize = 0x7FFF;
 const MIRRORED_MASK: usize = 0x3FFF;
@@ -22,19 +27,21 @@ impl Cnrom {
             chr_rom_offset: 0,
         }
     }
+}
 
-    pub fn ld_prg(&self, address: u16, prg_rom: &[u8]) -> u8 {
+impl Mapper for Cnrom {
+    fn ld_prg(&self, rom: &Rom, address: u16) -> u8 {
         if address < 0x8000 {
             info!("Attempt to read from cnrom {:X}", address);
             0
         } else if self.mirrored {
-            prg_rom[address as usize & MIRRORED_MASK]
+            rom.prg_rom[address as usize & MIRRORED_MASK]
         } else {
-            prg_rom[address as usize & UNMIRRORED_MASK]
+            rom.prg_rom[address as usize & UNMIRRORED_MASK]
         }
     }
 
-    pub fn store_prg(&mut self, address: u16, val: u8) {
+    fn store_prg(&mut self, _rom: &mut Rom, address: u16, val: u8) {
         if address < 0x8000 {
             info!(
                 "Attempted to write to addr {:X} with val {:X}",
@@ -46,17 +53,17 @@ impl Cnrom {
         }
     }
 
-    pub fn ld_chr(&self, address: u16, chr_rom: &[u8], chr_ram: &[u8]) -> u8 {
+    fn ld_chr(&self, rom: &Rom, address: u16) -> u8 {
         if self.use_chr_ram {
-            chr_ram[address as usize]
+            rom.chr_ram[address as usize]
         } else {
-            chr_rom[self.chr_rom_offset + address as usize]
+            rom.chr_rom[self.chr_rom_offset + address as usize]
         }
     }
 
-    pub fn store_chr(&mut self, address: u16, val: u8, chr_ram: &mut Vec<u8>) {
+    fn store_chr(&mut self, rom: &mut Rom, address: u16, val: u8) {
         if self.use_chr_ram {
-            chr_ram[address as usize] = val;
+            rom.chr_ram[address as usize] = val;
         } else {
             info!(
                 "Attempt to store to cnrom address {:X} val {}",
@@ -65,7 +72,16 @@ impl Cnrom {
         }
     }
 
-    pub fn reset(&mut self) {
+    fn reset(&mut self) {
         self.chr_rom_offset = 0;
     }
+
+    fn save_state(&self) -> Result<Vec<u8>, StateFileError> {
+        encode_board(self)
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateFileError> {
+        *self = decode_board(data)?;
+        Ok(())
+    }
 }
@@ -1,3 +1,8 @@
+use crate::mapper::Mapper;
+use crate::mapper::decode_board;
+use crate::mapper::encode_board;
+use crate::rom::Rom;
+use crate::state::StateFileError;
 use log::*;
 use serde::Deserialize;
 use serde::Serialize;
@@ -21,38 +26,57 @@ impl Nrom {
             use_chr_ram,
         }
     }
+}
 
-    pub fn ld_prg(&self, address: u16, prg_rom: &[u8]) -> u8 {
-        if address < NROM_PRG_ROM_START {
+impl Mapper for Nrom {
+    fn ld_prg(&self, rom: &Rom, address: u16) -> u8 {
+        if (0x6000..NROM_PRG_ROM_START).contains(&address) {
+            rom.prg_ram[address as usize - 0x6000]
+        } else if address < NROM_PRG_ROM_START {
             info!("Attempt to read from nrom {:X}", address);
             0
         } else if self.mirrored {
-            prg_rom[address as usize & MIRRORED_MASK]
+            rom.prg_rom[address as usize & MIRRORED_MASK]
         } else {
-            prg_rom[address as usize & UNMIRRORED_MASK]
+            rom.prg_rom[address as usize & UNMIRRORED_MASK]
         }
     }
 
-    pub fn store_prg(&self, address: u16, val: u8) {
-        info!(
-            "Attempt to write to nrom address {:X}, val {}",
-            address, val
-        );
+    // Boards like Family Basic put 8KB of PRG RAM at 0x6000. Test roms rely
+    // on it to report their results, so it is always present.
+    fn store_prg(&mut self, rom: &mut Rom, address: u16, val: u8) {
+        if (0x6000..NROM_PRG_ROM_START).contains(&address) {
+            rom.prg_ram[address as usize - 0x6000] = val;
+        } else {
+            info!(
+                "Attempt to write to nrom address {:X}, val {}",
+                address, val
+            );
+        }
     }
 
-    pub fn ld_chr(&self, address: u16, chr_rom: &[u8], chr_ram: &[u8]) -> u8 {
+    fn ld_chr(&self, rom: &Rom, address: u16) -> u8 {
         if self.use_chr_ram {
-            chr_ram[address as usize]
+            rom.chr_ram[address as usize]
         } else {
-            chr_rom[address as usize]
+            rom.chr_rom[address as usize]
         }
     }
 
-    pub fn store_chr(&mut self, address: u16, val: u8, chr_ram: &mut Vec<u8>) {
+    fn store_chr(&mut self, rom: &mut Rom, address: u16, val: u8) {
         if self.use_chr_ram {
-            chr_ram[address as usize] = val;
+            rom.chr_ram[address as usize] = val;
         } else {
             info!("Attempt to store to nrom address {:X} val {}", address, val);
         }
     }
+
+    fn save_state(&self) -> Result<Vec<u8>, StateFileError> {
+        encode_board(self)
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateFileError> {
+        *self = decode_board(data)?;
+        Ok(())
+    }
 }
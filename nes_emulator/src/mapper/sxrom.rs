@@ -5,8 +5,13 @@
 // This could be due to timing issues/accuracy issues in other emulator
 // components but it's hard to tell
 
+use crate::mapper::Mapper;
+use crate::mapper::decode_board;
+use crate::mapper::encode_board;
+use crate::rom::Rom;
 use crate::rom::ScreenBank;
 use crate::rom::ScreenMode;
+use crate::state::StateFileError;
 use bitfield::bitfield;
 use log::*;
 use serde::Deserialize;
@@ -75,11 +80,43 @@ impl Sxrom {
         }
     }
 
-    pub fn store_prg(&mut self, address: u16, val: u8, prg_ram: &mut Vec<u8>) {
+    // Banks past the end of the CHR memory wrap around, the board just
+    // doesn't connect the extra address lines
+    fn get_chr_index(&self, addr: u16, chr_len: usize) -> usize {
+        let addr = addr as usize & 0x1FFF;
+        let index = if !self.ctrl.chr_rom_mode() {
+            // & with !0x1000 to ignore low bit in 8KB mode
+            (self.chr_bank_0_offset & !0x1000) + addr
+        } else if addr < 0x1000 {
+            self.chr_bank_0_offset + addr
+        } else {
+            self.chr_bank_1_offset + (addr - 0x1000)
+        };
+        index % chr_len
+    }
+
+    fn get_prg_index(&self, addr: u16, prg_len: usize) -> usize {
+        let addr = addr as usize & 0x7FFF;
+        let index = match (self.ctrl.prg_rom_mode(), addr) {
+            // Normally this code shifts right by 1, then left by 14, ANDing
+            // the base by !0x2000 pulls out the bit that would be set to 0 if
+            // we shifted by 1 first
+            (0 | 1, _) => ((self.prg_bank_offset & !0x2000) >> 1) + addr,
+            (2, 0x0000..=0x3FFF) => addr,
+            (2, _) => self.prg_bank_offset + (addr - 0x4000),
+            (_, 0x0000..=0x3FFF) => self.prg_bank_offset + addr,
+            (_, _) => self.last_page_start + addr - 0x4000,
+        };
+        index % prg_len
+    }
+}
+
+impl Mapper for Sxrom {
+    fn store_prg(&mut self, rom: &mut Rom, address: u16, val: u8) {
         if address < 0x6000 {
             info!("Storing to unmapped prg mem {:X}", address);
         } else if address < 0x8000 {
-            prg_ram[address as usize - 0x6000] = val;
+            rom.prg_ram[address as usize - 0x6000] = val;
         } else if (val & 0x80) != 0 {
             self.reset();
             self.ctrl = Ctrl(self.ctrl.as_byte() | 0x0C);
@@ -96,22 +133,24 @@ impl Sxrom {
                     let chr_bank_1 = (val & 0b11111) as usize % 8;
                     self.chr_bank_1_offset = chr_bank_1 << 12;
                 }
-                0xE000..=0xFFFF => {
+                // $E000-$FFFF
+                _ => {
                     let prg_bank_page = (val & 0b1111) as usize;
                     // Equivalent to multiplying by 0x4000 which is
                     // the page size
                     self.prg_bank_offset = prg_bank_page << 14;
                     self.prg_ram_enabled = (val & 0b10000) == 0;
                 }
-                _ => panic!("Impossible to get here"),
             }
         }
     }
 
-    pub fn ld_prg(&self, address: u16, prg_rom: &[u8], prg_ram: &[u8]) -> u8 {
+    fn ld_prg(&self, rom: &Rom, address: u16) -> u8 {
         match address {
-            0x6000..=0x7FFF => prg_ram[address as usize - 0x6000],
-            0x8000..=0xFFFF => prg_rom[self.get_prg_index(address)],
+            0x6000..=0x7FFF => rom.prg_ram[address as usize - 0x6000],
+            0x8000..=0xFFFF => {
+                rom.prg_rom[self.get_prg_index(address, rom.prg_rom.len())]
+            }
             addr => {
                 info!("Reading from unmapped memory {:X}", addr);
                 0
@@ -119,67 +158,24 @@ impl Sxrom {
         }
     }
 
-    pub fn ld_chr(&self, address: u16, chr_rom: &[u8], chr_ram: &[u8]) -> u8 {
+    fn ld_chr(&self, rom: &Rom, address: u16) -> u8 {
         if self.use_chr_ram {
-            chr_ram[self.get_chr_index(address)]
+            rom.chr_ram[self.get_chr_index(address, rom.chr_ram.len())]
         } else {
-            chr_rom[self.get_chr_index(address)]
+            rom.chr_rom[self.get_chr_index(address, rom.chr_rom.len())]
         }
     }
 
-    pub fn store_chr(&mut self, address: u16, val: u8, chr_ram: &mut Vec<u8>) {
+    fn store_chr(&mut self, rom: &mut Rom, address: u16, val: u8) {
         if self.use_chr_ram {
-            chr_ram[self.get_chr_index(address)] = val;
+            let index = self.get_chr_index(address, rom.chr_ram.len());
+            rom.chr_ram[index] = val;
         } else {
             info!("Attempting to write to chr rom {:X}", address);
         }
     }
 
-    fn get_chr_index(&self, addr: u16) -> usize {
-        match self.ctrl.chr_rom_mode() as u8 {
-            // & with !0x1000 to ignore low bit in 8KB mode
-            0 => self.chr_bank_0_offset & !0x1000 + addr as usize,
-            1 => match addr {
-                0x0000..=0x0FFF => (self.chr_bank_0_offset) + addr as usize,
-                0x1000..=0x1FFF => {
-                    (self.chr_bank_1_offset) + (addr as usize - 0x1000)
-                }
-                c => panic!("Chr indices are only 0000-1FFFF {:X}", c),
-            },
-            _ => panic!("only one bit used here"),
-        }
-    }
-
-    fn get_prg_index(&self, addr: u16) -> usize {
-        match self.ctrl.prg_rom_mode() {
-            // Normally this code shifts right by 1, then left by 14, ANDing
-            // the base by !0x2000 pulls out the bit that would be set to 0 if
-            // we shifted by 1 first
-            0 | 1 => {
-                ((self.prg_bank_offset & !0x2000) >> 1)
-                    + (addr as usize - 0x8000)
-            }
-            2 => match addr {
-                0x8000..=0xBFFF => addr as usize - 0x8000,
-                0xC000..=0xFFFF => {
-                    self.prg_bank_offset + (addr as usize - 0xC000)
-                }
-                _ => panic!("addr can't be anything else"),
-            },
-            3 => match addr {
-                0x8000..=0xBFFF => {
-                    self.prg_bank_offset + (addr as usize - 0x8000)
-                }
-                0xC000..=0xFFFF => {
-                    self.last_page_start + addr as usize - 0xC000
-                }
-                a => panic!("addr can't be anything else {:X}", a),
-            },
-            b => panic!("Can't get anything else {:b}", b),
-        }
-    }
-
-    pub fn reset(&mut self) {
+    fn reset(&mut self) {
         self.shift.reset();
         self.ctrl = Ctrl(0x0C);
         self.chr_bank_0_offset = 0;
@@ -188,13 +184,21 @@ impl Sxrom {
         self.prg_ram_enabled = true;
     }
 
-    pub fn get_mirroring(&self) -> &ScreenMode {
+    fn mirroring(&self, _rom: &Rom) -> ScreenMode {
         match self.ctrl.mirroring() {
-            0 => &ScreenMode::OneScreenSwap(ScreenBank::Upper),
-            1 => &ScreenMode::OneScreenSwap(ScreenBank::Lower),
-            2 => &ScreenMode::Vertical,
-            3 => &ScreenMode::Horizontal,
-            _ => panic!("2 bit number can't be greater than 3"),
+            0 => ScreenMode::OneScreenSwap(ScreenBank::Upper),
+            1 => ScreenMode::OneScreenSwap(ScreenBank::Lower),
+            2 => ScreenMode::Vertical,
+            _ => ScreenMode::Horizontal,
         }
     }
+
+    fn save_state(&self) -> Result<Vec<u8>, StateFileError> {
+        encode_board(self)
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateFileError> {
+        *self = decode_board(data)?;
+        Ok(())
+    }
 }
@@ -1,20 +1,39 @@
+use crate::mapper::Mapper;
+use crate::mapper::decode_board;
+use crate::mapper::encode_board;
+use crate::rom::Rom;
 use crate::rom::ScreenMode;
+use crate::state::StateFileError;
 use bitfield::bitfield;
 use log::*;
 use serde::Deserialize;
 use serde::Serialize;
 
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+// A12 has to stay low for this many CPU cycles before a rising edge clocks
+// the IRQ counter. This filters out the short A12 toggles between sprite
+// pattern fetches.
+const A12_LOW_CYCLES: u8 = 3;
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Txrom {
     use_chr_ram: bool,
     bank_select: BankSelect,
+    // R0-R7, R0 and R1 select 2KB CHR banks, R2-R5 select 1KB CHR banks,
+    // R6 and R7 select 8KB PRG banks
+    bank_regs: [u8; 8],
     last_page_start: usize,
     mirroring: ScreenMode,
     ram_enabled: bool,
     allow_writes: bool,
     irq_latch: u8,
+    irq_counter: u8,
     reload_irq_counter: bool,
     irq_enabled: bool,
+    irq: bool,
+    a12_high: bool,
+    a12_low_cycles: u8,
 }
 
 bitfield! {
@@ -32,44 +51,20 @@ impl Txrom {
         Txrom {
             use_chr_ram,
             bank_select: BankSelect(0),
+            bank_regs: [0, 2, 4, 5, 6, 7, 0, 1],
             last_page_start,
-            ram_enabled: false,
-            allow_writes: false,
+            // Not every board wires up the protect bits, so leave the RAM
+            // usable until the game says otherwise
+            ram_enabled: true,
+            allow_writes: true,
             mirroring: ScreenMode::Horizontal,
             irq_latch: 0,
+            irq_counter: 0,
             reload_irq_counter: false,
             irq_enabled: false,
-        }
-    }
-
-    pub fn ld_prg(&self, address: u16, prg_rom: &[u8], prg_ram: &[u8]) -> u8 {
-        match address {
-            // This is optional, check what that means
-            0x6000..=0x7FFF => prg_ram[address as usize - 0x6000],
-            // 0x8000..=0x9FFF => (or $C000-$DFFF): 8 KB switchable PRG ROM bank
-            // 0xA000..=0xBFFF =>  8 KB switchable PRG ROM bank
-            // This one sometimes switches, read register here
-            // 0xC000..=0xDFFF => prg_rom[last_page_start - 0x4000 + address as
-            // usize]
-            0xE000..=0xFFFF => {
-                prg_rom[self.last_page_start - 0x2000 + address as usize]
-            }
-            _ => panic!(),
-        }
-    }
-
-    pub fn store_prg(&mut self, addr: u16, val: u8, prg_ram: &mut Vec<u8>) {
-        match (addr, addr & 1) {
-            (0x6000..=0x7FFF, _) => prg_ram[(addr - 0x6000) as usize] = val,
-            (0x8000..=0x9FFF, even_odd) => self.bank_ops(even_odd == 0, val),
-            (0xA000..=0xBFFF, even_odd) => self.misc_ops(even_odd == 0, val),
-            (0xC000..=0xDFFF, even_odd) => {
-                self.irq_latch_reload(even_odd == 0, val)
-            }
-            (0xE000..=0xFFFF, even_odd) => self.irq_enabled = even_odd == 1,
-            (addr, _) => {
-                info!("Attempt to store to unmapped prg mem {:X}", addr)
-            }
+            irq: false,
+            a12_high: false,
+            a12_low_cycles: 0,
         }
     }
 
@@ -77,6 +72,7 @@ impl Txrom {
         if even {
             self.irq_latch = val;
         } else {
+            self.irq_counter = 0;
             self.reload_irq_counter = true;
         }
     }
@@ -85,26 +81,28 @@ impl Txrom {
         if even {
             self.bank_select.set_byte(val);
         } else {
-            match self.bank_select.bank_reg_select() {
-                0b000 => unimplemented!(),
-                0b001 => unimplemented!(),
-                0b010 => unimplemented!(),
-                0b011 => unimplemented!(),
-                0b100 => unimplemented!(),
-                0b101 => unimplemented!(),
-                0b110 => unimplemented!(),
-                0b111 => unimplemented!(),
-                _ => panic!("TXROM: Can't get here"),
-            }
+            let reg = self.bank_select.bank_reg_select() as usize;
+            self.bank_regs[reg] = match reg {
+                // The 2KB banks ignore the low bit
+                0 | 1 => val & !1,
+                2..=5 => val,
+                // MMC3 only has 6 PRG ROM address lines
+                _ => val & 0b111111,
+            };
         }
     }
 
-    fn misc_ops(&mut self, even: bool, val: u8) {
+    // Four screen boards like TVROM don't connect the mirroring output, the
+    // write is ignored
+    fn misc_ops(&mut self, rom: &Rom, even: bool, val: u8) {
         if even {
-            self.mirroring = match val & 0b1 {
-                0 => ScreenMode::Vertical,
-                1 => ScreenMode::Horizontal,
-                _ => panic!("Impossible"),
+            if matches!(rom.header.screen, ScreenMode::FourScreen) {
+                return;
+            }
+            self.mirroring = if val & 0b1 == 0 {
+                ScreenMode::Vertical
+            } else {
+                ScreenMode::Horizontal
             }
         } else {
             // Implemented bits 0 and 1 for MMC6 at some point
@@ -114,18 +112,115 @@ impl Txrom {
         }
     }
 
-    pub fn ld_chr(
-        &self,
-        _address: u16,
-        _chr_rom: &[u8],
-        _chr_ram: &[u8],
-    ) -> u8 {
-        0
+    fn get_prg_index(&self, addr: u16, prg_rom: &[u8]) -> usize {
+        let second_last = self.last_page_start;
+        let bank_start =
+            |reg: u8| (reg as usize * PRG_BANK_SIZE) % prg_rom.len();
+        let start = match (addr, self.bank_select.prg_rom_mode()) {
+            (0x8000..=0x9FFF, false) => bank_start(self.bank_regs[6]),
+            (0x8000..=0x9FFF, true) => second_last,
+            (0xA000..=0xBFFF, _) => bank_start(self.bank_regs[7]),
+            (0xC000..=0xDFFF, false) => second_last,
+            (0xC000..=0xDFFF, true) => bank_start(self.bank_regs[6]),
+            // $E000-$FFFF
+            (_, _) => second_last + PRG_BANK_SIZE,
+        };
+        start + (addr as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn get_chr_index(&self, addr: u16, chr_len: usize) -> usize {
+        let addr = addr as usize & 0x1FFF;
+        // Inversion swaps the 2KB banks with the 1KB banks
+        let addr = if self.bank_select.chr_a12_inversion() {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+        let (reg, offset) = match addr {
+            0x0000..=0x07FF => (self.bank_regs[0], addr),
+            0x0800..=0x0FFF => (self.bank_regs[1], addr - 0x0800),
+            0x1000..=0x13FF => (self.bank_regs[2], addr - 0x1000),
+            0x1400..=0x17FF => (self.bank_regs[3], addr - 0x1400),
+            0x1800..=0x1BFF => (self.bank_regs[4], addr - 0x1800),
+            _ => (self.bank_regs[5], addr - 0x1C00),
+        };
+        (reg as usize * CHR_BANK_SIZE + offset) % chr_len
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0 || self.reload_irq_counter {
+            self.irq_counter = self.irq_latch;
+            self.reload_irq_counter = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq = true;
+        }
+    }
+}
+
+impl Mapper for Txrom {
+    fn ld_prg(&self, rom: &Rom, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => {
+                if self.ram_enabled {
+                    rom.prg_ram[address as usize - 0x6000]
+                } else {
+                    info!("Reading from disabled prg ram {:X}", address);
+                    0
+                }
+            }
+            0x8000..=0xFFFF => {
+                rom.prg_rom[self.get_prg_index(address, &rom.prg_rom)]
+            }
+            addr => {
+                info!("Reading from unmapped memory {:X}", addr);
+                0
+            }
+        }
     }
 
-    pub fn store_chr(&mut self, address: u16, val: u8, chr_ram: &mut Vec<u8>) {
+    fn store_prg(&mut self, rom: &mut Rom, addr: u16, val: u8) {
+        match (addr, addr & 1) {
+            (0x6000..=0x7FFF, _) => {
+                if self.ram_enabled && self.allow_writes {
+                    rom.prg_ram[(addr - 0x6000) as usize] = val;
+                }
+            }
+            (0x8000..=0x9FFF, even_odd) => self.bank_ops(even_odd == 0, val),
+            (0xA000..=0xBFFF, even_odd) => {
+                self.misc_ops(rom, even_odd == 0, val)
+            }
+            (0xC000..=0xDFFF, even_odd) => {
+                self.irq_latch_reload(even_odd == 0, val)
+            }
+            (0xE000..=0xFFFF, even_odd) => {
+                self.irq_enabled = even_odd == 1;
+                // Disabling the IRQ also acknowledges any pending one
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            (addr, _) => {
+                info!("Attempt to store to unmapped prg mem {:X}", addr)
+            }
+        }
+    }
+
+    fn ld_chr(&self, rom: &Rom, address: u16) -> u8 {
         if self.use_chr_ram {
-            chr_ram[address as usize] = val;
+            rom.chr_ram[self.get_chr_index(address, rom.chr_ram.len())]
+        } else {
+            rom.chr_rom[self.get_chr_index(address, rom.chr_rom.len())]
+        }
+    }
+
+    fn store_chr(&mut self, rom: &mut Rom, address: u16, val: u8) {
+        if self.use_chr_ram {
+            let index = self.get_chr_index(address, rom.chr_ram.len());
+            rom.chr_ram[index] = val;
         } else {
             info!(
                 "Tried to store to chr rom: addr {:X} val {:X}",
@@ -134,7 +229,48 @@ impl Txrom {
         }
     }
 
-    pub fn get_mirroring(&self) -> &ScreenMode {
-        &self.mirroring
+    fn mirroring(&self, rom: &Rom) -> ScreenMode {
+        match rom.header.screen {
+            ScreenMode::FourScreen => ScreenMode::FourScreen,
+            _ => self.mirroring,
+        }
+    }
+
+    // Called with every address the PPU puts on its bus. The IRQ counter is
+    // clocked by rising edges on A12, which with the usual setup of
+    // background tiles at $0000 and sprites at $1000 happens once per
+    // scanline.
+    fn ppu_addr(&mut self, addr: u16) {
+        let a12_high = addr & 0x1000 != 0;
+        if a12_high && !self.a12_high && self.a12_low_cycles >= A12_LOW_CYCLES {
+            self.clock_irq_counter();
+        }
+        if !a12_high && self.a12_high {
+            self.a12_low_cycles = 0;
+        }
+        self.a12_high = a12_high;
+    }
+
+    fn cpu_clock(&mut self) {
+        if !self.a12_high {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq
+    }
+
+    fn reset(&mut self) {
+        *self = Txrom::new(self.use_chr_ram, self.last_page_start);
+    }
+
+    fn save_state(&self) -> Result<Vec<u8>, StateFileError> {
+        encode_board(self)
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateFileError> {
+        *self = decode_board(data)?;
+        Ok(())
     }
 }
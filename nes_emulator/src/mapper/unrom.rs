@@ -1,3 +1,8 @@
+use crate::mapper::Mapper;
+use crate::mapper::decode_board;
+use crate::mapper::encode_board;
+use crate::rom::Rom;
+use crate::state::StateFileError;
 use log::*;
 use serde::Deserialize;
 use serde::Serialize;
@@ -17,8 +22,10 @@ impl Unrom {
             last_page_start,
         }
     }
+}
 
-    pub fn store_prg(&mut self, address: u16, val: u8) {
+impl Mapper for Unrom {
+    fn store_prg(&mut self, _rom: &mut Rom, address: u16, val: u8) {
         if address >= 0x8000 {
             let bank_select = (val & 0b111) as usize;
             self.current_bank_offset = bank_select * SIXTEEN_KB;
@@ -30,28 +37,37 @@ impl Unrom {
         }
     }
 
-    pub fn ld_prg(&self, address: u16, prg_rom: &[u8]) -> u8 {
+    fn ld_prg(&self, rom: &Rom, address: u16) -> u8 {
         if address < 0x8000 {
             info!("Reading from unmapped prg_rom address: {:X}", address);
             0
         // Bank switched using 3 bits
         } else if address < 0xC000 {
-            prg_rom[self.current_bank_offset + (address as usize - 0x8000)]
+            rom.prg_rom[self.current_bank_offset + (address as usize - 0x8000)]
         // Hard wired to last 16KB
         } else {
-            prg_rom[(7 * SIXTEEN_KB) + (address as usize - 0xC000)]
+            rom.prg_rom[(7 * SIXTEEN_KB) + (address as usize - 0xC000)]
         }
     }
 
-    pub fn ld_chr(&self, address: u16, chr_ram: &[u8]) -> u8 {
-        chr_ram[address as usize]
+    fn ld_chr(&self, rom: &Rom, address: u16) -> u8 {
+        rom.chr_ram[address as usize]
     }
 
-    pub fn store_chr(&mut self, address: u16, val: u8, chr_ram: &mut Vec<u8>) {
-        chr_ram[address as usize] = val;
+    fn store_chr(&mut self, rom: &mut Rom, address: u16, val: u8) {
+        rom.chr_ram[address as usize] = val;
     }
 
-    pub fn reset(&mut self) {
+    fn reset(&mut self) {
         self.current_bank_offset = 0;
     }
+
+    fn save_state(&self) -> Result<Vec<u8>, StateFileError> {
+        encode_board(self)
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateFileError> {
+        *self = decode_board(data)?;
+        Ok(())
+    }
 }
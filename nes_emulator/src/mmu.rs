@@ -1,7 +1,11 @@
 use crate::apu::Apu;
 use crate::controller::Controller;
-use crate::mapper::Mapper;
+use crate::debugger::PointList;
+use crate::debugger::WatchHit;
+use crate::debugger::Watchpoint;
+use crate::mapper::Cartridge;
 use crate::ppu::Ppu;
+use crate::state::StateFileError;
 use cpu_6502::Memory;
 use serde::Deserialize;
 use serde::Serialize;
@@ -16,63 +20,40 @@ const ROM_START: u16 = 0x4020;
 const ROM_END: u16 = 0xFFFF;
 
 pub const OAM_DMA: u16 = 0x4014;
+const APU_STATUS: u16 = 0x4015;
 pub const OAM_DATA: u16 = 0x2004;
 
 pub struct Mmu {
     pub ppu: Ppu,
     pub apu: Apu,
     pub ram: Ram,
-    pub mapper: Rc<RefCell<Mapper>>,
+    pub cartridge: Rc<RefCell<Cartridge>>,
     pub ctrl0: Controller,
     pub ctrl1: Controller,
+    // The last value on the CPU's data bus, which is what reads from
+    // unmapped addresses and undriven bits get
     open_bus: u8,
     pub oam_dma: Option<u8>,
-    _dmc_dma: bool,
+    // The address read on the last cycle. Consecutive reads of a controller
+    // port only clock it once, which is what makes a DMC fetch during a
+    // controller read drop a bit.
+    last_read: Option<u16>,
+    // CPU cycles since power on
+    pub cycles: u64,
+    // Checked on every read and write, the first hit is kept until the
+    // debugger takes it
+    pub watchpoints: PointList<Watchpoint>,
+    pub watch_hit: Option<WatchHit>,
 }
 
 impl Memory for Mmu {
     fn ld8(&mut self, address: u16) -> u8 {
-        let read = match address {
-            WRAM_START..=WRAM_END => self.ram.load(address & 0x7FF),
-            PPU_START..=PPU_END => {
-                let ppu_reg = address & 0b111;
-
-                match ppu_reg {
-                    0 | 1 | 3 | 5 | 6 => self.open_bus,
-                    2 => {
-                        let (ppu_status, _) = self.ppu.ld(2);
-                        self.open_bus = (ppu_status & 0b11100000)
-                            | (self.open_bus & 0b00011111);
-                        self.open_bus
-                    }
-                    4 => {
-                        let (read_val, _) = self.ppu.ld(ppu_reg);
-                        self.open_bus = read_val;
-                        read_val & 0b11100011
-                    }
-                    7 => {
-                        let (read_val, pal_read) = self.ppu.ld(ppu_reg);
-                        if pal_read.expect("Can't get None here") {
-                            (read_val & 0b00111111)
-                                | (self.open_bus & 0b11000000)
-                        } else {
-                            self.open_bus = read_val;
-                            read_val
-                        }
-                    }
-                    _ => panic!("No other possible values here"),
-                }
-            }
-            0x4015 => self.apu.load(address - 0x4000),
-            0x4016 => self.ctrl0.ld8(),
-            0x4017 => self.ctrl1.ld8(),
-            0x4000..=0x4014 | 0x4018..=0x401F => self.open_bus,
-            ROM_START..=ROM_END => {
-                let mapper = self.mapper.borrow();
-                mapper.ld_prg(address)
-            }
-        };
-        self.ppu.emulate_cycles(1);
+        let read = self.bus_read(address);
+        if !self.watchpoints.is_empty() {
+            self.watch(address, read, false);
+        }
+        self.last_read = Some(address);
+        self.tick();
         read
     }
 
@@ -83,6 +64,11 @@ impl Memory for Mmu {
     }
 
     fn store(&mut self, address: u16, val: u8) {
+        if !self.watchpoints.is_empty() {
+            self.watch(address, val, true);
+        }
+        self.open_bus = val;
+        self.last_read = None;
         match address {
             WRAM_START..=WRAM_END => self.ram.store(address & 0x7FF, val),
             PPU_START..=PPU_END => {
@@ -101,21 +87,63 @@ impl Memory for Mmu {
                 );
             }
             ROM_START..=ROM_END => {
-                self.mapper.borrow_mut().store_prg(address, val)
+                self.cartridge.borrow_mut().store_prg(address, val)
             }
         }
-        self.ppu.emulate_cycles(1);
+        self.tick();
+    }
+
+    fn irq_line(&self) -> bool {
+        self.irq_pending()
+    }
+
+    fn nmi_line(&self) -> bool {
+        self.ppu.nmi_line()
+    }
+
+    // OAM DMA starts with the write to $4014 and halts the CPU on its next
+    // read
+    fn dma_request(&self) -> Option<(u8, u16)> {
+        self.oam_dma.map(|page| (page, OAM_DATA))
+    }
+
+    fn dma_done(&mut self) {
+        self.oam_dma = None;
+    }
+
+    // The DMC fetches its samples the same way, a byte at a time
+    fn byte_dma_request(&self) -> Option<u16> {
+        self.apu.dmc.pending_fetch()
+    }
+
+    fn byte_dma_done(&mut self, val: u8) {
+        self.apu.dmc.fill(val);
+    }
+
+    // Reads happen on get cycles and writes on put cycles, see
+    // https://www.nesdev.org/wiki/DMA
+    fn is_get_cycle(&self) -> bool {
+        self.cycles % 2 == 1
     }
 }
 
+// Everything on the CPU side of the bus that isn't the APU
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MmuState {
+    ram: Ram,
+    ctrl0: Controller,
+    ctrl1: Controller,
+    open_bus: u8,
+    oam_dma: Option<u8>,
+    cycles: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Ram(Box<[u8]>);
 
 impl Default for Ram {
     fn default() -> Ram {
-        Ram {
-            0: Box::new([0; 0xFFF]),
-        }
+        Ram(Box::new([0; 0xFFF]))
     }
 }
 
@@ -130,22 +158,126 @@ impl Ram {
 }
 
 impl Mmu {
-    pub fn new(apu: Apu, ppu: Ppu, mapper: Rc<RefCell<Mapper>>) -> Mmu {
+    pub fn new(apu: Apu, ppu: Ppu, cartridge: Rc<RefCell<Cartridge>>) -> Mmu {
         Mmu {
             ppu,
             apu,
-            mapper,
+            cartridge,
             ram: Ram::default(),
             ctrl0: Controller::default(),
             ctrl1: Controller::default(),
             open_bus: 0,
             oam_dma: None,
-            _dmc_dma: false,
+            last_read: None,
+            watchpoints: PointList::default(),
+            watch_hit: None,
+            cycles: 0,
         }
     }
 
-    fn ppu_store(&mut self, address: u16, val: u8) {
+    pub fn get_state(&self) -> MmuState {
+        MmuState {
+            ram: self.ram.clone(),
+            ctrl0: self.ctrl0.clone(),
+            ctrl1: self.ctrl1.clone(),
+            open_bus: self.open_bus,
+            oam_dma: self.oam_dma,
+            cycles: self.cycles,
+        }
+    }
+
+    pub fn set_state(
+        &mut self,
+        mmu_state: MmuState,
+    ) -> Result<(), StateFileError> {
+        if mmu_state.ram.0.len() != self.ram.0.len() {
+            return Err(StateFileError::Incompatible("RAM size".to_string()));
+        }
+        self.ram = mmu_state.ram;
+        self.ctrl0 = mmu_state.ctrl0;
+        self.ctrl1 = mmu_state.ctrl1;
+        self.open_bus = mmu_state.open_bus;
+        self.oam_dma = mmu_state.oam_dma;
+        self.cycles = mmu_state.cycles;
+        Ok(())
+    }
+
+    // All of the cartridge and APU interrupt sources share one IRQ line
+    pub fn irq_pending(&self) -> bool {
+        self.apu.irq() || self.cartridge.borrow().irq()
+    }
+
+    // Every bus access takes one CPU cycle, so the rest of the system catches
+    // up here
+    fn tick(&mut self) {
+        self.ppu.emulate_cycles(1);
+        self.apu.step();
+        self.cartridge.borrow_mut().cpu_clock();
+        self.cycles += 1;
+    }
+
+    // What a read puts on the data bus, ld8 adds the cycle it takes
+    fn bus_read(&mut self, address: u16) -> u8 {
+        let val = match address {
+            WRAM_START..=WRAM_END => self.ram.load(address & 0x7FF),
+            PPU_START..=PPU_END => self.ppu.ld(address & 0b111),
+            // The status register is inside the CPU, so it never makes it
+            // onto the data bus and bit 5 is whatever was there before
+            APU_STATUS => {
+                let status = self.apu.load(address - 0x4000).unwrap_or(0);
+                return (status & !0x20) | (self.open_bus & 0x20);
+            }
+            0x4000..=0x4014 => {
+                self.apu.load(address - 0x4000).unwrap_or(self.open_bus)
+            }
+            // The controllers only drive the low bits
+            0x4016 | 0x4017 => {
+                let repeat = self.last_read == Some(address);
+                let ctrl = if address == 0x4016 {
+                    &mut self.ctrl0
+                } else {
+                    &mut self.ctrl1
+                };
+                let bit = if repeat { ctrl.peek() } else { ctrl.ld8() };
+                (self.open_bus & 0xE0) | bit
+            }
+            0x4018..=0x401F => self.open_bus,
+            ROM_START..=ROM_END => {
+                let cartridge = self.cartridge.borrow();
+                cartridge.ld_prg(address)
+            }
+        };
         self.open_bus = val;
+        val
+    }
+
+    // Reads memory without side effects, for debuggers. The PPU, APU and
+    // controller registers read as open bus.
+    pub fn peek(&self, address: u16) -> u8 {
+        match address {
+            WRAM_START..=WRAM_END => self.ram.load(address & 0x7FF),
+            ROM_START..=ROM_END => self.cartridge.borrow().ld_prg(address),
+            _ => self.open_bus,
+        }
+    }
+
+    fn watch(&mut self, address: u16, value: u8, write: bool) {
+        if self.watch_hit.is_some() {
+            return;
+        }
+        self.watch_hit = self
+            .watchpoints
+            .iter()
+            .find(|(_, watchpoint)| watchpoint.matches(address, write))
+            .map(|(id, _)| WatchHit {
+                id: *id,
+                address,
+                value,
+                write,
+            });
+    }
+
+    fn ppu_store(&mut self, address: u16, val: u8) {
         self.ppu.store((address - 0x2000) & 7, val);
     }
 
@@ -0,0 +1,383 @@
+use crate::controller::Button;
+use crate::state::State;
+use anyhow::Result;
+use std::fmt::Write;
+use thiserror::Error;
+
+// Button order of the gamepad fields in FCEUX .fm2 input logs
+const FM2_BUTTONS: [(Button, char); 8] = [
+    (Button::Right, 'R'),
+    (Button::Left, 'L'),
+    (Button::Down, 'D'),
+    (Button::Up, 'U'),
+    (Button::Start, 'T'),
+    (Button::Select, 'S'),
+    (Button::B, 'B'),
+    (Button::A, 'A'),
+];
+
+// Button order of the gamepad fields in BizHawk's NES input logs
+const BK2_BUTTONS: [(Button, &str, char); 8] = [
+    (Button::Up, "Up", 'U'),
+    (Button::Down, "Down", 'D'),
+    (Button::Left, "Left", 'L'),
+    (Button::Right, "Right", 'R'),
+    (Button::Start, "Start", 'S'),
+    (Button::Select, "Select", 's'),
+    (Button::B, "B", 'B'),
+    (Button::A, "A", 'A'),
+];
+
+// fm2 command bits
+const FM2_SOFT_RESET: u8 = 0b01;
+const FM2_POWER: u8 = 0b10;
+
+#[derive(Debug, Error)]
+pub enum MovieError {
+    #[error("Movie parse error on line {0}: {1}")]
+    Parse(usize, String),
+    #[error("Movie not supported: {0}")]
+    Unsupported(String),
+    #[error("Movie starts at power on, it has to be played on a fresh rom")]
+    NotAtPowerOn,
+    #[error("Movie was made for a {0} console")]
+    WrongTiming(&'static str),
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct MovieFrame {
+    // Player one and two buttons, one bit per button as in Button
+    pub ports: [u8; 2],
+    // Soft reset before the frame runs
+    pub reset: bool,
+}
+
+#[derive(Clone)]
+pub enum MovieStart {
+    PowerOn,
+    State(Box<State>),
+}
+
+#[derive(Clone)]
+pub struct Movie {
+    pub start: MovieStart,
+    pub pal: bool,
+    pub rerecords: u32,
+    pub frames: Vec<MovieFrame>,
+}
+
+// What the emulator is doing with a movie. Movie frames are counted from
+// start_frame, the emulator's frame count when the movie started.
+pub enum MovieMode {
+    Recording {
+        movie: Movie,
+        start_frame: u64,
+        // Set by a reset between frames, stored with the next frame
+        reset: bool,
+    },
+    Playing {
+        movie: Movie,
+        start_frame: u64,
+    },
+}
+
+impl Movie {
+    pub fn new(start: MovieStart, pal: bool) -> Movie {
+        Movie {
+            start,
+            pal,
+            rerecords: 0,
+            frames: Vec::new(),
+        }
+    }
+
+    // FCEUX's text movie format, see https://fceux.com/web/FM2.html
+    // Movies starting from a save state embed our own state format, those
+    // made by FCEUX can't be loaded.
+    pub fn from_fm2(text: &str) -> Result<Movie> {
+        let mut movie = Movie::new(MovieStart::PowerOn, false);
+        for (i, line) in text.lines().enumerate() {
+            let line_num = i + 1;
+            let line = line.trim_end();
+            if line.starts_with('|') {
+                let frame = parse_fm2_frame(line)
+                    .map_err(|e| MovieError::Parse(line_num, e))?;
+                // FCEUX puts a power on at the start of some movies, which
+                // is where we start anyway
+                if frame.1 && !movie.frames.is_empty() {
+                    return Err(MovieError::Unsupported(
+                        "power cycles during the movie".to_string(),
+                    )
+                    .into());
+                }
+                movie.frames.push(frame.0);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let number = || {
+                value.parse::<u32>().map_err(|_| {
+                    MovieError::Parse(
+                        line_num,
+                        format!("bad value for {}", key),
+                    )
+                })
+            };
+            match key {
+                "version" if number()? != 3 => {
+                    return Err(MovieError::Unsupported(format!(
+                        "fm2 version {}",
+                        value
+                    ))
+                    .into());
+                }
+                "binary" if number()? != 0 => {
+                    return Err(MovieError::Unsupported(
+                        "binary fm2 input logs".to_string(),
+                    )
+                    .into());
+                }
+                "palFlag" => movie.pal = number()? != 0,
+                "rerecordCount" => movie.rerecords = number()?,
+                "port0" | "port1" if number()? > 1 => {
+                    return Err(MovieError::Unsupported(format!(
+                        "{} device {}",
+                        key, value
+                    ))
+                    .into());
+                }
+                "port2" | "fourscore" | "FDS" if number()? != 0 => {
+                    return Err(MovieError::Unsupported(format!(
+                        "{} {}",
+                        key, value
+                    ))
+                    .into());
+                }
+                "savestate" => {
+                    let data = decode_hex(value).ok_or_else(|| {
+                        MovieError::Unsupported(
+                            "save states from other emulators".to_string(),
+                        )
+                    })?;
+                    let state = State::load(&mut &data[..])?;
+                    movie.start = MovieStart::State(Box::new(state));
+                }
+                _ => (),
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> Result<String> {
+        let mut text = String::new();
+        // Writing to a String can't fail
+        let _ = writeln!(text, "version 3");
+        let _ = writeln!(text, "emuVersion 0");
+        let _ = writeln!(text, "rerecordCount {}", self.rerecords);
+        let _ = writeln!(text, "palFlag {}", self.pal as u8);
+        let _ = writeln!(text, "fourscore 0");
+        let _ = writeln!(text, "microphone 0");
+        let _ = writeln!(text, "port0 1");
+        let _ = writeln!(text, "port1 1");
+        let _ = writeln!(text, "port2 0");
+        let _ = writeln!(text, "FDS 0");
+        let _ = writeln!(text, "NewPPU 0");
+        if let MovieStart::State(state) = &self.start {
+            let mut data = Vec::new();
+            state.save(&mut data)?;
+            let _ = writeln!(text, "savestate {}", encode_hex(&data));
+        }
+
+        for frame in &self.frames {
+            let commands = if frame.reset { FM2_SOFT_RESET } else { 0 };
+            let _ = write!(text, "|{}", commands);
+            for port in frame.ports {
+                text.push('|');
+                for (button, letter) in FM2_BUTTONS {
+                    let pressed = port & button as u8 != 0;
+                    text.push(if pressed { letter } else { '.' });
+                }
+            }
+            text.push_str("||\n");
+        }
+        Ok(text)
+    }
+
+    // The Input Log.txt of a BizHawk .bk2 archive. BizHawk keeps save states
+    // in a separate file of the archive, so only power on movies can be
+    // moved across.
+    pub fn from_bk2_input_log(text: &str) -> Result<Movie> {
+        let mut movie = Movie::new(MovieStart::PowerOn, false);
+        let mut groups = default_bk2_log_key();
+        for (i, line) in text.lines().enumerate() {
+            let line_num = i + 1;
+            let line = line.trim_end();
+            if let Some(log_key) = line.strip_prefix("LogKey:") {
+                groups = log_key
+                    .split('#')
+                    .filter(|group| !group.is_empty())
+                    .map(|group| {
+                        group
+                            .split('|')
+                            .filter(|name| !name.is_empty())
+                            .map(|name| name.to_string())
+                            .collect()
+                    })
+                    .collect();
+            } else if line.starts_with('|') {
+                let (frame, power) = parse_bk2_frame(line, &groups)
+                    .map_err(|e| MovieError::Parse(line_num, e))?;
+                if power && !movie.frames.is_empty() {
+                    return Err(MovieError::Unsupported(
+                        "power cycles during the movie".to_string(),
+                    )
+                    .into());
+                }
+                movie.frames.push(frame);
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_bk2_input_log(&self) -> Result<String> {
+        if let MovieStart::State(_) = self.start {
+            return Err(MovieError::Unsupported(
+                "bk2 input logs starting from a save state".to_string(),
+            )
+            .into());
+        }
+
+        let mut text = String::from("[Input]\nLogKey:");
+        for group in default_bk2_log_key() {
+            text.push('#');
+            for name in group {
+                text.push_str(&name);
+                text.push('|');
+            }
+        }
+        text.push('\n');
+        for frame in &self.frames {
+            text.push_str(if frame.reset { "|r." } else { "|.." });
+            for port in frame.ports {
+                text.push('|');
+                for (button, _, letter) in BK2_BUTTONS {
+                    let pressed = port & button as u8 != 0;
+                    text.push(if pressed { letter } else { '.' });
+                }
+            }
+            text.push_str("|\n");
+        }
+        text.push_str("[/Input]\n");
+        Ok(text)
+    }
+}
+
+// Returns the frame and whether it starts with a power cycle
+fn parse_fm2_frame(line: &str) -> Result<(MovieFrame, bool), String> {
+    let fields: Vec<&str> = line.split('|').collect();
+    if fields.len() < 4 {
+        return Err("expected |commands|port0|port1|".to_string());
+    }
+    let commands: u8 = fields[1]
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad commands {}", fields[1]))?;
+    let mut frame = MovieFrame {
+        reset: commands & FM2_SOFT_RESET != 0,
+        ..MovieFrame::default()
+    };
+    for (port, field) in frame.ports.iter_mut().zip(&fields[2..4]) {
+        // Empty when nothing is plugged in
+        if field.is_empty() {
+            continue;
+        }
+        if field.chars().count() != FM2_BUTTONS.len() {
+            return Err(format!("bad gamepad {}", field));
+        }
+        for ((button, _), c) in FM2_BUTTONS.iter().zip(field.chars()) {
+            if c != '.' && c != ' ' {
+                *port |= *button as u8;
+            }
+        }
+    }
+    Ok((frame, commands & FM2_POWER != 0))
+}
+
+fn default_bk2_log_key() -> Vec<Vec<String>> {
+    let mut groups = vec![vec!["Reset".to_string(), "Power".to_string()]];
+    for player in ["P1", "P2"] {
+        groups.push(
+            BK2_BUTTONS
+                .iter()
+                .map(|(_, name, _)| format!("{} {}", player, name))
+                .collect(),
+        );
+    }
+    groups
+}
+
+fn parse_bk2_frame(
+    line: &str,
+    groups: &[Vec<String>],
+) -> Result<(MovieFrame, bool), String> {
+    let fields: Vec<&str> = line
+        .trim_start_matches('|')
+        .trim_end_matches('|')
+        .split('|')
+        .collect();
+    if fields.len() != groups.len() {
+        return Err(format!("expected {} fields", groups.len()));
+    }
+
+    let mut frame = MovieFrame::default();
+    let mut power = false;
+    for (field, names) in fields.iter().zip(groups) {
+        if field.chars().count() != names.len() {
+            return Err(format!("bad field {}", field));
+        }
+        for (c, name) in field.chars().zip(names) {
+            if c == '.' {
+                continue;
+            }
+            match name.as_str() {
+                "Reset" => frame.reset = true,
+                "Power" => power = true,
+                name => {
+                    let (port, button) = name
+                        .split_once(' ')
+                        .and_then(|(player, button)| {
+                            let port = match player {
+                                "P1" => 0,
+                                "P2" => 1,
+                                _ => return None,
+                            };
+                            BK2_BUTTONS
+                                .iter()
+                                .find(|(_, n, _)| *n == button)
+                                .map(|(button, _, _)| (port, *button))
+                        })
+                        .ok_or_else(|| format!("unsupported input {}", name))?;
+                    frame.ports[port] |= button as u8;
+                }
+            }
+        }
+    }
+    Ok((frame, power))
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut text, byte| {
+        let _ = write!(text, "{:02x}", byte);
+        text
+    })
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -1,26 +1,30 @@
-use crate::mapper::Mapper;
+use crate::mapper::Cartridge;
+use crate::state::StateFileError;
+use crate::timing::Timing;
 use serde::Deserialize;
 use serde::Serialize;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::ppu::io_latch::IoLatch;
 use crate::ppu::pregisters::Ctrl;
 use crate::ppu::pregisters::PRegisters;
 use crate::ppu::pregisters::VramAddr;
 use crate::ppu::shift_regs::InternalRegs;
 use crate::ppu::sprite::Priority;
 use crate::ppu::sprite::Sprite;
+use crate::ppu::sprite_eval::SpriteEval;
 use crate::ppu::vram::*;
 
+pub mod io_latch;
 pub mod pregisters;
 pub mod shift_regs;
 pub mod sprite;
+pub mod sprite_eval;
 pub mod vram;
 
-const SPRITE_NUM: usize = 64;
 const SCREEN_WIDTH: usize = 256;
 const SCREEN_HEIGHT: usize = 240;
-const PRERENDER: u16 = 261;
 
 pub const PALETTE: [u32; 64] = [
     0x808080, 0x003DA6, 0x0012B0, 0x440096, 0xA1005E, 0xC70028, 0xBA0600,
@@ -40,25 +44,37 @@ struct Rgb {
     data: [u8; 3],
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct PpuState {
     vram: Box<[u8]>,
     palette: [u8; 0x20],
+    ppudata_buff: u8,
+    #[serde(with = "serde_bytes")]
+    oam: Vec<u8>,
+    tmp_oam: Vec<Sprite>,
+    main_oam: Vec<Sprite>,
     ppu_regs: PRegisters,
     ppu_render_regs: InternalRegs,
     cc: u16,
     scanline: u16,
     write_latch: bool,
     t_addr: VramAddr,
-    trip_nmi: bool,
+    fine_x: u8,
+    frame_ready: bool,
     vblank_off: bool,
     at_entry: u8,
+    odd_frame: bool,
+    dot_remainder: usize,
+    io_latch: IoLatch,
+    sprite_eval: SpriteEval,
+    // The part of the frame drawn so far
+    #[serde(with = "serde_bytes")]
+    screen_buff: Box<[u8]>,
 }
 
 pub struct Ppu {
     pub regs: PRegisters,
     pub frame_ready: bool,
-    pub nmi_pending: bool,
     vram: Vram,
     // multiply by 3 to account for r g b
     screen_buff: Box<[u8]>,
@@ -79,12 +95,6 @@ pub struct Ppu {
     // Fine x scrolling is not part of the 16 bit internal v_addr, so the NES
     // has a separate fine x register for inner tile scrolling
     fine_x: u8,
-    // Used to force an nmi when in vblank and a write to CTRL enables NMI
-    trip_nmi: bool,
-    // When an NMI is tripped, the NMI shouldn't execute until after the NEXT
-    // instruction. This flag is used to differentiate between a regular NMI
-    // and a forced NMI.
-    pub queued_nmi: bool,
     // Used to correctly emulate the race condition when reading from STATUS
     // disables NMI for that frame
     vblank_off: bool,
@@ -93,15 +103,19 @@ pub struct Ppu {
     // Contains the shift and latch registers the NES uses for rendering
     internal_regs: InternalRegs,
     odd_frame: bool,
+    timing: Timing,
+    // Fractional PPU dots owed to the CPU, PAL runs 3.2 dots per CPU cycle
+    dot_remainder: usize,
+    io_latch: IoLatch,
+    sprite_eval: SpriteEval,
 }
 
 impl Ppu {
-    pub fn new(mapper: Rc<RefCell<Mapper>>) -> Ppu {
+    pub fn new(cartridge: Rc<RefCell<Cartridge>>, timing: Timing) -> Ppu {
         Ppu {
-            trip_nmi: false,
             vblank_off: false,
             regs: PRegisters::default(),
-            vram: Vram::new(mapper),
+            vram: Vram::new(cartridge),
             screen_buff: Box::new([0; SCREEN_WIDTH * 3 * SCREEN_HEIGHT]),
             oam: [0; 256],
             tmp_oam: Vec::with_capacity(8),
@@ -114,9 +128,11 @@ impl Ppu {
             at_entry: 0,
             internal_regs: InternalRegs::default(),
             odd_frame: false,
+            timing,
+            dot_remainder: 0,
             frame_ready: false,
-            nmi_pending: false,
-            queued_nmi: false,
+            io_latch: IoLatch::default(),
+            sprite_eval: SpriteEval::default(),
         }
     }
 
@@ -124,34 +140,65 @@ impl Ppu {
         PpuState {
             vram: self.vram.vram.clone(),
             palette: self.vram.palette,
+            ppudata_buff: self.vram.ppudata_buff,
+            oam: self.oam.to_vec(),
+            tmp_oam: self.tmp_oam.clone(),
+            main_oam: self.main_oam.clone(),
             ppu_regs: self.regs,
             ppu_render_regs: self.internal_regs,
             cc: self.cc,
             scanline: self.scanline,
             write_latch: self.write_latch,
             t_addr: self.t_addr,
-            trip_nmi: self.trip_nmi,
+            fine_x: self.fine_x,
+            frame_ready: self.frame_ready,
             vblank_off: self.vblank_off,
             at_entry: self.at_entry,
+            odd_frame: self.odd_frame,
+            dot_remainder: self.dot_remainder,
+            io_latch: self.io_latch,
+            sprite_eval: self.sprite_eval,
+            screen_buff: self.screen_buff.clone(),
         }
     }
 
-    pub fn set_state(&mut self, ppu_state: PpuState) {
+    pub fn set_state(
+        &mut self,
+        ppu_state: PpuState,
+    ) -> Result<(), StateFileError> {
+        if ppu_state.vram.len() != self.vram.vram.len()
+            || ppu_state.oam.len() != self.oam.len()
+            || ppu_state.screen_buff.len() != self.screen_buff.len()
+        {
+            return Err(StateFileError::Incompatible(
+                "PPU memory size".to_string(),
+            ));
+        }
         self.vram.vram = ppu_state.vram;
         self.vram.palette = ppu_state.palette;
+        self.vram.ppudata_buff = ppu_state.ppudata_buff;
+        self.oam.copy_from_slice(&ppu_state.oam);
+        self.tmp_oam = ppu_state.tmp_oam;
+        self.main_oam = ppu_state.main_oam;
         self.regs = ppu_state.ppu_regs;
         self.internal_regs = ppu_state.ppu_render_regs;
         self.cc = ppu_state.cc;
         self.scanline = ppu_state.scanline;
         self.write_latch = ppu_state.write_latch;
         self.t_addr = ppu_state.t_addr;
-        self.trip_nmi = ppu_state.trip_nmi;
+        self.fine_x = ppu_state.fine_x;
+        self.frame_ready = ppu_state.frame_ready;
         self.vblank_off = ppu_state.vblank_off;
         self.at_entry = ppu_state.at_entry;
+        self.odd_frame = ppu_state.odd_frame;
+        self.dot_remainder = ppu_state.dot_remainder;
+        self.io_latch = ppu_state.io_latch;
+        self.sprite_eval = ppu_state.sprite_eval;
+        self.screen_buff = ppu_state.screen_buff;
+        Ok(())
     }
 
     pub fn reset(&mut self) {
-        self.trip_nmi = false;
         self.vblank_off = false;
         self.regs = PRegisters::default();
         self.vram.reset();
@@ -159,6 +206,7 @@ impl Ppu {
         self.oam = [0; 256];
         self.tmp_oam = Vec::with_capacity(8);
         self.main_oam = Vec::with_capacity(8);
+        self.sprite_eval = SpriteEval::default();
         self.cc = 0;
         self.scanline = 0;
         self.write_latch = false;
@@ -166,6 +214,7 @@ impl Ppu {
         self.t_addr = VramAddr(0);
         self.at_entry = 0;
         self.internal_regs = InternalRegs::default();
+        self.dot_remainder = 0;
     }
 
     fn get_palette_color(&self, vram_offset: u8) -> Rgb {
@@ -180,16 +229,19 @@ impl Ppu {
         }
     }
 
-    pub fn ld(&mut self, address: u16) -> (u8, Option<bool>) {
-        match address {
-            2 => (self.read_ppustatus(), None),
-            4 => (self.oam[self.regs.oam_addr as usize], None),
-            7 => {
-                let (val, pal_read) = self.read_ppudata();
-                (val, Some(pal_read))
-            }
-            _ => panic!("Somehow got to invalid register"),
-        }
+    // A register read as the CPU sees it. Registers only drive some of the
+    // bits, the rest come from the I/O latch.
+    pub fn ld(&mut self, address: u16) -> u8 {
+        let (val, driven) = match address & 0b111 {
+            2 => (self.read_ppustatus(), 0b11100000),
+            4 => (self.read_oamdata(), 0xFF),
+            7 => self.read_ppudata(),
+            // Write only
+            _ => (0, 0),
+        };
+        let frames = self.timing.io_decay_frames();
+        self.io_latch.drive(val, driven, frames);
+        self.io_latch.load()
     }
 
     fn read_ppustatus(&mut self) -> u8 {
@@ -200,46 +252,86 @@ impl Ppu {
         tmp
     }
 
-    // This bool tells us if this read was from a palette or not
-    fn read_ppudata(&mut self) -> (u8, bool) {
-        let addr = self.regs.addr.addr();
+    // While rendering, reads see whatever sprite evaluation and the sprite
+    // fetches have on the OAM bus
+    fn read_oamdata(&self) -> u8 {
+        if self.is_rendering() {
+            self.sprite_eval.oam_bus(self.cc)
+        } else {
+            self.oam[self.regs.oam_addr as usize]
+        }
+    }
+
+    // Returns the value and the bits it drives. Palette entries are only 6
+    // bits wide and skip the read buffer, which gets the nametable byte
+    // underneath instead.
+    fn read_ppudata(&mut self) -> (u8, u8) {
+        let addr = self.regs.addr.addr() & 0x3FFF;
         let val = self.vram.buffered_ld8(addr);
         self.regs.addr.add_offset(self.regs.ctrl.vram_incr());
-        (val, (0x3F00..=0x3FFF).contains(&addr))
+        if addr < 0x3F00 {
+            (val, 0xFF)
+        } else if self.regs.mask.is_grey_scale() {
+            (val & 0x30, 0b00111111)
+        } else {
+            (val, 0b00111111)
+        }
     }
 
     pub fn store(&mut self, address: u16, val: u8) {
-        match address {
+        let frames = self.timing.io_decay_frames();
+        self.io_latch.drive(val, 0xFF, frames);
+        match address & 0b111 {
             0 => {
                 self.write_ctrl(val);
             }
-            1 => {
-                self.regs.mask.store(val);
-            }
+            1 => self.write_mask(val),
             2 => (),
             3 => self.write_oamaddr(val),
             4 => self.write_oamdata(val),
             5 => self.write_scroll(val),
             6 => self.write_ppuaddr(val),
-            7 => self.write_ppudata(val),
-            _ => panic!("Somehow got to invalid register"),
+            _ => self.write_ppudata(val),
         }
     }
 
     fn write_ctrl(&mut self, val: u8) {
         let ctrl = Ctrl(val);
-        self.trip_nmi = !self.regs.ctrl.nmi_on() && ctrl.nmi_on();
         self.regs.ctrl = ctrl;
         self.t_addr.set_nt(self.regs.ctrl.nametable());
     }
 
+    fn write_mask(&mut self, val: u8) {
+        let was_rendering = self.rendering_enabled();
+        self.regs.mask.store(val);
+        if !self.rendering_line() {
+            return;
+        }
+        match (was_rendering, self.rendering_enabled()) {
+            (true, false) => self.sprite_eval.rendering_disabled(self.cc),
+            (false, true) => self.sprite_eval.corrupt_oam(&mut self.oam),
+            _ => (),
+        }
+    }
+
     fn write_oamaddr(&mut self, val: u8) {
         self.regs.oam_addr = val;
     }
 
+    // The attribute bytes don't have bits 2-4, they always read back as 0.
+    // Writes while rendering don't reach OAM, they only bump n.
     fn write_oamdata(&mut self, val: u8) {
-        self.oam[self.regs.oam_addr as usize] = val;
-        self.regs.oam_addr = self.regs.oam_addr.wrapping_add(1);
+        if self.is_rendering() {
+            self.regs.oam_addr = self.regs.oam_addr.wrapping_add(4);
+            return;
+        }
+        let addr = self.regs.oam_addr;
+        self.oam[addr as usize] = if addr & 0b11 == 2 {
+            val & 0b11100011
+        } else {
+            val
+        };
+        self.regs.oam_addr = addr.wrapping_add(1);
     }
 
     fn write_scroll(&mut self, val: u8) {
@@ -257,6 +349,9 @@ impl Ppu {
         if self.write_latch {
             self.t_addr.set_l_byte(val);
             self.regs.addr = self.t_addr;
+            // The new address goes straight out on the PPU bus, which some
+            // games use to clock the MMC3 scanline counter by hand
+            self.vram.ppu_addr(self.regs.addr.addr());
         } else {
             self.t_addr.set_h_byte_clear_bit(val);
         }
@@ -274,51 +369,92 @@ impl Ppu {
             .copy_from_slice(&color.data);
     }
 
+    // Evaluation only runs on the visible scanlines, but the pre-render line
+    // still fetches whatever is left in secondary OAM. Nothing gets drawn
+    // from those, so there are never sprites on scanline 0.
     fn step_sprites(&mut self) {
+        let evaluating = self.rendering_enabled() && !self.is_prerender();
         match self.cc {
-            1 => {
-                self.tmp_oam.clear();
-                if self.is_prerender() {
-                    self.regs.status.set_sprite_o_f(false);
-                    self.regs.status.set_sprite_0_hit(false);
+            1 if self.is_prerender() && self.rendering_enabled() => {
+                self.sprite_eval.corrupt_oam(&mut self.oam);
+                // If OAMADDR isn't below 8 when rendering starts, the row it
+                // points at gets copied over the first 8 bytes of OAM
+                let row = (self.regs.oam_addr & 0xF8) as usize;
+                if row != 0 {
+                    self.oam.copy_within(row..row + 8, 0);
                 }
             }
-            257 => {
-                self.get_sprites();
-                self.regs.oam_addr = 0;
-            }
-            258..=320 => {
-                self.regs.oam_addr = 0;
+            1..=64 if evaluating => self.sprite_eval.clear(self.cc),
+            65..=256 if evaluating => {
+                let overflow = self.sprite_eval.evaluate(
+                    self.cc,
+                    &self.oam,
+                    &mut self.regs.oam_addr,
+                    self.scanline,
+                    self.regs.ctrl.sprite_size(),
+                );
+                if overflow {
+                    self.regs.status.set_sprite_o_f(true);
+                }
             }
-            321 => {
-                self.main_oam = self.tmp_oam.clone();
-                for sprite in self.main_oam.iter_mut() {
-                    let address =
-                        sprite.get_pt_address(&self.regs.ctrl, self.scanline);
-                    sprite.low_byte = self.vram.ld8(address);
-                    sprite.high_byte = self.vram.ld8(address + 8);
+            257..=320 => {
+                if self.cc == 257 {
+                    self.tmp_oam = self.sprite_eval.sprites();
+                }
+                if self.rendering_enabled() {
+                    self.regs.oam_addr = 0;
+                    self.fetch_sprite_pattern();
                 }
             }
+            321 if self.is_prerender() => self.main_oam.clear(),
+            321 => self.main_oam = self.tmp_oam.clone(),
             _ => (),
         }
     }
 
-    fn get_sprites(&mut self) {
-        self.tmp_oam.clear();
-        for sprite_index in 0..SPRITE_NUM {
-            if self.tmp_oam.len() == 8 {
-                self.regs.status.set_sprite_o_f(true);
-                return;
+    // Each of the 8 sprite slots gets 8 dots: two garbage nametable fetches
+    // followed by the low and high pattern bytes. Empty slots still fetch
+    // tile $FF, mappers watching A12 rely on these fetches happening.
+    fn fetch_sprite_pattern(&mut self) {
+        let slot = (self.cc - 257) as usize / 8;
+        let address = match self.tmp_oam.get(slot) {
+            Some(sprite) => {
+                sprite.get_pt_address(&self.regs.ctrl, self.scanline)
             }
-            let sprite_y = self.oam[sprite_index * 4] as u16;
-            if sprite_y <= self.scanline
-                && sprite_y + self.regs.ctrl.sprite_size() > self.scanline
-            {
-                self.tmp_oam.push(Sprite::new(sprite_index, &self.oam));
+            None => Sprite::dummy_pt_address(&self.regs.ctrl),
+        };
+        match (self.cc - 257) % 8 {
+            0 | 2 => self.vram.ppu_addr(self.regs.addr.nt_addr()),
+            4 => {
+                let low_byte = self.vram.ld8(address);
+                if let Some(sprite) = self.tmp_oam.get_mut(slot) {
+                    sprite.low_byte = low_byte;
+                }
+            }
+            6 => {
+                let high_byte = self.vram.ld8(address + 8);
+                if let Some(sprite) = self.tmp_oam.get_mut(slot) {
+                    sprite.high_byte = high_byte;
+                }
             }
+            _ => (),
         }
     }
 
+    fn rendering_enabled(&self) -> bool {
+        self.regs.mask.show_bg() || self.regs.mask.show_sprites()
+    }
+
+    // The pre-render and visible scanlines, where the PPU owns OAM and VRAM
+    // if rendering is on
+    fn rendering_line(&self) -> bool {
+        self.scanline < SCREEN_HEIGHT as u16 || self.is_prerender()
+    }
+
+    fn is_rendering(&self) -> bool {
+        self.rendering_enabled() && self.rendering_line()
+    }
+
     fn sprite_pixel(
         &mut self,
         x: u8,
@@ -346,7 +482,7 @@ impl Ppu {
                 continue;
             }
 
-            if sprite.index == 0 && bg_opaque && sprite.x != 255 {
+            if sprite.sprite_0 && bg_opaque && sprite.x != 255 {
                 self.regs.status.set_sprite_0_hit(true);
             }
 
@@ -369,7 +505,7 @@ impl Ppu {
                 //This is over simplified to make it faster. If there are
                 //games that write to vram during rendering and the emulator
                 //does not work, check this part out first.
-                0 => {
+                0 if self.rendering_enabled() => {
                     let nt_entry = self.vram.ld8(self.regs.addr.nt_addr());
                     self.at_entry = self.vram.ld8(self.regs.addr.at_addr());
 
@@ -405,10 +541,8 @@ impl Ppu {
                 }
             }
 
-            280..=304 => {
-                if self.is_prerender() && self.regs.mask.show_bg() {
-                    self.regs.addr.pull_y(self.t_addr);
-                }
+            280..=304 if self.is_prerender() && self.regs.mask.show_bg() => {
+                self.regs.addr.pull_y(self.t_addr);
             }
 
             _ => (),
@@ -429,7 +563,7 @@ impl Ppu {
     }
 
     fn is_prerender(&self) -> bool {
-        self.scanline == PRERENDER
+        self.scanline == self.timing.prerender_scanline()
     }
 
     //TODO: Split this logic into 2 functions to separate out the shift since
@@ -471,16 +605,36 @@ impl Ppu {
         &self.screen_buff
     }
 
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    // The PPU's NMI output, asserted while in vblank with NMI enabled. The
+    // CPU only reacts to it going high, so enabling NMI during vblank
+    // triggers another one.
+    pub fn nmi_line(&self) -> bool {
+        self.regs.status.vblank() && self.regs.ctrl.nmi_on()
+    }
+
+    // The dot (PPU cycle) within the current scanline, 0 to 340
+    pub fn dot(&self) -> u16 {
+        self.cc
+    }
+
     fn step_cc(&mut self) {
         self.cc += 1;
-        if self.odd_frame && self.is_prerender() && (self.cc == 340) {
+        if self.odd_frame
+            && self.timing.skips_odd_frame_dot()
+            && self.is_prerender()
+            && (self.cc == 340)
+        {
             self.scanline = 0;
             self.cc = 0;
             self.odd_frame = !self.odd_frame;
         } else if self.cc > 340 {
             self.cc = 0;
             self.scanline += 1;
-            if self.scanline > PRERENDER {
+            if self.scanline > self.timing.prerender_scanline() {
                 if self.regs.mask.show_bg() {
                     self.odd_frame = !self.odd_frame;
                 }
@@ -499,18 +653,15 @@ impl Ppu {
             240 => {
                 if self.cc == 0 {
                     self.frame_ready = true;
+                    self.io_latch.step_frame();
                 }
             }
-            241 => {
+            s if s == self.timing.vblank_scanline() => {
                 if self.cc == 1 && !self.vblank_off {
                     self.regs.status.set_vblank(true);
-                    if self.regs.ctrl.nmi_on() {
-                        self.nmi_pending = true;
-                    }
                 }
             }
-            242..=260 => (),
-            PRERENDER => {
+            s if s == self.timing.prerender_scanline() => {
                 if self.cc == 1 {
                     self.regs.status.set_vblank(false);
                     self.regs.status.set_sprite_0_hit(false);
@@ -520,24 +671,24 @@ impl Ppu {
                 self.render_pixel();
                 self.step_bg_regs();
             }
+            // Post render and the rest of vblank are idle
+            s if s < self.timing.prerender_scanline() => (),
             _ => panic!(
                 "Scanline can't get here {}. Check emulate_cycles",
                 self.scanline
             ),
         };
 
-        // This is the logic for forcing nmi
-        self.queued_nmi |=
-            self.trip_nmi && self.regs.status.vblank() && !self.vblank_off;
-
-        self.trip_nmi = false;
         self.vblank_off = false;
 
         self.step_cc();
     }
 
     pub fn emulate_cycles(&mut self, cyc_elapsed: usize) {
-        for _ in 0..(cyc_elapsed * 3) {
+        let (dots, cycles) = self.timing.ppu_dots_per_cpu_cycle();
+        self.dot_remainder += cyc_elapsed * dots;
+        while self.dot_remainder >= cycles {
+            self.dot_remainder -= cycles;
             self.step();
         }
     }
@@ -0,0 +1,41 @@
+use serde::Deserialize;
+use serde::Serialize;
+
+// The data bus between the CPU and the PPU's registers. It holds whatever
+// was last written to or read from a register, and write only registers
+// read back as this value. The bits are only kept up by the bus capacitance,
+// so each one decays to 0 if nothing drives it for a while.
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
+pub struct IoLatch {
+    val: u8,
+    // Frames until each bit decays
+    decay: [u8; 8],
+}
+
+impl IoLatch {
+    pub fn load(&self) -> u8 {
+        self.val
+    }
+
+    // Sets the bits in mask to the ones in val, they decay after frames
+    pub fn drive(&mut self, val: u8, mask: u8, frames: u8) {
+        self.val = (self.val & !mask) | (val & mask);
+        for (bit, decay) in self.decay.iter_mut().enumerate() {
+            if mask & (1 << bit) != 0 {
+                *decay = frames;
+            }
+        }
+    }
+
+    // Called once per frame
+    pub fn step_frame(&mut self) {
+        for (bit, decay) in self.decay.iter_mut().enumerate() {
+            if *decay > 0 {
+                *decay -= 1;
+                if *decay == 0 {
+                    self.val &= !(1 << bit);
+                }
+            }
+        }
+    }
+}
@@ -16,8 +16,8 @@ pub struct AtShift {
 
 impl AtShift {
     pub fn get_color(&self, c: u8, at_off: u8) -> u8 {
-        (((((self.high_tile >> (at_off)) & 1) as u8) << 1)
-            | (((self.low_tile >> (at_off)) & 1) as u8))
+        ((((self.high_tile >> at_off) & 1) << 1)
+            | ((self.low_tile >> at_off) & 1))
             << 2
             | c
     }
@@ -1,21 +1,23 @@
 use super::pregisters::Ctrl;
 use bitfield::bitfield;
+use serde::Deserialize;
+use serde::Serialize;
 
-const SPRITE_ATTR: usize = 4;
-
-#[derive(Copy, Clone)]
+#[derive(Serialize, Deserialize, Copy, Clone)]
 pub struct Sprite {
     pub x: u8,
     pub y: u8,
     pub pt_index: u8,
     pub attributes: SpriteAttr,
-    pub index: u8,
+    // Slot 0 holds the first sprite evaluation checked, which is the one
+    // that can trigger a sprite 0 hit
+    pub sprite_0: bool,
     pub low_byte: u8,
     pub high_byte: u8,
 }
 
 bitfield! {
-    #[derive(Copy, Clone)]
+    #[derive(Serialize, Deserialize, Copy, Clone)]
     pub struct SpriteAttr(u8);
     pub palette,  _: 1, 0;
     pub priority, _:    5;
@@ -24,13 +26,14 @@ bitfield! {
 }
 
 impl Sprite {
-    pub fn new(index: usize, oam: &[u8; 256]) -> Sprite {
+    // Builds a sprite from its 4 bytes in secondary OAM
+    pub fn new(bytes: &[u8], sprite_0: bool) -> Sprite {
         Sprite {
-            index: index as u8,
-            x: oam[(index * SPRITE_ATTR) + 3],
-            y: oam[index * SPRITE_ATTR],
-            pt_index: oam[(index * SPRITE_ATTR) + 1],
-            attributes: SpriteAttr(oam[(index * SPRITE_ATTR) + 2]),
+            sprite_0,
+            x: bytes[3],
+            y: bytes[0],
+            pt_index: bytes[1],
+            attributes: SpriteAttr(bytes[2]),
             low_byte: 0,
             high_byte: 0,
         }
@@ -41,20 +44,21 @@ impl Sprite {
     }
 
     pub fn get_pt_address(&self, ctrl: &Ctrl, y: u16) -> u16 {
-        let (pt_i, tall_sprite) = match ctrl.sprite_size() {
-            8 => (ctrl.sprite_pt_addr() + (16 * (self.pt_index as u16)), false),
-            16 => {
-                let offset = ((self.pt_index & !1) as u16) * 16;
-                let base = ((self.pt_index & 1) as u16) * 0x1000;
-                ((base + offset), true)
-            }
-            _ => panic!("No other sprite sizes"),
+        let tall_sprite = ctrl.sprite_size() == 16;
+        let pt_i = if tall_sprite {
+            let offset = ((self.pt_index & !1) as u16) * 16;
+            let base = ((self.pt_index & 1) as u16) * 0x1000;
+            base + offset
+        } else {
+            ctrl.sprite_pt_addr() + (16 * (self.pt_index as u16))
         };
 
-        let tmp = y - self.y as u16;
+        // Only the low bits of the row make it to the address, which keeps
+        // sprites fetched on the pre-render line in their tile
+        let tmp = y.wrapping_sub(self.y as u16) & (ctrl.sprite_size() - 1);
 
         let y = if self.attributes.flip_y() {
-            ctrl.sprite_size() as u16 - 1 - tmp
+            ctrl.sprite_size() - 1 - tmp
         } else {
             tmp
         };
@@ -65,6 +69,15 @@ impl Sprite {
 
         pt_i + y + y_offset
     }
+
+    // Unused sprite slots fetch the first row of tile $FF
+    pub fn dummy_pt_address(ctrl: &Ctrl) -> u16 {
+        if ctrl.sprite_size() == 16 {
+            0x1000 + 16 * 0xFE
+        } else {
+            ctrl.sprite_pt_addr() + 16 * 0xFF
+        }
+    }
 }
 
 pub enum Priority {
@@ -74,10 +87,10 @@ pub enum Priority {
 
 impl Priority {
     pub fn from_attr(bit: u8) -> Priority {
-        match bit {
-            0 => Priority::Foreground,
-            1 => Priority::Background,
-            _ => panic!("Can't get number either than 0 or 1 after anding"),
+        if bit == 0 {
+            Priority::Foreground
+        } else {
+            Priority::Background
         }
     }
 }
@@ -0,0 +1,170 @@
+use super::sprite::Sprite;
+use serde::Deserialize;
+use serde::Serialize;
+
+// Sprite evaluation finds the sprites on the next scanline and copies them
+// into secondary OAM. Dots 1-64 clear secondary OAM to $FF, then dots
+// 65-256 read a byte from OAM on odd dots and handle it on even ones. OAMADDR
+// is the pointer into OAM, n being the sprite (the top 6 bits) and m the
+// byte within it (the bottom 2).
+#[derive(Serialize, Deserialize, Copy, Clone, Default)]
+pub struct SpriteEval {
+    secondary: [u8; 32],
+    // Where the next byte goes in secondary OAM
+    sec_addr: u8,
+    // The byte read on the last odd dot, also what OAMDATA reads return
+    latch: u8,
+    // The sprite being read is in range and still getting copied
+    in_range: bool,
+    // Every sprite has been checked, the rest of the dots just bump n
+    done: bool,
+    // The first sprite checked was in range, so slot 0 can hit sprite 0
+    sprite_0: bool,
+    // Bytes left to read of the sprite that overflowed
+    overflow_bytes: u8,
+    // OAM rows that get overwritten with row 0 when rendering is turned back
+    // on, one bit per 8 bytes
+    corrupt_rows: u32,
+}
+
+impl SpriteEval {
+    pub fn clear(&mut self, dot: u16) {
+        self.latch = 0xFF;
+        if dot.is_multiple_of(2) {
+            self.secondary[(dot as usize - 1) / 2] = 0xFF;
+        }
+    }
+
+    // Returns true if the sprite overflow flag got set on this dot
+    pub fn evaluate(
+        &mut self,
+        dot: u16,
+        oam: &[u8; 256],
+        oam_addr: &mut u8,
+        scanline: u16,
+        height: u16,
+    ) -> bool {
+        if dot == 65 {
+            self.sec_addr = 0;
+            self.in_range = false;
+            self.done = false;
+            self.sprite_0 = false;
+            self.overflow_bytes = 0;
+        }
+        if dot % 2 == 1 {
+            self.latch = oam[*oam_addr as usize];
+            return false;
+        }
+
+        let mut n = *oam_addr >> 2;
+        let mut m = *oam_addr & 0b11;
+        let mut overflow = false;
+        if !self.done
+            && !self.in_range
+            && scanline.wrapping_sub(self.latch as u16) < height
+        {
+            self.in_range = true;
+            self.sprite_0 |= dot == 66;
+        }
+
+        if self.done {
+            n = (n + 1) & 0x3F;
+            if self.sec_addr >= 32 {
+                self.latch = self.secondary[self.sec_addr as usize & 0x1F];
+            }
+        } else if self.sec_addr < 32 {
+            self.secondary[self.sec_addr as usize] = self.latch;
+            if self.in_range {
+                m += 1;
+                self.sec_addr += 1;
+                if self.sec_addr.is_multiple_of(4) {
+                    self.in_range = false;
+                    m = 0;
+                    n = (n + 1) & 0x3F;
+                    self.done = n == 0;
+                }
+            } else {
+                n = (n + 1) & 0x3F;
+                self.done = n == 0;
+            }
+        } else {
+            // With 8 sprites found, writes to secondary OAM turn into reads
+            self.latch = self.secondary[self.sec_addr as usize & 0x1F];
+            if self.in_range {
+                overflow = true;
+                m += 1;
+                if m == 4 {
+                    n = (n + 1) & 0x3F;
+                    m = 0;
+                }
+                if self.overflow_bytes == 0 {
+                    self.overflow_bytes = 3;
+                } else {
+                    self.overflow_bytes -= 1;
+                    if self.overflow_bytes == 0 {
+                        self.done = true;
+                        m = 0;
+                    }
+                }
+            } else {
+                // The hardware bug, m gets incremented along with n, so the
+                // Y compare moves diagonally through OAM and checks tile,
+                // attribute and X bytes
+                n = (n + 1) & 0x3F;
+                m = (m + 1) & 0b11;
+                self.done = n == 0;
+            }
+        }
+        *oam_addr = (n << 2) | m;
+        overflow
+    }
+
+    // The sprites found for the next scanline
+    pub fn sprites(&self) -> Vec<Sprite> {
+        let count = (self.sec_addr as usize).div_ceil(4).min(8);
+        self.secondary
+            .chunks(4)
+            .take(count)
+            .enumerate()
+            .map(|(slot, bytes)| Sprite::new(bytes, slot == 0 && self.sprite_0))
+            .collect()
+    }
+
+    // What OAMDATA reads return while rendering. The sprite fetches read
+    // the Y, tile, attribute and then X byte for the rest of their 8 dots.
+    pub fn oam_bus(&self, dot: u16) -> u8 {
+        match dot {
+            1..=256 => self.latch,
+            257..=320 => {
+                let slot = (dot - 257) as usize / 8;
+                let byte = ((dot - 257) as usize % 8).min(3);
+                self.secondary[slot * 4 + byte]
+            }
+            _ => self.secondary[0],
+        }
+    }
+
+    // Turning rendering off part way through a scanline corrupts a row of
+    // OAM, which depends on the dot it happened on
+    pub fn rendering_disabled(&mut self, dot: u16) {
+        match dot {
+            0..=63 => self.corrupt_rows |= 1 << (dot / 2),
+            256..=319 => {
+                let base = (dot - 256) / 8;
+                let offset = ((dot - 256) % 8).min(3);
+                self.corrupt_rows |= 1 << (base * 4 + offset);
+            }
+            _ => (),
+        }
+    }
+
+    // Copies row 0 over the corrupted rows
+    pub fn corrupt_oam(&mut self, oam: &mut [u8; 256]) {
+        for row in 1..32 {
+            if self.corrupt_rows & (1 << row) != 0 {
+                oam.copy_within(0..8, row * 8);
+            }
+        }
+        self.corrupt_rows = 0;
+    }
+}
@@ -1,34 +1,24 @@
-use crate::mapper::Mapper;
-use crate::rom::ScreenBank;
-use crate::rom::ScreenMode;
+use crate::mapper::Cartridge;
+use crate::mapper::Nametable;
 use std::cell::RefCell;
 use std::rc::Rc;
 
 const VRAM_SIZE: usize = 0x800;
 
-const NT_0: u16 = 0x000;
-const NT_0_END: u16 = 0x3FF;
-const NT_1: u16 = 0x400;
-const NT_1_END: u16 = 0x7FF;
-const NT_2: u16 = 0x800;
-const NT_2_END: u16 = 0xBFF;
-const NT_3: u16 = 0xC00;
-const NT_3_END: u16 = 0xFFF;
-
 pub struct Vram {
     pub vram: Box<[u8]>,
-    mapper: Rc<RefCell<Mapper>>,
+    cartridge: Rc<RefCell<Cartridge>>,
     pub palette: [u8; 0x20],
-    ppudata_buff: u8,
+    pub ppudata_buff: u8,
 }
 
 impl Vram {
-    pub fn new(mapper: Rc<RefCell<Mapper>>) -> Vram {
+    pub fn new(cartridge: Rc<RefCell<Cartridge>>) -> Vram {
         Vram {
             vram: Box::new([0; VRAM_SIZE]),
             palette: [0; 0x20],
             ppudata_buff: 0,
-            mapper,
+            cartridge,
         }
     }
 
@@ -38,65 +28,79 @@ impl Vram {
     }
 
     pub fn buffered_ld8(&mut self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
         if addr < 0x3F00 {
             let val = self.ppudata_buff;
             self.ppudata_buff = self.ld8(addr);
             val
         } else {
-            self.ppudata_buff = self.vram[self.nt_mirror(addr & 0xFFF)];
+            self.ppudata_buff = self.ld_nt(addr & 0xFFF);
             self.ld8(addr)
         }
     }
 
+    // The PPU bus is 14 bits wide, so everything above $3FFF mirrors it
     pub fn ld8(&self, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
         match addr {
-            0x0000..=0x1FFF => self.mapper.borrow_mut().ld_chr(addr),
-            0x2000..=0x3EFF => self.vram[self.nt_mirror(addr & 0xFFF)],
-            0x3F00..=0x3FFF => self.palette[self.palette_mirror(addr)],
-            _ => panic!(),
+            0x0000..=0x1FFF => {
+                let mut cartridge = self.cartridge.borrow_mut();
+                cartridge.ppu_addr(addr);
+                cartridge.ld_chr(addr)
+            }
+            0x2000..=0x3EFF => {
+                self.ppu_addr(addr);
+                self.ld_nt(addr & 0xFFF)
+            }
+            _ => self.palette[self.palette_mirror(addr)],
         }
     }
 
     pub fn store(&mut self, addr: u16, val: u8) {
+        let addr = addr & 0x3FFF;
         match addr {
-            0x0000..=0x1FFF => self.mapper.borrow_mut().store_chr(addr, val),
-            0x2000..=0x3EFF => self.vram[self.nt_mirror(addr & 0xFFF)] = val,
-            0x3F00..=0x3FFF => self.palette[self.palette_mirror(addr)] = val,
-            _ => panic!(),
+            0x0000..=0x1FFF => {
+                let mut cartridge = self.cartridge.borrow_mut();
+                cartridge.ppu_addr(addr);
+                cartridge.store_chr(addr, val)
+            }
+            0x2000..=0x3EFF => {
+                self.ppu_addr(addr);
+                self.store_nt(addr & 0xFFF, val)
+            }
+            _ => self.palette[self.palette_mirror(addr)] = val,
         }
     }
 
-    // Helper function that resolves the nametable mirroring and returns an
-    // index usable for VRAM array indexing
-    fn nt_mirror(&self, addr: u16) -> usize {
-        match &*self.mapper.borrow().get_mirroring() {
-            ScreenMode::Horizontal => match addr {
-                NT_0..=NT_0_END => addr as usize,
-                NT_1..=NT_2_END => (addr - 0x400) as usize,
-                NT_3..=NT_3_END => (addr - 0x800) as usize,
-                _ => panic!("Horizontal: addr outside of nt passed"),
-            },
-            ScreenMode::Vertical => match addr {
-                NT_0..=NT_1_END => addr as usize,
-                NT_2..=NT_3_END => (addr - 0x800) as usize,
-                _ => panic!("Vertical: addr outside of nt passed"),
-            },
-            ScreenMode::OneScreenSwap(bank) => {
-                let addr = addr & 0x3FF;
-                match bank {
-                    ScreenBank::Lower => addr as usize,
-                    ScreenBank::Upper => addr as usize + 0x400,
-                }
-            }
-            ScreenMode::FourScreen => {
-                unimplemented!("Four Screen mode not supported yet")
-            }
+    // Puts an address on the PPU bus without reading or writing, so the
+    // mapper can see it. Palette accesses stay inside the PPU and never
+    // reach the bus.
+    pub fn ppu_addr(&self, addr: u16) {
+        if addr < 0x3F00 {
+            self.cartridge.borrow_mut().ppu_addr(addr);
+        }
+    }
+
+    // Nametables live in the console's VRAM unless the cartridge takes over
+    fn ld_nt(&self, addr: u16) -> u8 {
+        let cartridge = self.cartridge.borrow();
+        match cartridge.nametable(addr) {
+            Nametable::Ciram(index) => self.vram[index],
+            Nametable::Cartridge => cartridge.ld_nt(addr),
+        }
+    }
+
+    fn store_nt(&mut self, addr: u16, val: u8) {
+        let mut cartridge = self.cartridge.borrow_mut();
+        match cartridge.nametable(addr) {
+            Nametable::Ciram(index) => self.vram[index] = val,
+            Nametable::Cartridge => cartridge.store_nt(addr, val),
         }
     }
 
     fn palette_mirror(&self, addr: u16) -> usize {
         let addr = (addr as usize) & 0x1F;
-        match (addr as usize) % 32 {
+        match addr % 32 {
             0x10 | 0x14 | 0x18 | 0x1C => addr & 0xF,
             _ => addr,
         }
@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+
+// A snapshot every interval frames, up to capacity snapshots. Only the newest
+// snapshot is kept whole, every older one is stored as the XOR against the
+// snapshot after it with the runs of zeros squeezed out. Most of the machine
+// (RAM, VRAM, CHR RAM) barely changes between snapshots, so the deltas end up
+// a small fraction of a full state.
+//
+// The controller buttons of every frame since the oldest snapshot are kept
+// as well, so any frame in between two snapshots can be recreated by
+// replaying from the earlier one.
+pub struct Rewind {
+    interval: u64,
+    capacity: usize,
+    newest: Option<Snapshot>,
+    // Oldest first
+    deltas: VecDeque<Delta>,
+    // Player one and two buttons, starting at the oldest snapshot's frame
+    inputs: VecDeque<[u8; 2]>,
+}
+
+struct Snapshot {
+    frame: u64,
+    data: Vec<u8>,
+}
+
+struct Delta {
+    frame: u64,
+    len: usize,
+    rle: Vec<u8>,
+}
+
+// Where to rewind to, the snapshot and the buttons to replay on top of it
+pub struct Seek {
+    pub frame: u64,
+    pub data: Vec<u8>,
+    pub inputs: Vec<[u8; 2]>,
+}
+
+impl Rewind {
+    pub fn new(interval: u64, capacity: usize) -> Rewind {
+        Rewind {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            newest: None,
+            deltas: VecDeque::new(),
+            inputs: VecDeque::new(),
+        }
+    }
+
+    fn oldest_frame(&self) -> Option<u64> {
+        match self.deltas.front() {
+            Some(delta) => Some(delta.frame),
+            None => self.newest.as_ref().map(|snapshot| snapshot.frame),
+        }
+    }
+
+    pub fn wants_snapshot(&self, frame: u64) -> bool {
+        match &self.newest {
+            Some(snapshot) => frame >= snapshot.frame + self.interval,
+            None => true,
+        }
+    }
+
+    // The buttons for the frame about to run. Nothing can be replayed
+    // before the first snapshot, so those are dropped. Anything logged for
+    // this frame or later is replaced, in case the frame gets run again.
+    pub fn push_input(&mut self, frame: u64, buttons: [u8; 2]) {
+        if let Some(oldest) = self.oldest_frame() {
+            if frame < oldest {
+                return;
+            }
+            self.inputs.truncate((frame - oldest) as usize);
+            self.inputs.push_back(buttons);
+        }
+    }
+
+    pub fn push(&mut self, frame: u64, data: Vec<u8>) {
+        if let Some(newest) = self.newest.take() {
+            self.deltas.push_back(Delta {
+                frame: newest.frame,
+                len: newest.data.len(),
+                rle: encode_delta(&newest.data, &data),
+            });
+        }
+        self.newest = Some(Snapshot { frame, data });
+
+        while self.deltas.len() + 1 > self.capacity {
+            let dropped = self.deltas.pop_front().map(|delta| delta.frame);
+            if let (Some(dropped), Some(oldest)) =
+                (dropped, self.oldest_frame())
+            {
+                self.inputs.drain(..(oldest - dropped) as usize);
+            }
+        }
+    }
+
+    // Finds the newest snapshot at or before frame. Everything after it is
+    // thrown away, since the emulator is about to go back in time.
+    pub fn seek(&mut self, frame: u64) -> Option<Seek> {
+        loop {
+            let newest = self.newest.as_ref()?;
+            if newest.frame <= frame {
+                break;
+            }
+            match self.deltas.pop_back() {
+                Some(delta) => {
+                    let data = decode_delta(&delta, &newest.data);
+                    self.newest = Some(Snapshot {
+                        frame: delta.frame,
+                        data,
+                    });
+                }
+                None => {
+                    self.newest = None;
+                    self.inputs.clear();
+                    return None;
+                }
+            }
+        }
+
+        let oldest = self.oldest_frame()?;
+        let newest = self.newest.as_ref()?;
+        let start = (newest.frame - oldest) as usize;
+        let end = ((frame - oldest) as usize).min(self.inputs.len());
+        let inputs = self.inputs.range(start..end).copied().collect();
+        self.inputs.truncate(start);
+        Some(Seek {
+            frame: newest.frame,
+            data: newest.data.clone(),
+            inputs,
+        })
+    }
+}
+
+// XORs old against new, shorter snapshots are padded with zeros. The result
+// is a list of (zero run, literal count, literals) chunks, counts as
+// little endian u32s.
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let len = old.len().max(new.len());
+    let byte = |i: usize| {
+        old.get(i).copied().unwrap_or(0) ^ new.get(i).copied().unwrap_or(0)
+    };
+    let mut rle = Vec::new();
+    let mut i = 0;
+    while i < len {
+        let zeros_start = i;
+        while i < len && byte(i) == 0 {
+            i += 1;
+        }
+        let literals_start = i;
+        while i < len && byte(i) != 0 {
+            i += 1;
+        }
+        rle.extend(((literals_start - zeros_start) as u32).to_le_bytes());
+        rle.extend(((i - literals_start) as u32).to_le_bytes());
+        rle.extend((literals_start..i).map(byte));
+    }
+    rle
+}
+
+fn decode_delta(delta: &Delta, new: &[u8]) -> Vec<u8> {
+    let mut old = new.to_vec();
+    old.resize(delta.len.max(new.len()), 0);
+    let count = |at: usize| {
+        u32::from_le_bytes(delta.rle[at..at + 4].try_into().unwrap()) as usize
+    };
+    let mut at = 0;
+    let mut i = 0;
+    while at < delta.rle.len() {
+        i += count(at);
+        let literals = count(at + 4);
+        at += 8;
+        for byte in &delta.rle[at..at + literals] {
+            old[i] ^= byte;
+            i += 1;
+        }
+        at += literals;
+    }
+    old.truncate(delta.len);
+    old
+}
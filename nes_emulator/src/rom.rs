@@ -1,6 +1,4 @@
 use anyhow::Result;
-use log::Level;
-use log::log_enabled;
 use nom::IResult;
 use nom::Parser;
 use nom::bytes::complete::tag;
@@ -17,6 +15,9 @@ const PRG_RAM_PAGE_SIZE: usize = 8192;
 const CHR_ROM_PAGE_SIZE: usize = 8192;
 const CHR_RAM_PAGE_SIZE: usize = 8192;
 const TRAINER_LEN: usize = 512;
+// Four screen boards add 2KB of nametable RAM for the third and fourth
+// nametables
+const FOUR_SCREEN_RAM_SIZE: usize = 0x800;
 
 #[derive(Debug, Error)]
 pub enum LoadRomError {
@@ -24,12 +25,34 @@ pub enum LoadRomError {
     Unsupported(String),
     #[error("Parse error: invalid rom")]
     ParseError,
+    #[error("Mapper {0} (submapper {1}) not supported")]
+    UnsupportedMapper(u16, u8),
 }
 
-fn parse_rom(src: &[u8]) -> IResult<&[u8], Rom> {
+// Sizes in NES 2.0 headers are either a count of pages, or when the top
+// nibble is 0xF, an exponent and multiplier: 2^E * (MM * 2 + 1)
+fn rom_size(lsb: u8, msb: u8, page_size: usize) -> usize {
+    if msb == 0xF {
+        let multiplier = (lsb as usize & 0b11) * 2 + 1;
+        1usize
+            .checked_shl((lsb >> 2) as u32)
+            .and_then(|size| size.checked_mul(multiplier))
+            // Impossibly large, the take will fail
+            .unwrap_or(usize::MAX)
+    } else {
+        ((msb as usize) << 8 | lsb as usize) * page_size
+    }
+}
+
+// RAM sizes in NES 2.0 headers are a shift count, 64 << shift, 0 means none
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 { 0 } else { 64 << shift }
+}
+
+fn parse_header(src: &[u8]) -> IResult<&[u8], Header> {
     let (
         src,
-        (_, prg_pgs, chr_pgs, flag6, flag7, prg_ram_pgs, flag9, flag10, _),
+        (_, prg_pgs, chr_pgs, flag6, flag7, byte8, byte9, byte10, byte11),
     ) = (
         tag(&b"NES\x1A"[..]),
         be_u8(),
@@ -39,82 +62,178 @@ fn parse_rom(src: &[u8]) -> IResult<&[u8], Rom> {
         be_u8(),
         be_u8(),
         be_u8(),
-        take(5usize),
+        be_u8(),
     )
         .parse(src)?;
-    let (_, (_, prg_rom, chr_rom)) = (
-        cond((flag6 & 0b100) != 0, take(TRAINER_LEN)),
-        take(prg_pgs as usize * PRG_ROM_PAGE_SIZE),
-        take(chr_pgs as usize * CHR_ROM_PAGE_SIZE),
+    let (src, (byte12, byte13, byte14, byte15)) =
+        (be_u8(), be_u8(), be_u8(), be_u8()).parse(src)?;
+
+    let screen = if flag6 & 0b1000 != 0 {
+        ScreenMode::FourScreen
+    } else if flag6 & 0b01 == 1 {
+        ScreenMode::Vertical
+    } else {
+        ScreenMode::Horizontal
+    };
+    let save_ram = flag6 & 0b10 != 0;
+    let trainer = flag6 & 0b100 != 0;
+    let mapper = (flag7 & 0xF0 | flag6 >> 4) as u16;
+
+    let header = if flag7 & 0b1100 == 0b1000 {
+        Header {
+            rom_type: RomType::Nes2,
+            mapper: (byte8 as u16 & 0x0F) << 8 | mapper,
+            submapper: byte8 >> 4,
+            screen,
+            save_ram,
+            trainer,
+            console: match flag7 & 0b11 {
+                0 => ConsoleType::Nes,
+                1 => ConsoleType::VsSystem {
+                    ppu_type: byte13 & 0x0F,
+                    hardware_type: byte13 >> 4,
+                },
+                2 => ConsoleType::Playchoice10,
+                _ => ConsoleType::Extended(byte13 & 0x0F),
+            },
+            region: match byte12 & 0b11 {
+                0 => Region::NTSC,
+                1 => Region::PAL,
+                2 => Region::MultiRegion,
+                _ => Region::Dendy,
+            },
+            prg_rom_size: rom_size(prg_pgs, byte9 & 0x0F, PRG_ROM_PAGE_SIZE),
+            chr_rom_size: rom_size(chr_pgs, byte9 >> 4, CHR_ROM_PAGE_SIZE),
+            prg_ram_size: ram_size(byte10 & 0x0F),
+            prg_nvram_size: ram_size(byte10 >> 4),
+            chr_ram_size: ram_size(byte11 & 0x0F),
+            chr_nvram_size: ram_size(byte11 >> 4),
+            misc_roms: byte14 & 0b11,
+            expansion_device: byte15 & 0b111111,
+            flag10: 0,
+        }
+    } else {
+        // iNES only gives one PRG RAM size, 0 meaning 8KB for compatibility
+        let prg_ram = if byte8 != 0 {
+            PRG_RAM_PAGE_SIZE * byte8 as usize
+        } else {
+            PRG_RAM_PAGE_SIZE
+        };
+        Header {
+            rom_type: RomType::INes,
+            mapper,
+            submapper: 0,
+            screen,
+            save_ram,
+            trainer,
+            console: if flag7 & 0b01 == 1 {
+                ConsoleType::VsSystem {
+                    ppu_type: 0,
+                    hardware_type: 0,
+                }
+            } else if flag7 & 0b10 != 0 {
+                ConsoleType::Playchoice10
+            } else {
+                ConsoleType::Nes
+            },
+            region: if byte9 & 0b01 == 1 {
+                Region::PAL
+            } else {
+                Region::NTSC
+            },
+            prg_rom_size: prg_pgs as usize * PRG_ROM_PAGE_SIZE,
+            chr_rom_size: chr_pgs as usize * CHR_ROM_PAGE_SIZE,
+            prg_ram_size: if save_ram { 0 } else { prg_ram },
+            prg_nvram_size: if save_ram { prg_ram } else { 0 },
+            chr_ram_size: if chr_pgs == 0 { CHR_RAM_PAGE_SIZE } else { 0 },
+            chr_nvram_size: 0,
+            misc_roms: 0,
+            expansion_device: 0,
+            flag10: byte10,
+        }
+    };
+    Ok((src, header))
+}
+
+// CRC-32 (IEEE), the checksum rom databases use to identify dumps
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+fn parse_rom(src: &[u8]) -> IResult<&[u8], Rom> {
+    let (src, header) = parse_header(src)?;
+    let (src, (_, prg_rom, chr_rom)) = (
+        cond(header.trainer, take(TRAINER_LEN)),
+        take(header.prg_rom_size),
+        take(header.chr_rom_size),
     )
         .parse(src)?;
 
+    // Boards with neither CHR ROM nor a CHR RAM size still need somewhere
+    // to put their pattern tables
+    let chr_ram_size = match header.chr_ram_size + header.chr_nvram_size {
+        0 if chr_rom.is_empty() => CHR_RAM_PAGE_SIZE,
+        size => size,
+    };
+    let nt_ram_size = match header.screen {
+        ScreenMode::FourScreen => FOUR_SCREEN_RAM_SIZE,
+        _ => 0,
+    };
+
     Ok((
         src,
         Rom {
-            header: Header {
-                mapper: flag7 & 0xF0 | ((flag6 & 0xF0) >> 4),
-                screen: if flag6 & 0b1000 != 0 {
-                    ScreenMode::FourScreen
-                } else if flag6 & 0b01 == 1 {
-                    ScreenMode::Vertical
-                } else {
-                    ScreenMode::Horizontal
-                },
-                save_ram: flag6 & 0b10 != 0,
-                vs_unisystem: flag7 & 0b01 == 1,
-                playchoice10: flag7 & 0b10 != 0,
-                region: if flag9 & 0b01 == 1 {
-                    Region::PAL
-                } else {
-                    Region::NTSC
-                },
-                flag10,
-                rom_type: if flag7 & 0b1100 == 0b1000 {
-                    RomType::Nes2
-                } else {
-                    RomType::INes
-                },
-            },
+            header,
+            crc32: crc32(&[prg_rom, chr_rom].concat()),
             prg_rom: prg_rom.into(),
             chr_rom: chr_rom.into(),
-            prg_ram_size: if prg_ram_pgs != 0 {
-                PRG_RAM_PAGE_SIZE * prg_ram_pgs as usize
-            } else {
-                PRG_RAM_PAGE_SIZE as usize
-            },
             prg_ram: Vec::new(),
-            chr_ram: if chr_pgs == 0 {
-                vec![0; CHR_RAM_PAGE_SIZE]
-            } else {
-                Vec::new()
-            },
+            chr_ram: vec![0; chr_ram_size],
+            nt_ram: vec![0; nt_ram_size],
         },
     ))
 }
 
 pub fn load_rom(rom_bytes: &[u8]) -> Result<Rom> {
-    let rom = match parse_rom(rom_bytes) {
-        Ok((_, rom)) => rom,
+    match parse_rom(rom_bytes) {
+        Ok((_, rom)) => Ok(rom),
         Err(e) => {
-            log::debug!("Nom parse error message {}", e.to_string());
-            return Err(LoadRomError::ParseError.into());
+            log::debug!("Nom parse error message {}", e);
+            Err(LoadRomError::ParseError.into())
         }
-    };
-
-    rom.check_invalid()?;
-    Ok(rom)
+    }
 }
 
-// Almost no roms use flag10, as such pulled as u8
+// Almost no roms use flag10, as such pulled as u8. Fields NES 2.0 adds are
+// left at their defaults for iNES roms.
 pub struct Header {
     pub rom_type: RomType,
-    pub mapper: u8,
+    pub mapper: u16,
+    pub submapper: u8,
     pub screen: ScreenMode,
     pub save_ram: bool,
-    vs_unisystem: bool,
-    playchoice10: bool,
+    pub trainer: bool,
+    pub console: ConsoleType,
+    // CPU/PPU timing
     pub region: Region,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    // Volatile and battery backed (non-volatile) RAM sizes in bytes
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    // Number of miscellaneous ROMs after CHR ROM
+    pub misc_roms: u8,
+    pub expansion_device: u8,
     flag10: u8,
 }
 
@@ -123,15 +242,18 @@ impl fmt::Debug for Header {
         write!(
             f,
             "Header:\n\
-             Type-{:?}, Mapper-{}, ScreenMode-{:?}, SRAM-{}\n\
-             VS Unisystem-{}, Playchoice10-{}, Region-{:?}, flag10-{}\n",
+             Type-{:?}, Mapper-{}.{}, ScreenMode-{:?}, SRAM-{}\n\
+             Console-{:?}, Region-{:?}, Misc Roms-{}, Expansion-{:#04X}\n\
+             flag10-{}\n",
             self.rom_type,
             self.mapper,
+            self.submapper,
             self.screen,
             self.save_ram,
-            self.vs_unisystem,
-            self.playchoice10,
+            self.console,
             self.region,
+            self.misc_roms,
+            self.expansion_device,
             self.flag10
         )
     }
@@ -142,29 +264,19 @@ pub struct Rom {
     pub prg_ram: Vec<u8>,
     pub chr_rom: Vec<u8>,
     pub chr_ram: Vec<u8>,
-    prg_ram_size: usize,
+    // Nametable RAM on the cartridge, see Mapper::ld_nt
+    pub nt_ram: Vec<u8>,
+    // Of the PRG and CHR ROM, identifies the rom save states belong to
+    pub crc32: u32,
     pub header: Header,
 }
 
 impl Rom {
-    fn check_invalid(&self) -> Result<(), LoadRomError> {
-        if log_enabled!(Level::Warn) {
-            if self.header.rom_type == RomType::Nes2 {
-                println!("Warning! Unsupported rom type NES2.0!");
-            }
-        }
-
-        if self.header.region == Region::PAL {
-            return Err(LoadRomError::Unsupported(
-                "Unsupported region PAL!".to_string(),
-            ));
-        }
-
-        Ok(())
-    }
-
+    // Boards index straight into the $6000-$7FFF window, so always allocate
+    // at least 8KB
     pub fn fill_prg_ram(&mut self) {
-        self.prg_ram = vec![0u8; self.prg_ram_size];
+        let size = self.header.prg_ram_size + self.header.prg_nvram_size;
+        self.prg_ram = vec![0u8; size.max(PRG_RAM_PAGE_SIZE)];
     }
 }
 
@@ -179,14 +291,14 @@ impl fmt::Debug for Rom {
              Chr Ram Size (kb) {}",
             self.header,
             self.prg_rom.len() / 1024,
-            self.prg_ram_size / 1024,
+            (self.header.prg_ram_size + self.header.prg_nvram_size) / 1024,
             self.chr_rom.len() / 1024,
             self.chr_ram.len() / 1024,
         )
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum ScreenMode {
     FourScreen,
     Vertical,
@@ -194,7 +306,7 @@ pub enum ScreenMode {
     OneScreenSwap(ScreenBank),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum ScreenBank {
     Lower,
     Upper,
@@ -206,8 +318,20 @@ pub enum RomType {
     Nes2,
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Region {
     NTSC,
     PAL,
+    // Runs on both NTSC and PAL consoles
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem { ppu_type: u8, hardware_type: u8 },
+    Playchoice10,
+    // The extended console type from byte 13 of NES 2.0 headers
+    Extended(u8),
 }
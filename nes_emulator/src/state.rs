@@ -1,10 +1,13 @@
-use crate::mapper::MemType;
-use crate::mmu::Ram;
+use crate::apu::Apu;
+use crate::mapper::CartridgeState;
+use crate::mmu::MmuState;
 use crate::ppu::PpuState;
-use crate::rom::ScreenMode;
+use crate::timing::Timing;
 use anyhow::Result;
 use bincode::error::DecodeError;
 use bincode::error::EncodeError;
+use cpu_6502::cpu::Halt;
+use cpu_6502::cpu::InterruptState;
 use cpu_6502::cpu::Registers;
 use serde::Deserialize;
 use serde::Serialize;
@@ -12,28 +15,95 @@ use std::io::Read;
 use std::io::Write;
 use thiserror::Error;
 
+const MAGIC: [u8; 4] = *b"NESS";
+// Bump whenever the layout of State or anything in it changes
+pub const STATE_VERSION: u32 = 11;
+
 #[derive(Debug, Error)]
 pub enum StateFileError {
     #[error("Encountered an error while loading state: {0}")]
     LoadState(DecodeError),
     #[error("Encountered an error while saving state: {0}")]
     SaveState(EncodeError),
+    #[error("Not a save state file")]
+    BadMagic,
+    #[error(
+        "Save state version {0} is not supported, expected version {STATE_VERSION}"
+    )]
+    UnsupportedVersion(u32),
+    #[error(
+        "Save state is for a different rom: CRC32 {found:08X}, expected {expected:08X}"
+    )]
+    WrongRom { found: u32, expected: u32 },
+    #[error("Save state is incompatible with this emulator: {0}")]
+    Incompatible(String),
+    #[error("Can't save a state in the middle of an instruction")]
+    MidInstruction,
 }
 
+// Written in front of every state with fixed size integers, so it can be
+// checked before trying to decode the rest
 #[derive(Serialize, Deserialize)]
+struct FileHeader {
+    magic: [u8; 4],
+    version: u32,
+    rom_crc32: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct State {
-    pub ppu_state: PpuState,
-    pub screen_mode: ScreenMode,
-    pub chr_ram: Vec<u8>,
+    // Stored in the file header
+    #[serde(skip)]
+    pub rom_crc32: u32,
+    pub timing: Timing,
     pub cpu_regs: Registers,
-    pub mapper: MemType,
-    pub ram: Ram,
+    pub cpu_interrupts: InterruptState,
+    pub cpu_halted: Option<Halt>,
+    pub cpu_cycles: u64,
+    pub ppu_state: PpuState,
+    pub apu: Apu,
+    pub mmu_state: MmuState,
+    pub cartridge_state: CartridgeState,
+    // Saved by the debugger part way through a frame, so loading it carries
+    // on with that frame instead of starting a new one
+    pub mid_frame: bool,
 }
 
 impl State {
+    // The in memory encoding, without the file header. Used for snapshots
+    // that never leave the emulator, like rewinding.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, StateFileError> {
+        bincode::serde::encode_to_vec(self, bincode::config::standard())
+            .map_err(StateFileError::SaveState)
+    }
+
+    pub fn from_bytes(
+        data: &[u8],
+        rom_crc32: u32,
+    ) -> Result<State, StateFileError> {
+        match bincode::serde::decode_from_slice::<State, _>(
+            data,
+            bincode::config::standard(),
+        ) {
+            Ok((state, _)) => Ok(State { rom_crc32, ..state }),
+            Err(e) => Err(StateFileError::LoadState(e)),
+        }
+    }
+
     pub fn save<T: Write>(&self, writer: &mut T) -> Result<()> {
+        let header = FileHeader {
+            magic: MAGIC,
+            version: STATE_VERSION,
+            rom_crc32: self.rom_crc32,
+        };
+        bincode::serde::encode_into_std_write(
+            &header,
+            writer,
+            bincode::config::standard().with_fixed_int_encoding(),
+        )
+        .map_err(StateFileError::SaveState)?;
         match bincode::serde::encode_into_std_write(
-            &self,
+            self,
             writer,
             bincode::config::standard(),
         ) {
@@ -43,11 +113,28 @@ impl State {
     }
 
     pub fn load<T: Read>(reader: &mut T) -> Result<State> {
-        match bincode::serde::decode_from_std_read(
+        let header: FileHeader = bincode::serde::decode_from_std_read(
+            reader,
+            bincode::config::standard().with_fixed_int_encoding(),
+        )
+        .map_err(|_| StateFileError::BadMagic)?;
+        if header.magic != MAGIC {
+            return Err(StateFileError::BadMagic.into());
+        }
+        if header.version != STATE_VERSION {
+            return Err(
+                StateFileError::UnsupportedVersion(header.version).into()
+            );
+        }
+
+        match bincode::serde::decode_from_std_read::<State, _, _>(
             reader,
             bincode::config::standard(),
         ) {
-            Ok(state) => Ok(state),
+            Ok(state) => Ok(State {
+                rom_crc32: header.rom_crc32,
+                ..state
+            }),
             Err(e) => Err(StateFileError::LoadState(e).into()),
         }
     }
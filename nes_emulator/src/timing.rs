@@ -0,0 +1,150 @@
+use crate::rom::Region;
+use serde::Deserialize;
+use serde::Serialize;
+
+const NTSC_CPU_CLOCK_SPEED: usize = 1789773; // measured in hertz
+const PAL_CPU_CLOCK_SPEED: usize = 1662607; // measured in hertz
+const DENDY_CPU_CLOCK_SPEED: usize = 1773448; // measured in hertz
+
+const NTSC_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_NOISE_PERIODS: [u16; 16] = [
+    4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+];
+
+const NTSC_DMC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_DMC_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+// Frame sequencer steps, measured in CPU cycles since the sequence started
+pub struct FrameSteps {
+    pub quarter_frame_1: u16,
+    pub half_frame_1: u16,
+    pub quarter_frame_3: u16,
+    pub four_step_irq: u16,
+    pub four_step_half_frame: u16,
+    pub four_step_len: u16,
+    pub five_step_half_frame: u16,
+    pub five_step_len: u16,
+}
+
+const NTSC_FRAME_STEPS: FrameSteps = FrameSteps {
+    quarter_frame_1: 7457,
+    half_frame_1: 14913,
+    quarter_frame_3: 22371,
+    four_step_irq: 29828,
+    four_step_half_frame: 29829,
+    four_step_len: 29830,
+    five_step_half_frame: 37281,
+    five_step_len: 37282,
+};
+
+const PAL_FRAME_STEPS: FrameSteps = FrameSteps {
+    quarter_frame_1: 8313,
+    half_frame_1: 16627,
+    quarter_frame_3: 24939,
+    four_step_irq: 33252,
+    four_step_half_frame: 33253,
+    four_step_len: 33254,
+    five_step_half_frame: 41565,
+    five_step_len: 41566,
+};
+
+// The console variant being emulated, see
+// https://www.nesdev.org/wiki/Cycle_reference_chart
+// The Dendy is a PAL famiclone with an NTSC style CPU and APU, but a PAL
+// length frame with a longer post render period before vblank.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Timing {
+    // Multi region roms run on NTSC consoles
+    pub fn from_region(region: Region) -> Timing {
+        match region {
+            Region::NTSC | Region::MultiRegion => Timing::Ntsc,
+            Region::PAL => Timing::Pal,
+            Region::Dendy => Timing::Dendy,
+        }
+    }
+
+    pub fn cpu_clock_speed(&self) -> usize {
+        match self {
+            Timing::Ntsc => NTSC_CPU_CLOCK_SPEED,
+            Timing::Pal => PAL_CPU_CLOCK_SPEED,
+            Timing::Dendy => DENDY_CPU_CLOCK_SPEED,
+        }
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        let dots_per_frame = 341.0 * (self.prerender_scanline() as f64 + 1.0);
+        let (dots, cycles) = self.ppu_dots_per_cpu_cycle();
+        self.cpu_clock_speed() as f64 * dots as f64
+            / (cycles as f64 * dots_per_frame)
+    }
+
+    // As a fraction, PAL runs 3.2 dots per cycle
+    pub fn ppu_dots_per_cpu_cycle(&self) -> (usize, usize) {
+        match self {
+            Timing::Ntsc | Timing::Dendy => (3, 1),
+            Timing::Pal => (16, 5),
+        }
+    }
+
+    // The last scanline of the frame
+    pub fn prerender_scanline(&self) -> u16 {
+        match self {
+            Timing::Ntsc => 261,
+            Timing::Pal | Timing::Dendy => 311,
+        }
+    }
+
+    // The scanline the vblank flag gets set on
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Timing::Ntsc | Timing::Pal => 241,
+            Timing::Dendy => 291,
+        }
+    }
+
+    // The PPU I/O latch takes about 600ms to decay, counted in frames
+    pub fn io_decay_frames(&self) -> u8 {
+        match self {
+            Timing::Ntsc => 36,
+            Timing::Pal | Timing::Dendy => 30,
+        }
+    }
+
+    // Only the NTSC PPU drops a dot on odd frames when rendering
+    pub fn skips_odd_frame_dot(&self) -> bool {
+        *self == Timing::Ntsc
+    }
+
+    pub fn noise_periods(&self) -> [u16; 16] {
+        match self {
+            Timing::Ntsc | Timing::Dendy => NTSC_NOISE_PERIODS,
+            Timing::Pal => PAL_NOISE_PERIODS,
+        }
+    }
+
+    pub fn dmc_rates(&self) -> [u16; 16] {
+        match self {
+            Timing::Ntsc | Timing::Dendy => NTSC_DMC_RATES,
+            Timing::Pal => PAL_DMC_RATES,
+        }
+    }
+
+    pub fn frame_steps(&self) -> &'static FrameSteps {
+        match self {
+            Timing::Ntsc | Timing::Dendy => &NTSC_FRAME_STEPS,
+            Timing::Pal => &PAL_FRAME_STEPS,
+        }
+    }
+}
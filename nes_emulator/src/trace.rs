@@ -0,0 +1,167 @@
+use crate::NesEmulator;
+use crate::mmu::Mmu;
+use cpu_6502::Memory;
+use cpu_6502::disasm::AddrMode;
+use cpu_6502::disasm::Instruction;
+use cpu_6502::disasm::decode_memory;
+use std::io::Write;
+
+// Nintendulator doesn't read the PPU and APU registers when it writes its
+// log, they always show up as $FF
+struct TraceView<'a>(&'a Mmu);
+
+impl Memory for TraceView<'_> {
+    fn ld8(&mut self, address: u16) -> u8 {
+        match address {
+            0x2000..=0x401F => 0xFF,
+            _ => self.0.peek(address),
+        }
+    }
+
+    fn ld16(&mut self, address: u16) -> u16 {
+        let l_byte = self.ld8(address);
+        let r_byte = self.ld8(address.wrapping_add(1));
+        (r_byte as u16) << 8 | (l_byte as u16)
+    }
+
+    fn store(&mut self, _address: u16, _val: u8) {}
+}
+
+impl NesEmulator {
+    // Writes a line for every instruction from now on, in the format of
+    // Nintendulator's nestest.log. Tracing stops if the sink fails.
+    pub fn start_trace(&mut self, out: Box<dyn Write>) {
+        self.trace = Some(out);
+    }
+
+    pub fn stop_trace(&mut self) {
+        if let Some(mut out) = self.trace.take()
+            && let Err(e) = out.flush()
+        {
+            log::error!("Failed to flush the trace: {}", e);
+        }
+    }
+
+    pub(crate) fn write_trace(&mut self) {
+        let line = self.trace_line();
+        if let Some(out) = &mut self.trace
+            && let Err(e) = writeln!(out, "{}", line)
+        {
+            log::error!("Failed to write the trace, stopping it: {}", e);
+            self.trace = None;
+        }
+    }
+
+    // The instruction about to run and the machine state before it: PC,
+    // instruction bytes, disassembly, registers, PPU scanline and dot, and
+    // CPU cycles since power on
+    pub fn trace_line(&self) -> String {
+        let regs = self.cpu.regs;
+        let mut view = TraceView(&self.mmu);
+        let instruction = decode_memory(&mut view, regs.pc.get_addr());
+        let bytes: Vec<String> = (0..instruction.size())
+            .map(|i| {
+                format!("{:02X}", view.ld8(instruction.addr.wrapping_add(i)))
+            })
+            .collect();
+        let disassembly = disassemble(&instruction, self, &mut view);
+
+        format!(
+            "{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} \
+             SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+            instruction.addr,
+            bytes.join(" "),
+            if instruction.official { ' ' } else { '*' },
+            disassembly,
+            regs.acc,
+            regs.x,
+            regs.y,
+            regs.flags.as_byte(),
+            regs.sp,
+            self.mmu.ppu.scanline(),
+            self.mmu.ppu.dot(),
+            self.cycles()
+        )
+    }
+}
+
+// The instruction with the addresses it touches and the values there
+fn disassemble(
+    instruction: &Instruction,
+    nes: &NesEmulator,
+    view: &mut TraceView,
+) -> String {
+    let regs = &nes.cpu.regs;
+    // nestest.log calls ISC by its other name
+    let mnemonic = match instruction.mnemonic {
+        "ISC" => "ISB",
+        mnemonic => mnemonic,
+    };
+    let operand = instruction.operand;
+    match instruction.mode {
+        AddrMode::Imp => return mnemonic.to_string(),
+        AddrMode::Acc => return format!("{} A", mnemonic),
+        AddrMode::Imm => return format!("{} #${:02X}", mnemonic, operand),
+        _ => (),
+    }
+    let addr = instruction
+        .effective_address(regs, view)
+        .expect("The other modes all have an address");
+    let value = view.ld8(addr);
+
+    match instruction.mode {
+        AddrMode::ZP => {
+            format!("{} ${:02X} = {:02X}", mnemonic, operand, value)
+        }
+        AddrMode::ZPX | AddrMode::ZPY => format!(
+            "{} ${:02X},{} @ {:02X} = {:02X}",
+            mnemonic,
+            operand,
+            index_name(instruction.mode),
+            addr,
+            value
+        ),
+        AddrMode::Abs if matches!(mnemonic, "JMP" | "JSR") => {
+            format!("{} ${:04X}", mnemonic, operand)
+        }
+        AddrMode::Abs => {
+            format!("{} ${:04X} = {:02X}", mnemonic, operand, value)
+        }
+        AddrMode::AbsX | AddrMode::AbsY => format!(
+            "{} ${:04X},{} @ {:04X} = {:02X}",
+            mnemonic,
+            operand,
+            index_name(instruction.mode),
+            addr,
+            value
+        ),
+        AddrMode::Ind => {
+            format!("{} (${:04X}) = {:04X}", mnemonic, operand, addr)
+        }
+        AddrMode::IndX => format!(
+            "{} (${:02X},X) @ {:02X} = {:04X} = {:02X}",
+            mnemonic,
+            operand,
+            (operand as u8).wrapping_add(regs.x),
+            addr,
+            value
+        ),
+        AddrMode::IndY => format!(
+            "{} (${:02X}),Y = {:04X} @ {:04X} = {:02X}",
+            mnemonic,
+            operand,
+            addr.wrapping_sub(regs.y as u16),
+            addr,
+            value
+        ),
+        // Branches
+        _ => format!("{} ${:04X}", mnemonic, addr),
+    }
+}
+
+fn index_name(mode: AddrMode) -> char {
+    match mode {
+        AddrMode::ZPY | AddrMode::AbsY => 'Y',
+        _ => 'X',
+    }
+}
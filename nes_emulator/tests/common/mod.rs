@@ -0,0 +1,101 @@
+// Fixtures shared by the integration tests. Each test file only uses some of
+// them.
+#![allow(dead_code)]
+
+use nes_emu::NesEmulator;
+use nes_emu::rom::Rom;
+use nes_emu::rom::load_rom;
+
+// An iNES header for NROM with 16KB of PRG ROM and 8KB of CHR ROM
+pub const HEADER: [u8; 16] =
+    [b'N', b'E', b'S', 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+// Builds the images the tests run. PRG ROM is 16KB of NOPs with the program
+// at $8000, and every vector points at $8000 until it gets changed.
+pub struct TestRom {
+    header: [u8; 16],
+    prg: Vec<u8>,
+    chr_fill: u8,
+}
+
+impl TestRom {
+    pub fn new(program: &[u8]) -> TestRom {
+        TestRom {
+            header: HEADER,
+            prg: vec![0xEA; 0x4000],
+            chr_fill: 0,
+        }
+        .code(0x8000, program)
+        .code(0xFFFA, &[0x00, 0x80, 0x00, 0x80, 0x00, 0x80])
+    }
+
+    // PRG ROM is mirrored at $C000, so addresses in either half work
+    pub fn code(mut self, addr: u16, code: &[u8]) -> TestRom {
+        let start = (addr & 0x3FFF) as usize;
+        self.prg[start..start + code.len()].copy_from_slice(code);
+        self
+    }
+
+    pub fn nmi(self, addr: u16) -> TestRom {
+        self.code(0xFFFA, &addr.to_le_bytes())
+    }
+
+    pub fn irq(self, addr: u16) -> TestRom {
+        self.code(0xFFFE, &addr.to_le_bytes())
+    }
+
+    // Mirroring, four screen and the low nibble of the mapper number
+    pub fn flag6(mut self, flag6: u8) -> TestRom {
+        self.header[6] = flag6;
+        self
+    }
+
+    // Changing the CHR ROM also changes the rom's hash
+    pub fn chr_fill(mut self, chr_fill: u8) -> TestRom {
+        self.chr_fill = chr_fill;
+        self
+    }
+
+    pub fn rom(&self) -> Rom {
+        let mut raw = self.header.to_vec();
+        raw.extend(&self.prg);
+        raw.extend(vec![self.chr_fill; 0x2000]);
+        load_rom(&raw).expect("Expected a valid rom")
+    }
+
+    pub fn nes(&self) -> NesEmulator {
+        NesEmulator::new(self.rom()).expect("Expected a supported rom")
+    }
+}
+
+// Spins on JMP $8000 without touching the PPU
+pub fn spinning_nes() -> NesEmulator {
+    TestRom::new(&[0x4C, 0x00, 0x80]).nes()
+}
+
+// Adds up the A button into a counter, and keeps writing the counter to VRAM
+// with rendering on
+pub fn button_counter_nes() -> NesEmulator {
+    TestRom::new(&[
+        0xA9, 0x1E, // LDA #$1E
+        0x8D, 0x01, 0x20, // STA $2001
+        0xA9, 0x01, // LDA #$01
+        0x8D, 0x16, 0x40, // STA $4016
+        0xA9, 0x00, // LDA #$00
+        0x8D, 0x16, 0x40, // STA $4016
+        0xAD, 0x16, 0x40, // LDA $4016
+        0x29, 0x01, // AND #$01
+        0x18, // CLC
+        0x65, 0x01, // ADC $01
+        0x85, 0x01, // STA $01
+        0x8D, 0x07, 0x20, // STA $2007
+        0x4C, 0x05, 0x80, // JMP $8005
+    ])
+    .chr_fill(0x55)
+    .nes()
+}
+
+// The picture and the cycle count, enough to tell two runs apart
+pub fn snapshot(nes: &NesEmulator) -> (Vec<u8>, u64) {
+    (nes.cur_frame().to_vec(), nes.mmu.cycles)
+}
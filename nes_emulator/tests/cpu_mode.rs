@@ -0,0 +1,162 @@
+extern crate nes_emu;
+mod common;
+use common::TestRom;
+use nes_emu::CpuMode;
+use nes_emu::NesEmulator;
+use nes_emu::state::StateFileError;
+
+// An NROM image with NMIs on that copies page 2 to OAM every vblank and
+// keeps reading PPUSTATUS in its main loop
+fn busy_nes() -> NesEmulator {
+    let main = [
+        0xA9, 0x80, // LDA #$80
+        0x8D, 0x00, 0x20, // STA $2000
+        0xAD, 0x02, 0x20, // LDA $2002
+        0xE8, // INX
+        0x9D, 0x00, 0x02, // STA $0200,X
+        0x4C, 0x05, 0x80, // JMP $8005
+    ];
+    let nmi = [
+        0xE6, 0x00, // INC $00
+        0xA9, 0x02, // LDA #$02
+        0x8D, 0x14, 0x40, // STA $4014
+        0x40, // RTI
+    ];
+    TestRom::new(&main).code(0x8100, &nmi).nmi(0x8100).nes()
+}
+
+#[test]
+fn modes_match() {
+    let mut instruction = busy_nes();
+    let mut cycle = busy_nes();
+    cycle.set_cpu_mode(CpuMode::Cycle);
+    assert_eq!(CpuMode::Instruction, instruction.cpu_mode());
+    assert_eq!(CpuMode::Cycle, cycle.cpu_mode());
+
+    for _ in 0..4 {
+        instruction.next_frame().expect("Expected the frame to run");
+        cycle.next_frame().expect("Expected the frame to run");
+        assert_eq!(instruction.get_pixel_buffer(), cycle.get_pixel_buffer());
+        assert_eq!(instruction.cycles(), cycle.cycles());
+        assert_eq!(
+            format!("{:?}", instruction.cpu.regs),
+            format!("{:?}", cycle.cpu.regs)
+        );
+    }
+    assert_eq!(3, cycle.mmu.peek(0x00));
+}
+
+#[test]
+fn ticking() {
+    let mut nes = busy_nes();
+    // LDA #$80, STA $2000
+    assert!(!nes.tick());
+    assert!(nes.tick());
+    let start = nes.cycles();
+    // PC moves past every byte of the instruction as it gets fetched
+    for pc in [0x8003, 0x8004, 0x8005] {
+        assert!(!nes.tick());
+        assert!(nes.cpu.mid_instruction());
+        assert_eq!(pc, nes.cpu.regs.pc.get_addr());
+    }
+    let Err(e) = nes.get_state() else {
+        panic!("Expected an error");
+    };
+    assert!(matches!(
+        e.downcast_ref::<StateFileError>(),
+        Some(StateFileError::MidInstruction)
+    ));
+
+    assert!(nes.tick());
+    assert_eq!(start + 4, nes.cycles());
+    assert_eq!(0x8005, nes.cpu.regs.pc.get_addr());
+    assert!(nes.get_state().is_ok());
+}
+
+// Copies page 2 to OAM and then runs a NOP
+fn dma_nes() -> NesEmulator {
+    let main = [
+        0xA9, 0x02, // LDA #$02
+        0x8D, 0x14, 0x40, // STA $4014
+        0xEA, // NOP
+    ];
+    TestRom::new(&main).nes()
+}
+
+#[test]
+fn dma_halt_ticks() {
+    // A step runs the whole DMA along with the NOP it halted
+    let mut instruction = dma_nes();
+    instruction.step().expect("Expected the step to run");
+    instruction.step().expect("Expected the step to run");
+    let start = instruction.cycles();
+    instruction.step().expect("Expected the step to run");
+    let dma = instruction.cycles() - start - 2;
+    assert!(dma == 513 || dma == 514);
+
+    // Ticking shows the halt landing on the cycle right after the write,
+    // the NOP's opcode fetch, and every DMA cycle after it
+    let mut cycle = dma_nes();
+    cycle.set_cpu_mode(CpuMode::Cycle);
+    while !cycle.tick() {}
+    while !cycle.tick() {}
+    assert_eq!(start, cycle.cycles());
+    for ticks in 1..=dma {
+        assert!(!cycle.tick());
+        assert_eq!(start + ticks, cycle.cycles());
+        assert!(cycle.cpu.mid_instruction());
+        assert_eq!(0x8005, cycle.cpu.regs.pc.get_addr());
+    }
+    assert!(cycle.get_state().is_err());
+    assert!(!cycle.tick());
+    assert_eq!(0x8006, cycle.cpu.regs.pc.get_addr());
+    assert!(cycle.tick());
+    assert_eq!(instruction.cycles(), cycle.cycles());
+}
+
+// Starts the default one byte DMC sample and then spins
+fn dmc_nes() -> NesEmulator {
+    let main = [
+        0xA9, 0x10, // LDA #$10
+        0x8D, 0x15, 0x40, // STA $4015
+        0x4C, 0x05, 0x80, // JMP $8005
+    ];
+    TestRom::new(&main).nes()
+}
+
+#[test]
+fn dmc_fetch_ticks() {
+    // The sample fetch halts the JMP's opcode fetch, then takes a dummy
+    // cycle and maybe an alignment one before the get cycle it reads on
+    let mut instruction = dmc_nes();
+    instruction.step().expect("Expected the step to run");
+    instruction.step().expect("Expected the step to run");
+    assert!(instruction.mmu.apu.dmc.pending_fetch().is_some());
+    let start = instruction.cycles();
+    instruction.step().expect("Expected the step to run");
+    let stall = instruction.cycles() - start - 3;
+    assert!(stall == 3 || stall == 4);
+    assert!(instruction.mmu.apu.dmc.pending_fetch().is_none());
+
+    // Every stall cycle is a tick of its own that the CPU counts too
+    let mut cycle = dmc_nes();
+    cycle.set_cpu_mode(CpuMode::Cycle);
+    while !cycle.tick() {}
+    while !cycle.tick() {}
+    assert_eq!(start, cycle.cycles());
+    let cpu_start = cycle.cpu.cycles();
+    for ticks in 1..=stall {
+        assert!(!cycle.tick());
+        assert_eq!(start + ticks, cycle.cycles());
+        assert_eq!(cpu_start + ticks, cycle.cpu.cycles());
+        assert!(cycle.cpu.mid_instruction());
+        assert_eq!(0x8005, cycle.cpu.regs.pc.get_addr());
+    }
+    assert!(cycle.mmu.apu.dmc.pending_fetch().is_none());
+    assert!(!cycle.tick());
+    assert_eq!(0x8006, cycle.cpu.regs.pc.get_addr());
+    assert!(!cycle.tick());
+    assert!(cycle.tick());
+    assert_eq!(instruction.cycles(), cycle.cycles());
+    assert_eq!(cycle.cycles() - start, cycle.cpu.cycles() - cpu_start);
+}
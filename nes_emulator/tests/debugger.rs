@@ -0,0 +1,246 @@
+extern crate nes_emu;
+mod common;
+use common::TestRom;
+use nes_emu::NesEmulator;
+use nes_emu::debugger::Access;
+use nes_emu::debugger::Breakpoint;
+use nes_emu::debugger::Comparison;
+use nes_emu::debugger::Condition;
+use nes_emu::debugger::DebuggerError;
+use nes_emu::debugger::Register;
+use nes_emu::debugger::RunCommand;
+use nes_emu::debugger::StopReason;
+use nes_emu::debugger::WatchHit;
+use nes_emu::debugger::Watchpoint;
+
+// An NROM image that counts up in X, storing it to $0200 after a call to a
+// subroutine that calls another one
+fn counting_nes() -> NesEmulator {
+    let program = [
+        0xA2, 0x00, // $8000 LDX #$00
+        0x20, 0x10, 0x80, // $8002 JSR $8010
+        0xE8, // $8005 INX
+        0x8E, 0x00, 0x02, // $8006 STX $0200
+        0xAD, 0x00, 0x02, // $8009 LDA $0200
+        0x4C, 0x02, 0x80, // $800C JMP $8002
+    ];
+    let outer = [
+        0xA9, 0x05, // $8010 LDA #$05
+        0x20, 0x20, 0x80, // $8012 JSR $8020
+        0x60, // $8015 RTS
+    ];
+    let inner = [
+        0xA0, 0x07, // $8020 LDY #$07
+        0x60, // $8022 RTS
+    ];
+    TestRom::new(&program)
+        .code(0x8010, &outer)
+        .code(0x8020, &inner)
+        .nes()
+}
+
+fn pc(nes: &NesEmulator) -> u16 {
+    nes.cpu.regs.pc.get_addr()
+}
+
+// Stops in front of the JSR at $8002
+fn at_jsr() -> NesEmulator {
+    let mut nes = counting_nes();
+    let id = nes.add_breakpoint(Breakpoint::at(0x8002));
+    assert_eq!(StopReason::Breakpoint(id), nes.run(RunCommand::Frame));
+    nes.remove_breakpoint(id);
+    nes
+}
+
+#[test]
+fn breakpoint_stops_the_frame() {
+    let mut nes = counting_nes();
+    let id = nes.add_breakpoint(Breakpoint::at(0x8005));
+    assert_eq!(
+        StopReason::Breakpoint(id),
+        nes.next_frame().expect("Expected the frame to run")
+    );
+    assert_eq!(0x8005, pc(&nes));
+    assert_eq!(0, nes.frame_count());
+
+    // Running again moves past the breakpoint and hits it on the next loop
+    assert_eq!(StopReason::Breakpoint(id), nes.run(RunCommand::Frame));
+    assert_eq!(0x8005, pc(&nes));
+    assert_eq!(1, nes.cpu.regs.x);
+
+    nes.remove_breakpoint(id);
+    assert_eq!(
+        StopReason::FrameEnd,
+        nes.next_frame().expect("Expected the frame to run")
+    );
+    assert_eq!(1, nes.frame_count());
+}
+
+#[test]
+fn breakpoint_on_the_first_instruction() {
+    // The first instruction of a frame gets checked like any other
+    let mut nes = counting_nes();
+    assert_eq!(
+        StopReason::FrameEnd,
+        nes.next_frame().expect("Expected the frame to run")
+    );
+    let start = pc(&nes);
+    let cycles = nes.cycles();
+    let id = nes.add_breakpoint(Breakpoint::at(start));
+    assert_eq!(
+        StopReason::Breakpoint(id),
+        nes.next_frame().expect("Expected the frame to run")
+    );
+    assert_eq!(start, pc(&nes));
+    assert_eq!(cycles, nes.cycles());
+    assert_eq!(1, nes.frame_count());
+
+    // So does the one after a step
+    nes.remove_breakpoint(id);
+    assert_eq!(StopReason::Step, nes.run(RunCommand::StepInto));
+    let cycles = nes.cycles();
+    let id = nes.add_breakpoint(Breakpoint::at(pc(&nes)));
+    assert_eq!(StopReason::Breakpoint(id), nes.run(RunCommand::Frame));
+    assert_eq!(cycles, nes.cycles());
+}
+
+#[test]
+fn conditional_breakpoint() {
+    let mut nes = counting_nes();
+    let condition = Condition::new(Register::X, Comparison::Eq, 3);
+    let id =
+        nes.add_breakpoint(Breakpoint::at(0x8006).with_condition(condition));
+    assert_eq!(StopReason::Breakpoint(id), nes.run(RunCommand::Frame));
+    assert_eq!(0x8006, pc(&nes));
+    assert_eq!(3, nes.cpu.regs.x);
+
+    let mut nes = counting_nes();
+    let id = nes.add_breakpoint(Breakpoint::when(Condition::new(
+        Register::Y,
+        Comparison::Ge,
+        7,
+    )));
+    assert_eq!(StopReason::Breakpoint(id), nes.run(RunCommand::Frame));
+    assert_eq!(0x8022, pc(&nes));
+}
+
+#[test]
+fn watchpoints() {
+    let mut nes = counting_nes();
+    let id = nes.add_watchpoint(Watchpoint::new(0x0200, 0x0200, Access::Write));
+    let expected = WatchHit {
+        id,
+        address: 0x0200,
+        value: 1,
+        write: true,
+    };
+    assert_eq!(StopReason::Watchpoint(expected), nes.run(RunCommand::Frame));
+    assert_eq!(0x8009, pc(&nes));
+    nes.remove_watchpoint(id);
+
+    // Watchpoints cover a range of addresses
+    let id = nes.add_watchpoint(Watchpoint::new(0x01FF, 0x0201, Access::Read));
+    let expected = WatchHit {
+        id,
+        address: 0x0200,
+        value: 1,
+        write: false,
+    };
+    assert_eq!(StopReason::Watchpoint(expected), nes.run(RunCommand::Frame));
+    assert_eq!(0x800C, pc(&nes));
+}
+
+#[test]
+fn stepping() {
+    let mut nes = at_jsr();
+    assert_eq!(StopReason::Step, nes.run(RunCommand::StepInto));
+    assert_eq!(0x8010, pc(&nes));
+
+    let mut nes = at_jsr();
+    assert_eq!(StopReason::Step, nes.run(RunCommand::StepOver));
+    assert_eq!(0x8005, pc(&nes));
+    assert_eq!(5, nes.cpu.regs.acc);
+    assert_eq!(7, nes.cpu.regs.y);
+
+    // Stepping over anything else is a single step
+    assert_eq!(StopReason::Step, nes.run(RunCommand::StepOver));
+    assert_eq!(0x8006, pc(&nes));
+}
+
+#[test]
+fn step_out_skips_nested_calls() {
+    let mut nes = at_jsr();
+    nes.run(RunCommand::StepInto);
+    nes.run(RunCommand::StepInto);
+    assert_eq!(0x8012, pc(&nes));
+    assert_eq!(StopReason::Step, nes.run(RunCommand::StepOut));
+    assert_eq!(0x8005, pc(&nes));
+    assert_eq!(7, nes.cpu.regs.y);
+}
+
+#[test]
+fn run_to_ppu_position() {
+    let mut nes = counting_nes();
+    let reason = nes.run(RunCommand::RunTo {
+        scanline: 100,
+        dot: 200,
+    });
+    assert_eq!(StopReason::Position, reason);
+    assert_eq!(100, nes.mmu.ppu.scanline());
+    // Stops on the first instruction boundary past the dot
+    assert!((200..230).contains(&nes.mmu.ppu.dot()));
+
+    // Positions behind the PPU wrap around to the next frame
+    let reason = nes.run(RunCommand::RunTo {
+        scanline: 10,
+        dot: 0,
+    });
+    assert_eq!(StopReason::Position, reason);
+    assert_eq!(10, nes.mmu.ppu.scanline());
+    assert_eq!(1, nes.frame_count());
+
+    let reason = nes.run(RunCommand::RunTo {
+        scanline: 1000,
+        dot: 0,
+    });
+    assert_eq!(StopReason::FrameEnd, reason);
+}
+
+#[test]
+fn console_commands() {
+    let mut nes = counting_nes();
+    let output = nes.debug_command("b $8006 if x == 2").unwrap();
+    assert_eq!("Breakpoint 0 at $8006 if X == $2", output);
+    let output = nes.debug_command("watch w 0200").unwrap();
+    assert_eq!("Watchpoint 0 on w $0200", output);
+    let output = nes.debug_command("list").unwrap();
+    assert_eq!(
+        "Breakpoint 0 at $8006 if X == $2\nWatchpoint 0 on w $0200\n",
+        output
+    );
+
+    let output = nes.debug_command("c").unwrap();
+    assert!(output.starts_with("Watchpoint 0: wrote $01 to $0200\n"));
+    nes.debug_command("unwatch 0").unwrap();
+    let output = nes.debug_command("continue").unwrap();
+    assert!(output.starts_with("Breakpoint 0\n"));
+    assert_eq!(2, nes.cpu.regs.x);
+
+    let output = nes.debug_command("s").unwrap();
+    assert!(output.contains("PC:8009"));
+    nes.debug_command("d 0").unwrap();
+    nes.debug_command("goto 20 5").unwrap();
+    assert_eq!(20, nes.mmu.ppu.scanline());
+
+    for bad in [
+        "",
+        "b",
+        "b zz",
+        "b 8000 if q == 1",
+        "delete 7",
+        "frobnicate",
+    ] {
+        let e = nes.debug_command(bad).expect_err("Expected an error");
+        assert!(e.downcast_ref::<DebuggerError>().is_some());
+    }
+}
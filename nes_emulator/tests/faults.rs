@@ -0,0 +1,115 @@
+extern crate nes_emu;
+mod common;
+use common::HEADER;
+use common::TestRom;
+use nes_emu::EmulationError;
+use nes_emu::Fault;
+use nes_emu::NesEmulator;
+use nes_emu::debugger::RunCommand;
+use nes_emu::debugger::StopReason;
+use nes_emu::rom::LoadRomError;
+use nes_emu::rom::load_rom;
+
+// An NROM image that turns on NMIs, counts a few frames in its NMI handler
+// and then runs into a JAM
+fn jamming_nes() -> NesEmulator {
+    let main = [
+        0xA9, 0x80, // LDA #$80
+        0x8D, 0x00, 0x20, // STA $2000
+        0xA5, 0x00, // LDA $00
+        0xC9, 0x03, // CMP #$03
+        0xD0, 0xFA, // BNE $8005
+        0x02, // JAM
+    ];
+    let nmi = [
+        0xE6, 0x00, // INC $00
+        0x40, // RTI
+    ];
+    TestRom::new(&main).code(0x8100, &nmi).nmi(0x8100).nes()
+}
+
+// Runs frames until the CPU jams
+fn run_to_jam(nes: &mut NesEmulator) -> EmulationError {
+    for _ in 0..10 {
+        if let Err(e) = nes.next_frame() {
+            return e;
+        }
+    }
+    panic!("Expected the CPU to jam");
+}
+
+#[test]
+fn jam_is_reported_where_it_happened() {
+    let mut nes = jamming_nes();
+    let e = run_to_jam(&mut nes);
+    assert_eq!(Fault::Jam, e.fault);
+    assert_eq!(0x800C, e.regs.pc.get_addr());
+    assert_eq!(nes.mmu.ppu.scanline(), e.scanline);
+    assert_eq!(nes.mmu.ppu.dot(), e.dot);
+    assert!(e.to_string().starts_with("CPU jammed at PC:800C"));
+    assert!(nes.jammed());
+}
+
+#[test]
+fn jam_halts_the_cpu() {
+    let mut nes = jamming_nes();
+    run_to_jam(&mut nes);
+
+    // The PPU keeps going, NMIs no longer get serviced and the fault
+    // doesn't get reported again
+    let frame = nes.frame_count();
+    for _ in 0..2 {
+        nes.next_frame().expect("Expected the frame to run");
+    }
+    assert_eq!(frame + 2, nes.frame_count());
+    assert_eq!(3, nes.mmu.peek(0x00));
+    assert!(nes.jammed());
+
+    nes.reset();
+    assert!(!nes.jammed());
+}
+
+#[test]
+fn debugger_stops_on_faults() {
+    let mut nes = jamming_nes();
+    let mut reason = StopReason::FrameEnd;
+    while reason == StopReason::FrameEnd {
+        reason = nes.run(RunCommand::Frame);
+    }
+    assert_eq!(StopReason::Fault(Fault::Jam), reason);
+}
+
+#[test]
+fn save_states_keep_the_jam() {
+    let mut nes = jamming_nes();
+    run_to_jam(&mut nes);
+    let jammed = nes.get_state().expect("Expected a valid state");
+    nes.reset();
+    nes.load_state(jammed).expect("Expected the state to load");
+    assert!(nes.jammed());
+}
+
+#[test]
+fn unsupported_roms_are_errors() {
+    let mut raw = HEADER.to_vec();
+    // Mapper 15
+    raw[6] = 0xF0;
+    raw.extend(vec![0; 0x6000]);
+    let rom = load_rom(&raw).expect("Expected a valid rom");
+    assert!(matches!(
+        NesEmulator::new(rom),
+        Err(LoadRomError::UnsupportedMapper(15, 0))
+    ));
+
+    // A NES 2.0 header with 8KB of PRG ROM, 2^13 * 1
+    let mut raw = HEADER.to_vec();
+    raw[4] = 13 << 2;
+    raw[7] = 0x08;
+    raw[9] = 0x0F;
+    raw.extend(vec![0; 0x2000 + 0x2000]);
+    let rom = load_rom(&raw).expect("Expected a valid rom");
+    assert!(matches!(
+        NesEmulator::new(rom),
+        Err(LoadRomError::Unsupported(_))
+    ));
+}
@@ -0,0 +1,63 @@
+extern crate nes_emu;
+mod common;
+use common::TestRom;
+use nes_emu::NesEmulator;
+use nes_emu::controller::InputPolls;
+
+// An NROM image that polls the controllers in its NMI handler on every other
+// frame, reading $4016 twice and $4017 once
+fn every_other_frame_nes() -> NesEmulator {
+    let program = [
+        0xA9, 0x80, // LDA #$80
+        0x8D, 0x00, 0x20, // STA $2000
+        0x4C, 0x05, 0x80, // JMP $8005
+        // NMI
+        0xE8, // INX
+        0x8A, // TXA
+        0x29, 0x01, // AND #$01
+        0xF0, 0x13, // BEQ RTI
+        0xA9, 0x01, // LDA #$01
+        0x8D, 0x16, 0x40, // STA $4016
+        0xA9, 0x00, // LDA #$00
+        0x8D, 0x16, 0x40, // STA $4016
+        0xAD, 0x16, 0x40, // LDA $4016
+        0xAD, 0x16, 0x40, // LDA $4016
+        0xAD, 0x17, 0x40, // LDA $4017
+        0x40, // RTI
+    ];
+    TestRom::new(&program).nmi(0x8008).nes()
+}
+
+#[test]
+fn lag_frames_are_counted() {
+    let mut nes = every_other_frame_nes();
+    // The first frame ends before the first NMI
+    nes.next_frame().expect("Expected the frame to run");
+    assert!(nes.lag_frame());
+    assert_eq!(1, nes.lag_count());
+
+    let mut lag_frames = Vec::new();
+    for _ in 0..10 {
+        nes.next_frame().expect("Expected the frame to run");
+        lag_frames.push(nes.lag_frame());
+    }
+    let expected: Vec<bool> = (0..10).map(|i| i % 2 == 1).collect();
+    assert_eq!(expected, lag_frames);
+    assert_eq!(6, nes.lag_count());
+}
+
+#[test]
+fn polls_are_reported() {
+    let mut nes = every_other_frame_nes();
+    nes.next_frame().expect("Expected the frame to run");
+    nes.next_frame().expect("Expected the frame to run");
+    assert_eq!(
+        InputPolls {
+            reads: [2, 1],
+            strobes: [1, 1],
+        },
+        nes.input_polls()
+    );
+    nes.next_frame().expect("Expected the frame to run");
+    assert_eq!(InputPolls::default(), nes.input_polls());
+}
@@ -0,0 +1,96 @@
+extern crate nes_emu;
+mod common;
+use common::TestRom;
+use nes_emu::NesEmulator;
+
+// An NROM image with the NMI handler at $8100 and the IRQ handler at $8200
+fn interrupt_nes(main: &[u8], nmi: &[u8], irq: &[u8]) -> NesEmulator {
+    TestRom::new(main)
+        .code(0x8100, nmi)
+        .code(0x8200, irq)
+        .nmi(0x8100)
+        .irq(0x8200)
+        .nes()
+}
+
+const ENABLE_NMI: [u8; 8] = [
+    0xA9, 0x80, // LDA #$80
+    0x8D, 0x00, 0x20, // STA $2000
+    0x4C, 0x05, 0x80, // JMP $8005
+];
+
+#[test]
+fn nmi_every_vblank() {
+    let count_nmi = [
+        0xE6, 0x00, // INC $00
+        0x40, // RTI
+    ];
+    let mut nes = interrupt_nes(&ENABLE_NMI, &count_nmi, &[]);
+    for frame in 0..5 {
+        nes.next_frame().expect("Expected the frame to run");
+        // Frames end before vblank starts
+        assert_eq!(frame, nes.mmu.peek(0x00));
+    }
+}
+
+#[test]
+fn enabling_nmi_in_vblank() {
+    // The first NMI turns NMI off and on again, which is another rising edge
+    // on the NMI line while vblank is still set
+    let retrigger = [
+        0xE6, 0x00, // INC $00
+        0xA5, 0x01, // LDA $01
+        0xD0, 0x0C, // BNE +12
+        0xE6, 0x01, // INC $01
+        0xA9, 0x00, // LDA #$00
+        0x8D, 0x00, 0x20, // STA $2000
+        0xA9, 0x80, // LDA #$80
+        0x8D, 0x00, 0x20, // STA $2000
+        0x40, // RTI
+    ];
+    let mut nes = interrupt_nes(&ENABLE_NMI, &retrigger, &[]);
+    nes.next_frame().expect("Expected the frame to run");
+    nes.next_frame().expect("Expected the frame to run");
+    assert_eq!(2, nes.mmu.peek(0x00));
+    nes.next_frame().expect("Expected the frame to run");
+    assert_eq!(3, nes.mmu.peek(0x00));
+}
+
+#[test]
+fn apu_frame_irq() {
+    let main = [
+        0xA9, 0x00, // LDA #$00
+        0x8D, 0x17, 0x40, // STA $4017
+        0x58, // CLI
+        0x4C, 0x06, 0x80, // JMP $8006
+    ];
+    let count_irq = [
+        0xE6, 0x00, // INC $00
+        0xAD, 0x15, 0x40, // LDA $4015
+        0x40, // RTI
+    ];
+    let mut nes = interrupt_nes(&main, &[], &count_irq);
+    for _ in 0..4 {
+        nes.next_frame().expect("Expected the frame to run");
+    }
+    // The 4 step sequence raises its IRQ a little less than once a frame
+    assert_eq!(3, nes.mmu.peek(0x00));
+}
+
+#[test]
+fn irq_masked_without_cli() {
+    let main = [
+        0xA9, 0x00, // LDA #$00
+        0x8D, 0x17, 0x40, // STA $4017
+        0x4C, 0x05, 0x80, // JMP $8005
+    ];
+    let count_irq = [
+        0xE6, 0x00, // INC $00
+        0x40, // RTI
+    ];
+    let mut nes = interrupt_nes(&main, &[], &count_irq);
+    nes.next_frame().expect("Expected the frame to run");
+    nes.next_frame().expect("Expected the frame to run");
+    assert_eq!(0, nes.mmu.peek(0x00));
+    assert!(nes.mmu.irq_pending());
+}
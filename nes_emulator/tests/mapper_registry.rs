@@ -0,0 +1,96 @@
+extern crate nes_emu;
+use nes_emu::mapper::Cartridge;
+use nes_emu::mapper::Mapper;
+use nes_emu::mapper::MapperRegistry;
+use nes_emu::mapper::decode_board;
+use nes_emu::mapper::encode_board;
+use nes_emu::rom::Rom;
+use nes_emu::rom::load_rom;
+use nes_emu::state::StateFileError;
+
+// A 16KB PRG, 8KB CHR iNES image for the given mapper number
+fn rom_with_mapper(mapper: u8) -> Rom {
+    let mut raw = vec![
+        b'N',
+        b'E',
+        b'S',
+        0x1A,
+        1,
+        1,
+        (mapper & 0x0F) << 4,
+        mapper & 0xF0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ];
+    raw.extend(vec![0xEA; 0x4000]);
+    raw.extend(vec![0; 0x2000]);
+    load_rom(&raw).expect("Expected a valid rom")
+}
+
+// Maps a single fixed value over all of PRG space
+struct ConstBoard(u8);
+
+impl Mapper for ConstBoard {
+    fn ld_prg(&self, _rom: &Rom, _addr: u16) -> u8 {
+        self.0
+    }
+
+    fn store_prg(&mut self, _rom: &mut Rom, _addr: u16, val: u8) {
+        self.0 = val;
+    }
+
+    fn ld_chr(&self, rom: &Rom, addr: u16) -> u8 {
+        rom.chr_rom[addr as usize]
+    }
+
+    fn store_chr(&mut self, _rom: &mut Rom, _addr: u16, _val: u8) {}
+
+    fn save_state(&self) -> Result<Vec<u8>, StateFileError> {
+        encode_board(&self.0)
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), StateFileError> {
+        self.0 = decode_board(data)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn unknown_mapper_is_an_error() {
+    assert!(Cartridge::from_rom(rom_with_mapper(250)).is_err());
+}
+
+#[test]
+fn registered_board_is_used() {
+    let mut registry = MapperRegistry::default();
+    registry.register(250, None, |_| Box::new(ConstBoard(0x42)));
+    let mut cartridge =
+        Cartridge::with_registry(rom_with_mapper(250), &registry)
+            .expect("Expected the registered board");
+    assert_eq!(0x42, cartridge.ld_prg(0x8000));
+
+    let state = cartridge.get_state().expect("Expected a valid state");
+    cartridge.store_prg(0x8000, 0x17);
+    assert_eq!(0x17, cartridge.ld_prg(0xFFFF));
+    cartridge
+        .set_state(state)
+        .expect("Expected the state to load");
+    assert_eq!(0x42, cartridge.ld_prg(0x8000));
+}
+
+#[test]
+fn small_axrom() {
+    // A 16KB AxROM image shows up in both halves whatever bank is selected
+    let mut cartridge = Cartridge::from_rom(rom_with_mapper(7))
+        .expect("Expected a supported rom");
+    assert_eq!(0xEA, cartridge.ld_prg(0x8000));
+    assert_eq!(0xEA, cartridge.ld_prg(0xFFFF));
+    cartridge.store_prg(0x8000, 0x07);
+    assert_eq!(0xEA, cartridge.ld_prg(0xC000));
+}
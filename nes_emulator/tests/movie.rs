@@ -0,0 +1,170 @@
+extern crate nes_emu;
+mod common;
+use common::button_counter_nes;
+use common::snapshot;
+use nes_emu::NesEmulator;
+use nes_emu::PlayerController;
+use nes_emu::controller::Button;
+use nes_emu::movie::Movie;
+use nes_emu::movie::MovieError;
+use nes_emu::movie::MovieFrame;
+use nes_emu::movie::MovieStart;
+
+// Records 30 frames of mashing A with a reset in the middle
+fn record(nes: &mut NesEmulator) -> (Movie, (Vec<u8>, u64)) {
+    nes.start_recording()
+        .expect("Expected the recording to start");
+    for i in 0..30 {
+        if i == 10 {
+            nes.reset();
+        }
+        nes.set_button(Button::A, i % 4 < 2, PlayerController::One);
+        nes.next_frame().expect("Expected the frame to run");
+    }
+    let movie = nes.stop_movie().expect("Expected a movie");
+    (movie, snapshot(nes))
+}
+
+fn play(nes: &mut NesEmulator, movie: Movie) -> (Vec<u8>, u64) {
+    nes.play_movie(movie).expect("Expected the movie to play");
+    while nes.movie_playing() {
+        // The movie has to win over the frontend
+        nes.set_button(Button::A, true, PlayerController::One);
+        nes.next_frame().expect("Expected the frame to run");
+    }
+    snapshot(nes)
+}
+
+#[test]
+fn power_on_movie_plays_back() {
+    let (movie, expected) = record(&mut button_counter_nes());
+    assert!(matches!(movie.start, MovieStart::PowerOn));
+    assert_eq!(30, movie.frames.len());
+    assert!(movie.frames[10].reset);
+
+    assert!(expected == play(&mut button_counter_nes(), movie));
+}
+
+#[test]
+fn second_port_is_recorded() {
+    let mut nes = button_counter_nes();
+    nes.start_recording()
+        .expect("Expected the recording to start");
+    nes.set_button(Button::B, true, PlayerController::Two);
+    nes.next_frame().expect("Expected the frame to run");
+    nes.set_button(Button::B, false, PlayerController::Two);
+    nes.set_button(Button::Start, true, PlayerController::One);
+    nes.next_frame().expect("Expected the frame to run");
+    let movie = nes.stop_movie().expect("Expected a movie");
+    assert_eq!([0, Button::B as u8], movie.frames[0].ports);
+    assert_eq!([Button::Start as u8, 0], movie.frames[1].ports);
+}
+
+#[test]
+fn power_on_movie_needs_a_fresh_rom() {
+    let (movie, _) = record(&mut button_counter_nes());
+    let mut nes = button_counter_nes();
+    nes.next_frame().expect("Expected the frame to run");
+    let err = nes.play_movie(movie).expect_err("Expected an error");
+    assert!(matches!(
+        err.downcast_ref::<MovieError>(),
+        Some(MovieError::NotAtPowerOn)
+    ));
+}
+
+#[test]
+fn state_movie_through_fm2() {
+    let mut nes = button_counter_nes();
+    for _ in 0..5 {
+        nes.next_frame().expect("Expected the frame to run");
+    }
+    let (movie, expected) = record(&mut nes);
+    assert!(matches!(movie.start, MovieStart::State(_)));
+
+    let fm2 = movie.to_fm2().expect("Expected a valid fm2");
+    let movie = Movie::from_fm2(&fm2).expect("Expected a valid fm2");
+    let mut nes = button_counter_nes();
+    nes.next_frame().expect("Expected the frame to run");
+    assert!(expected == play(&mut nes, movie));
+}
+
+#[test]
+fn bk2_round_trip() {
+    let (movie, expected) = record(&mut button_counter_nes());
+    let log = movie
+        .to_bk2_input_log()
+        .expect("Expected a valid input log");
+    let imported =
+        Movie::from_bk2_input_log(&log).expect("Expected a valid input log");
+    assert_eq!(movie.frames, imported.frames);
+    assert!(expected == play(&mut button_counter_nes(), imported));
+}
+
+#[test]
+fn fceux_fm2() {
+    let fm2 = "version 3\n\
+               emuVersion 22020\n\
+               rerecordCount 12\n\
+               palFlag 0\n\
+               romFilename game\n\
+               guid 452DE2C3-EF43-2FA9-77AC-0677FC51543B\n\
+               fourscore 0\n\
+               port0 1\n\
+               port1 1\n\
+               port2 0\n\
+               comment author someone\n\
+               |2|........|........||\n\
+               |0|R......A|........||\n\
+               |1|...UT...|.L....B.||\n";
+    let movie = Movie::from_fm2(fm2).expect("Expected a valid fm2");
+    assert!(matches!(movie.start, MovieStart::PowerOn));
+    assert_eq!(12, movie.rerecords);
+    assert_eq!(
+        vec![
+            MovieFrame::default(),
+            MovieFrame {
+                ports: [Button::Right as u8 | Button::A as u8, 0],
+                reset: false,
+            },
+            MovieFrame {
+                ports: [
+                    Button::Up as u8 | Button::Start as u8,
+                    Button::Left as u8 | Button::B as u8,
+                ],
+                reset: true,
+            },
+        ],
+        movie.frames
+    );
+
+    let zapper = fm2.replace("port1 1", "port1 2");
+    let err = Movie::from_fm2(&zapper).err();
+    assert!(matches!(
+        err.as_ref().and_then(|e| e.downcast_ref::<MovieError>()),
+        Some(MovieError::Unsupported(_))
+    ));
+}
+
+#[test]
+fn bizhawk_input_log() {
+    let log = "[Input]\n\
+               LogKey:#Reset|Power|#P1 Up|P1 Down|P1 Left|P1 Right|P1 Start|P1 Select|P1 B|P1 A|#P2 Up|P2 Down|P2 Left|P2 Right|P2 Start|P2 Select|P2 B|P2 A|\n\
+               |..|........|........|\n\
+               |r.|U.....BA|...R.s..|\n\
+               [/Input]\n";
+    let movie =
+        Movie::from_bk2_input_log(log).expect("Expected a valid input log");
+    assert_eq!(
+        vec![
+            MovieFrame::default(),
+            MovieFrame {
+                ports: [
+                    Button::Up as u8 | Button::B as u8 | Button::A as u8,
+                    Button::Right as u8 | Button::Select as u8,
+                ],
+                reset: true,
+            },
+        ],
+        movie.frames
+    );
+}
@@ -0,0 +1,67 @@
+extern crate cpu_6502;
+extern crate nes_emu;
+mod common;
+use common::TestRom;
+use cpu_6502::Memory;
+use nes_emu::NesEmulator;
+
+// An image with the given flag6 mapper and mirroring bits
+fn nes_with_flag6(flag6: u8) -> NesEmulator {
+    TestRom::new(&[]).flag6(flag6).nes()
+}
+
+fn write_vram(nes: &mut NesEmulator, addr: u16, val: u8) {
+    nes.mmu.store(0x2006, (addr >> 8) as u8);
+    nes.mmu.store(0x2006, addr as u8);
+    nes.mmu.store(0x2007, val);
+}
+
+fn read_vram(nes: &mut NesEmulator, addr: u16) -> u8 {
+    nes.mmu.store(0x2006, (addr >> 8) as u8);
+    nes.mmu.store(0x2006, addr as u8);
+    // The first read only fills the read buffer
+    nes.mmu.ld8(0x2007);
+    nes.mmu.ld8(0x2007)
+}
+
+// Writes a different value to each nametable and reads back all four
+fn nametable_contents(nes: &mut NesEmulator) -> [u8; 4] {
+    let nametables = [0x2000, 0x2400, 0x2800, 0x2C00];
+    for (i, addr) in nametables.iter().enumerate() {
+        write_vram(nes, *addr + 0x42, i as u8 + 1);
+    }
+    nametables.map(|addr| read_vram(nes, addr + 0x42))
+}
+
+#[test]
+fn horizontal_mirroring() {
+    let mut nes = nes_with_flag6(0b0000);
+    assert_eq!([2, 2, 4, 4], nametable_contents(&mut nes));
+}
+
+#[test]
+fn vertical_mirroring() {
+    let mut nes = nes_with_flag6(0b0001);
+    assert_eq!([3, 4, 3, 4], nametable_contents(&mut nes));
+}
+
+#[test]
+fn four_screen_nametables_are_independent() {
+    let mut nes = nes_with_flag6(0b1000);
+    assert_eq!([1, 2, 3, 4], nametable_contents(&mut nes));
+
+    let state = nes.get_state().expect("Expected a valid state");
+    write_vram(&mut nes, 0x2C42, 0xFF);
+    nes.load_state(state).expect("Expected the state to load");
+    assert_eq!(4, read_vram(&mut nes, 0x2C42));
+}
+
+#[test]
+fn mmc3_four_screen_ignores_mirroring_writes() {
+    // Mapper 4 with the four screen bit set, like TVROM
+    let mut nes = nes_with_flag6(0x48);
+    nes.mmu.store(0xA000, 0x01);
+    assert_eq!([1, 2, 3, 4], nametable_contents(&mut nes));
+    nes.mmu.store(0xA000, 0x00);
+    assert_eq!([1, 2, 3, 4], nametable_contents(&mut nes));
+}
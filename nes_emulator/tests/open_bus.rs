@@ -0,0 +1,105 @@
+extern crate cpu_6502;
+extern crate nes_emu;
+mod common;
+use common::spinning_nes;
+use cpu_6502::Memory;
+
+#[test]
+fn write_only_ppu_registers_read_the_latch() {
+    let mut nes = spinning_nes();
+    nes.mmu.store(0x2002, 0x5A);
+    for reg in [0x2000, 0x2001, 0x2003, 0x2005, 0x2006] {
+        assert_eq!(0x5A, nes.mmu.ld8(reg));
+    }
+    // Mirrors of the registers share the latch
+    nes.mmu.store(0x3FFA, 0xC3);
+    assert_eq!(0xC3, nes.mmu.ld8(0x2008));
+}
+
+#[test]
+fn ppustatus_only_drives_the_top_bits() {
+    let mut nes = spinning_nes();
+    nes.mmu.store(0x2002, 0xFF);
+    assert_eq!(0x1F, nes.mmu.ld8(0x2002) & 0x1F);
+    // Reading it put the flags on the latch
+    let status = nes.mmu.ld8(0x2002);
+    assert_eq!(status, nes.mmu.ld8(0x2000));
+}
+
+#[test]
+fn oam_attributes_have_no_bits_2_to_4() {
+    let mut nes = spinning_nes();
+    nes.mmu.store(0x2003, 0x01);
+    for _ in 0..3 {
+        nes.mmu.store(0x2004, 0xFF);
+    }
+    nes.mmu.store(0x2003, 0x01);
+    assert_eq!(0xFF, nes.mmu.ld8(0x2004));
+    nes.mmu.store(0x2003, 0x02);
+    assert_eq!(0xE3, nes.mmu.ld8(0x2004));
+    // The masked bits are driven as 0
+    assert_eq!(0xE3, nes.mmu.ld8(0x2000));
+}
+
+#[test]
+fn palette_reads_keep_the_top_bits() {
+    let mut nes = spinning_nes();
+    // The nametable byte under the palette
+    nes.mmu.store(0x2006, 0x2F);
+    nes.mmu.store(0x2006, 0x00);
+    nes.mmu.store(0x2007, 0x77);
+    nes.mmu.store(0x2006, 0x3F);
+    nes.mmu.store(0x2006, 0x00);
+    nes.mmu.store(0x2007, 0x2A);
+
+    nes.mmu.store(0x2006, 0x3F);
+    nes.mmu.store(0x2006, 0x00);
+    nes.mmu.store(0x2002, 0xC0);
+    assert_eq!(0xEA, nes.mmu.ld8(0x2007));
+    // The read buffer got the nametable byte instead of the palette entry
+    nes.mmu.store(0x2006, 0x20);
+    nes.mmu.store(0x2006, 0x00);
+    assert_eq!(0x77, nes.mmu.ld8(0x2007));
+}
+
+#[test]
+fn latch_bits_decay() {
+    let mut nes = spinning_nes();
+    nes.mmu.store(0x2002, 0xFF);
+    for _ in 0..10 {
+        nes.next_frame().expect("Expected the frame to run");
+    }
+    assert_eq!(0xFF, nes.mmu.ld8(0x2001));
+
+    // Only the status bits get refreshed by the read
+    nes.mmu.ld8(0x2002);
+    for _ in 0..30 {
+        nes.next_frame().expect("Expected the frame to run");
+    }
+    assert_eq!(0x00, nes.mmu.ld8(0x2001) & 0x1F);
+}
+
+#[test]
+fn cpu_data_bus() {
+    let mut nes = spinning_nes();
+    nes.mmu.store(0x0000, 0xA5);
+    assert_eq!(0xA5, nes.mmu.ld8(0x4000));
+    assert_eq!(0xA5, nes.mmu.ld8(0x4018));
+
+    // Controllers only drive the low bits
+    assert_eq!(0xA0, nes.mmu.ld8(0x4016) & 0xE0);
+    nes.mmu.store(0x0000, 0x40);
+    assert_eq!(0x40, nes.mmu.ld8(0x4016) & 0xE0);
+
+    // Reads of $4015 don't reach the bus, bit 5 comes from it
+    nes.mmu.store(0x0000, 0xFF);
+    assert_eq!(0x20, nes.mmu.ld8(0x4015) & 0x20);
+    assert_eq!(0xFF, nes.mmu.ld8(0x4018));
+    nes.mmu.store(0x0000, 0x00);
+    assert_eq!(0x00, nes.mmu.ld8(0x4015) & 0x20);
+
+    // Reads update it too
+    nes.mmu.store(0x0010, 0x3C);
+    nes.mmu.ld8(0x0010);
+    assert_eq!(0x3C, nes.mmu.ld8(0x4018));
+}
@@ -0,0 +1,49 @@
+extern crate nes_emu;
+use nes_emu::apu::resampler::Resampler;
+use std::f64::consts::PI;
+
+const CPU_CLOCK: f32 = 1_789_773.0;
+// A pulse channel with a timer period of 8, 12.4kHz. Its third harmonic at
+// 37.3kHz is past half of either sample rate.
+const SQUARE_CYCLES: u32 = 144;
+
+// The amplitude of one frequency in the samples, by correlating them with it
+fn amplitude(samples: &[f32], freq: f64, sample_rate: f64) -> f64 {
+    let (re, im) = samples.iter().enumerate().fold(
+        (0.0, 0.0),
+        |(re, im), (n, &sample)| {
+            let angle = 2.0 * PI * freq * n as f64 / sample_rate;
+            (
+                re + sample as f64 * angle.cos(),
+                im + sample as f64 * angle.sin(),
+            )
+        },
+    );
+    2.0 * (re * re + im * im).sqrt() / samples.len() as f64
+}
+
+#[test]
+fn square_wave_doesnt_alias() {
+    let freq = CPU_CLOCK as f64 / SQUARE_CYCLES as f64;
+    for sample_rate in [44100, 48000] {
+        let mut resampler = Resampler::new(CPU_CLOCK, sample_rate);
+        for cycle in 0..CPU_CLOCK as u32 {
+            let high = cycle % SQUARE_CYCLES < SQUARE_CYCLES / 2;
+            resampler.push(if high { 0.1 } else { 0.0 });
+        }
+        // Past where the high passes have settled
+        let samples = &resampler.samples()[sample_rate as usize / 10..];
+        let rate = sample_rate as f64;
+        let fundamental = amplitude(samples, freq, rate);
+        // Where the third harmonic folds back to
+        let alias = amplitude(samples, rate - 3.0 * freq, rate);
+        assert!(fundamental > 0.03, "{} Hz: {}", sample_rate, fundamental);
+        assert!(
+            alias < fundamental / 1000.0,
+            "{} Hz: the alias is {} against {}",
+            sample_rate,
+            alias,
+            fundamental
+        );
+    }
+}
@@ -0,0 +1,66 @@
+extern crate nes_emu;
+mod common;
+use common::button_counter_nes;
+use common::snapshot;
+use nes_emu::PlayerController;
+use nes_emu::controller::Button;
+
+#[test]
+fn rewind_frame_by_frame() {
+    let mut nes = button_counter_nes();
+    nes.enable_rewind(4, 100);
+    let mut history = vec![snapshot(&nes)];
+    for i in 0..20 {
+        nes.set_button(Button::A, i % 3 == 0, PlayerController::One);
+        nes.next_frame().expect("Expected the frame to run");
+        history.push(snapshot(&nes));
+    }
+
+    for frame in (0..20).rev() {
+        assert!(nes.rewind_frame().expect("Expected the rewind to work"));
+        assert_eq!(frame, nes.frame_count());
+        assert!(history[frame as usize] == snapshot(&nes));
+    }
+    assert!(!nes.rewind_frame().expect("Expected the rewind to work"));
+}
+
+#[test]
+fn rewind_then_play_again() {
+    let mut nes = button_counter_nes();
+    nes.enable_rewind(3, 100);
+    for _ in 0..10 {
+        nes.next_frame().expect("Expected the frame to run");
+    }
+    for _ in 0..5 {
+        assert!(nes.rewind_frame().expect("Expected the rewind to work"));
+    }
+    // Replaying the same buttons ends up in the same place
+    let mut replayed = Vec::new();
+    for _ in 0..5 {
+        nes.next_frame().expect("Expected the frame to run");
+        replayed.push(snapshot(&nes));
+    }
+    for _ in 0..5 {
+        assert!(nes.rewind_frame().expect("Expected the rewind to work"));
+    }
+    for expected in replayed {
+        nes.next_frame().expect("Expected the frame to run");
+        assert!(expected == snapshot(&nes));
+    }
+}
+
+#[test]
+fn rewind_history_is_bounded() {
+    let mut nes = button_counter_nes();
+    nes.enable_rewind(1, 5);
+    for _ in 0..20 {
+        nes.next_frame().expect("Expected the frame to run");
+    }
+    // Snapshots for frames 16 through 20 are left
+    for frame in (16..20).rev() {
+        assert!(nes.rewind_frame().expect("Expected the rewind to work"));
+        assert_eq!(frame, nes.frame_count());
+    }
+    assert!(!nes.rewind_frame().expect("Expected the rewind to work"));
+    assert_eq!(16, nes.frame_count());
+}
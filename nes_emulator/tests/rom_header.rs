@@ -0,0 +1,75 @@
+extern crate nes_emu;
+use nes_emu::rom::ConsoleType;
+use nes_emu::rom::Region;
+use nes_emu::rom::RomType;
+use nes_emu::rom::load_rom;
+
+// Header bytes followed by enough PRG and CHR data to fill the given sizes
+fn rom_with_header(
+    header: [u8; 16],
+    prg_len: usize,
+    chr_len: usize,
+) -> Vec<u8> {
+    let mut raw = header.to_vec();
+    raw.extend(vec![0xEA; prg_len]);
+    raw.extend(vec![0; chr_len]);
+    raw
+}
+
+#[test]
+fn ines_header() {
+    let header = [
+        b'N', b'E', b'S', 0x1A, 2, 1, 0x12, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = load_rom(&rom_with_header(header, 0x8000, 0x2000))
+        .expect("Expected a valid rom");
+    assert_eq!(RomType::INes, rom.header.rom_type);
+    assert_eq!(1, rom.header.mapper);
+    assert_eq!(0, rom.header.submapper);
+    assert!(rom.header.save_ram);
+    assert_eq!(0x2000, rom.header.prg_nvram_size);
+    assert_eq!(0, rom.header.chr_ram_size);
+    assert_eq!(ConsoleType::Nes, rom.header.console);
+    assert_eq!(Region::NTSC, rom.header.region);
+}
+
+#[test]
+fn nes2_header() {
+    let header = [
+        b'N', b'E', b'S', 0x1A, 2, 0, 0x40, 0x09, 0x51, 0x00, 0x70, 0x07, 0x02,
+        0x21, 0x01, 0x01,
+    ];
+    let rom = load_rom(&rom_with_header(header, 0x8000, 0))
+        .expect("Expected a valid rom");
+    assert_eq!(RomType::Nes2, rom.header.rom_type);
+    assert_eq!(0x104, rom.header.mapper);
+    assert_eq!(5, rom.header.submapper);
+    assert_eq!(0x8000, rom.header.prg_rom_size);
+    assert_eq!(0, rom.header.chr_rom_size);
+    assert_eq!(0, rom.header.prg_ram_size);
+    assert_eq!(0x2000, rom.header.prg_nvram_size);
+    assert_eq!(0x2000, rom.header.chr_ram_size);
+    assert_eq!(0x2000, rom.chr_ram.len());
+    assert_eq!(
+        ConsoleType::VsSystem {
+            ppu_type: 1,
+            hardware_type: 2
+        },
+        rom.header.console
+    );
+    assert_eq!(Region::MultiRegion, rom.header.region);
+    assert_eq!(1, rom.header.misc_roms);
+    assert_eq!(1, rom.header.expansion_device);
+}
+
+#[test]
+fn nes2_exponent_multiplier_size() {
+    // 2^10 * (1 * 2 + 1) = 3KB of PRG ROM
+    let header = [
+        b'N', b'E', b'S', 0x1A, 0b101001, 1, 0, 0x08, 0, 0x0F, 0, 0, 0, 0, 0, 0,
+    ];
+    let rom = load_rom(&rom_with_header(header, 3 * 1024, 0x2000))
+        .expect("Expected a valid rom");
+    assert_eq!(3 * 1024, rom.prg_rom.len());
+    assert_eq!(0x2000, rom.chr_rom.len());
+}
@@ -0,0 +1,125 @@
+extern crate nes_emu;
+mod common;
+use common::TestRom;
+use common::button_counter_nes;
+use nes_emu::NesEmulator;
+use nes_emu::debugger::Breakpoint;
+use nes_emu::debugger::RunCommand;
+use nes_emu::state::State;
+use nes_emu::state::StateFileError;
+
+// An NROM image that keeps writing a counter to RAM and VRAM with rendering
+// on. The fill byte changes the CHR ROM, and with it the rom's hash.
+fn counter_nes(chr_fill: u8) -> NesEmulator {
+    let program = [
+        0xA9, 0x1E, // LDA #$1E
+        0x8D, 0x01, 0x20, // STA $2001
+        0xE8, // INX
+        0x86, 0x00, // STX $00
+        0x8E, 0x07, 0x20, // STX $2007
+        0x4C, 0x05, 0x80, // JMP $8005
+    ];
+    TestRom::new(&program).chr_fill(chr_fill).nes()
+}
+
+fn save(nes: &NesEmulator) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    nes.get_state()
+        .expect("Expected a valid state")
+        .save(&mut bytes)
+        .expect("Expected the state to save");
+    bytes
+}
+
+fn load_error(nes: &mut NesEmulator, bytes: &[u8]) -> StateFileError {
+    let err = match State::load(&mut &bytes[..]) {
+        Ok(state) => nes.load_state(state).expect_err("Expected an error"),
+        Err(e) => e,
+    };
+    err.downcast().expect("Expected a StateFileError")
+}
+
+#[test]
+fn mid_frame_state_round_trip() {
+    let mut nes = counter_nes(0x55);
+    nes.next_frame().expect("Expected the frame to run");
+    for _ in 0..1000 {
+        nes.step().expect("Expected the step to run");
+    }
+    let bytes = save(&nes);
+
+    nes.next_frame().expect("Expected the frame to run");
+    let expected_frame = nes.get_pixel_buffer().to_vec();
+    let expected_cycles = nes.mmu.cycles;
+    let expected_cpu_cycles = nes.cpu.cycles();
+
+    // Run somewhere else entirely before loading
+    nes.next_frame().expect("Expected the frame to run");
+    let state = State::load(&mut &bytes[..]).expect("Expected a valid state");
+    nes.load_state(state).expect("Expected the state to load");
+    nes.next_frame().expect("Expected the frame to run");
+    assert_eq!(expected_frame, nes.get_pixel_buffer());
+    assert_eq!(expected_cycles, nes.mmu.cycles);
+    assert_eq!(expected_cpu_cycles, nes.cpu.cycles());
+}
+
+#[test]
+fn debugger_stop_state_finishes_the_frame() {
+    let mut expected = button_counter_nes();
+    expected.next_frame().expect("Expected the frame to run");
+    expected.next_frame().expect("Expected the frame to run");
+
+    // Stop on the first controller read of the second frame, after its
+    // strobe
+    let mut nes = button_counter_nes();
+    nes.next_frame().expect("Expected the frame to run");
+    let id = nes.add_breakpoint(Breakpoint::at(0x800F));
+    nes.run(RunCommand::Frame);
+    nes.remove_breakpoint(id);
+    let bytes = save(&nes);
+
+    nes.next_frame().expect("Expected the frame to run");
+    nes.next_frame().expect("Expected the frame to run");
+    let state = State::load(&mut &bytes[..]).expect("Expected a valid state");
+    nes.load_state(state).expect("Expected the state to load");
+    nes.next_frame().expect("Expected the frame to run");
+    assert_eq!(expected.input_polls(), nes.input_polls());
+}
+
+#[test]
+fn state_for_another_rom() {
+    let bytes = save(&counter_nes(0x55));
+    let mut nes = counter_nes(0xAA);
+    let cycles = nes.mmu.cycles;
+    assert!(matches!(
+        load_error(&mut nes, &bytes),
+        StateFileError::WrongRom { .. }
+    ));
+    assert_eq!(cycles, nes.mmu.cycles);
+}
+
+#[test]
+fn not_a_state() {
+    let mut nes = counter_nes(0x55);
+    let mut bytes = save(&nes);
+    bytes[0] = b'X';
+    assert!(matches!(
+        load_error(&mut nes, &bytes),
+        StateFileError::BadMagic
+    ));
+    assert!(matches!(
+        load_error(&mut nes, &[]),
+        StateFileError::BadMagic
+    ));
+}
+
+#[test]
+fn unsupported_version() {
+    let mut nes = counter_nes(0x55);
+    let mut bytes = save(&nes);
+    bytes[4..8].copy_from_slice(&0xFFFFu32.to_le_bytes());
+    assert!(matches!(
+        load_error(&mut nes, &bytes),
+        StateFileError::UnsupportedVersion(0xFFFF)
+    ));
+}
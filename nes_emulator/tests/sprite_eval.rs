@@ -0,0 +1,72 @@
+extern crate cpu_6502;
+extern crate nes_emu;
+mod common;
+use common::spinning_nes;
+use cpu_6502::Memory;
+use nes_emu::NesEmulator;
+
+// Fills OAM with $F0, which is below the screen whichever byte evaluation
+// takes as Y, then applies the given bytes
+fn write_oam(nes: &mut NesEmulator, bytes: &[(u8, u8)]) {
+    let mut oam = [0xF0; 256];
+    for (addr, val) in bytes {
+        oam[*addr as usize] = *val;
+    }
+    nes.mmu.store(0x2003, 0x00);
+    for val in oam {
+        nes.mmu.store(0x2004, val);
+    }
+}
+
+// Sets the Y of the first count sprites
+fn sprites_on_line(count: u8, y: u8) -> Vec<(u8, u8)> {
+    (0..count).map(|sprite| (sprite * 4, y)).collect()
+}
+
+// Draws a frame with sprites on and returns the overflow flag
+fn overflows(bytes: &[(u8, u8)]) -> bool {
+    let mut nes = spinning_nes();
+    write_oam(&mut nes, bytes);
+    nes.mmu.store(0x2001, 0x18);
+    nes.next_frame().expect("Expected the frame to run");
+    nes.mmu.ld8(0x2002) & 0x20 != 0
+}
+
+#[test]
+fn overflow_after_eight_sprites() {
+    assert!(!overflows(&sprites_on_line(8, 16)));
+    assert!(overflows(&sprites_on_line(9, 16)));
+}
+
+#[test]
+fn diagonal_scan_false_positive() {
+    // Sprite 8 is off the line, so sprite 9's tile byte gets compared as Y
+    let mut bytes = sprites_on_line(8, 16);
+    bytes.push((9 * 4 + 1, 20));
+    assert!(overflows(&bytes));
+}
+
+#[test]
+fn diagonal_scan_false_negative() {
+    // Sprite 9 is on the line, but the scan has moved on to its tile byte
+    let mut bytes = sprites_on_line(8, 16);
+    bytes.push((9 * 4, 16));
+    assert!(!overflows(&bytes));
+}
+
+#[test]
+fn oamaddr_row_copied_when_rendering_starts() {
+    let mut nes = spinning_nes();
+    let row: Vec<(u8, u8)> = (0x10..0x18).map(|addr| (addr, 0xE3)).collect();
+    write_oam(&mut nes, &row);
+    nes.mmu.store(0x2001, 0x18);
+    nes.next_frame().expect("Expected the frame to run");
+    nes.mmu.store(0x2003, 0x13);
+    nes.next_frame().expect("Expected the frame to run");
+
+    nes.mmu.store(0x2001, 0x00);
+    for addr in 0x00..0x08 {
+        nes.mmu.store(0x2003, addr);
+        assert_eq!(0xE3, nes.mmu.ld8(0x2004));
+    }
+}
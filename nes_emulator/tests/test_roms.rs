@@ -1,7 +1,9 @@
+extern crate cpu_6502;
 extern crate hex;
 extern crate nes_emu;
 extern crate serde;
 extern crate sha3;
+use cpu_6502::Memory;
 use nes_emu::NesEmulator;
 use nes_emu::rom::load_rom;
 use sha3::Digest;
@@ -22,9 +24,11 @@ macro_rules! hash_test {
                         "Failure to read the file");
                     let rom = load_rom(&raw_bytes).expect(
                         "Expected a valid rom");
-                    let mut nes = NesEmulator::new(rom);
+                    let mut nes = NesEmulator::new(rom).expect(
+                        "Expected a supported rom");
                     for _ in 0..$frame_num {
-                        nes.next_frame();
+                        nes.next_frame().expect(
+                            "Expected the frame to run");
                     }
                     assert_eq!(
                         $hash,
@@ -34,6 +38,171 @@ macro_rules! hash_test {
     };
 }
 
+// Newer blargg test roms report through PRG RAM: $6000 holds 0x80 while the
+// test is running, 0x81 when the rom wants a reset, and the final result code
+// afterwards. $6001-$6003 hold a signature to show the result is valid.
+fn blargg_status(nes: &NesEmulator) -> Option<u8> {
+    let cartridge = nes.mmu.cartridge.borrow();
+    match cartridge.rom.prg_ram.get(0..4) {
+        Some([status, 0xDE, 0xB0, 0x61]) => Some(*status),
+        _ => None,
+    }
+}
+
+fn blargg_message(nes: &NesEmulator) -> String {
+    let cartridge = nes.mmu.cartridge.borrow();
+    cartridge.rom.prg_ram[4..]
+        .iter()
+        .take_while(|c| **c != 0)
+        .map(|c| *c as char)
+        .collect()
+}
+
+macro_rules! status_test {
+    ( $(($max_frames:literal, $rom_path:literal, $test_name:ident)),* ) => {
+            $(
+                #[test]
+                fn $test_name() {
+                    let mut raw_bytes = Vec::new();
+                    let mut raw_rom = File::open($rom_path).expect(
+                        "Expected a valid path");
+                    raw_rom.read_to_end(&mut raw_bytes).expect(
+                        "Failure to read the file");
+                    let rom = load_rom(&raw_bytes).expect(
+                        "Expected a valid rom");
+                    let mut nes = NesEmulator::new(rom).expect(
+                        "Expected a supported rom");
+                    let mut reset_in = None;
+                    for _ in 0..$max_frames {
+                        nes.next_frame().expect(
+                            "Expected the frame to run");
+                        match (blargg_status(&nes), reset_in) {
+                            (Some(0x80), _) | (None, _) => (),
+                            // The roms ask for the reset to be delayed by at
+                            // least 100ms
+                            (Some(0x81), None) => reset_in = Some(10),
+                            (Some(0x81), Some(0)) => {
+                                nes.reset();
+                                reset_in = None;
+                            }
+                            (Some(0x81), Some(frames)) => {
+                                reset_in = Some(frames - 1)
+                            }
+                            (Some(code), _) => {
+                                assert_eq!(
+                                    0, code, "{}", blargg_message(&nes));
+                                return;
+                            }
+                        }
+                    }
+                    panic!("Test did not finish in {} frames", $max_frames);
+                }
+            )*
+    };
+}
+
+// The older blargg roms only show their result on screen and leave the code
+// at $F8 when they finish, where 1 means every test passed
+macro_rules! result_code_test {
+    ( $(($frame_num:literal, $rom_path:literal, $test_name:ident)),* ) => {
+            $(
+                #[test]
+                fn $test_name() {
+                    let mut raw_bytes = Vec::new();
+                    let mut raw_rom = File::open($rom_path).expect(
+                        "Expected a valid path");
+                    raw_rom.read_to_end(&mut raw_bytes).expect(
+                        "Failure to read the file");
+                    let rom = load_rom(&raw_bytes).expect(
+                        "Expected a valid rom");
+                    let mut nes = NesEmulator::new(rom).expect(
+                        "Expected a supported rom");
+                    for _ in 0..$frame_num {
+                        nes.next_frame().expect(
+                            "Expected the frame to run");
+                    }
+                    assert_eq!(1, nes.mmu.ld8(0x00F8));
+                }
+            )*
+    };
+}
+
+status_test! {
+    (600, "./tests/nes_test_roms/apu_test/rom_singles/1-len_ctr.nes",
+     apu_len_ctr),
+    (600, "./tests/nes_test_roms/apu_test/rom_singles/2-len_table.nes",
+     apu_len_table),
+    (600, "./tests/nes_test_roms/apu_test/rom_singles/3-irq_flag.nes",
+     apu_irq_flag),
+    (600, "./tests/nes_test_roms/apu_test/rom_singles/4-jitter.nes",
+     apu_jitter),
+    (600, "./tests/nes_test_roms/apu_test/rom_singles/5-len_timing.nes",
+     apu_len_timing),
+    (600, "./tests/nes_test_roms/apu_test/rom_singles/6-irq_flag_timing.nes",
+     apu_irq_flag_timing),
+    (600, "./tests/nes_test_roms/apu_test/rom_singles/7-dmc_basics.nes",
+     apu_dmc_basics),
+    (600, "./tests/nes_test_roms/apu_test/rom_singles/8-dmc_rates.nes",
+     apu_dmc_rates),
+    (600,
+     "./tests/nes_test_roms/cpu_interrupts_v2/rom_singles/1-cli_latency.nes",
+     cpu_interrupts_cli_latency),
+    (600,
+     "./tests/nes_test_roms/cpu_interrupts_v2/rom_singles/2-nmi_and_brk.nes",
+     cpu_interrupts_nmi_and_brk),
+    (600,
+     "./tests/nes_test_roms/cpu_interrupts_v2/rom_singles/3-nmi_and_irq.nes",
+     cpu_interrupts_nmi_and_irq),
+    (600,
+     "./tests/nes_test_roms/cpu_interrupts_v2/rom_singles/4-irq_and_dma.nes",
+     cpu_interrupts_irq_and_dma),
+    (600,
+     "./tests/nes_test_roms/cpu_interrupts_v2/rom_singles/5-branch_delays_irq.nes",
+     cpu_interrupts_branch_delays_irq),
+    (600, "./tests/nes_test_roms/dma_sync/dma_sync.nes", dma_sync),
+    (600, "./tests/nes_test_roms/dmc_dma_during_read4/dma_2007_read.nes",
+     dmc_dma_2007_read),
+    (600, "./tests/nes_test_roms/dmc_dma_during_read4/dma_2007_write.nes",
+     dmc_dma_2007_write),
+    (600, "./tests/nes_test_roms/dmc_dma_during_read4/dma_4016_read.nes",
+     dmc_dma_4016_read),
+    (600, "./tests/nes_test_roms/dmc_dma_during_read4/double_2007_read.nes",
+     dmc_dma_double_2007_read),
+    (600, "./tests/nes_test_roms/dmc_dma_during_read4/read_write_2007.nes",
+     dmc_dma_read_write_2007),
+    (600, "./tests/nes_test_roms/mmc3_test_2/rom_singles/1-clocking.nes",
+     mmc3_clocking),
+    (600, "./tests/nes_test_roms/mmc3_test_2/rom_singles/2-details.nes",
+     mmc3_details),
+    (600, "./tests/nes_test_roms/mmc3_test_2/rom_singles/3-A12_clocking.nes",
+     mmc3_a12_clocking),
+    (600,
+     "./tests/nes_test_roms/mmc3_test_2/rom_singles/4-scanline_timing.nes",
+     mmc3_scanline_timing),
+    (600, "./tests/nes_test_roms/mmc3_test_2/rom_singles/5-MMC3.nes",
+     mmc3_irq),
+    (600, "./tests/nes_test_roms/ppu_open_bus/ppu_open_bus.nes",
+     ppu_open_bus),
+    (600,
+     "./tests/nes_test_roms/cpu_exec_space/test_cpu_exec_space_ppuio.nes",
+     cpu_exec_space_ppuio),
+    (600, "./tests/nes_test_roms/cpu_exec_space/test_cpu_exec_space_apu.nes",
+     cpu_exec_space_apu)
+}
+
+result_code_test! {
+    (120, "./tests/nes_test_roms/sprite_overflow_tests/1.Basics.nes",
+     sprite_overflow_basics),
+    (120, "./tests/nes_test_roms/sprite_overflow_tests/2.Details.nes",
+     sprite_overflow_details),
+    (120, "./tests/nes_test_roms/sprite_overflow_tests/3.Timing.nes",
+     sprite_overflow_timing),
+    (120, "./tests/nes_test_roms/sprite_overflow_tests/4.Obscure.nes",
+     sprite_overflow_obscure),
+    (120, "./tests/nes_test_roms/sprite_overflow_tests/5.Emulator.nes",
+     sprite_overflow_emulator)
+}
+
 hash_test! {
     ("a96ed5458e27b41e7b87dc9d77cdc62cdcf2762bacf9183e4acb4fd09a1b8b31", 54,
      "./tests/nes_test_roms/sprite_hit_tests_2005.10.05/01.basics.nes",
@@ -93,3 +262,21 @@ hash_test! {
      "./tests/nes_test_roms/ppu_vbl_nmi/rom_singles/07-nmi_on_timing.nes",
      nmi_on_timing)
 }
+
+// nestest's automation mode starts at $C000 and runs every CPU test without
+// needing the PPU. Its log comes from Nintendulator.
+#[test]
+fn nestest() {
+    let raw_bytes = std::fs::read("./tests/nes_test_roms/other/nestest.nes")
+        .expect("Expected a valid path");
+    let log =
+        std::fs::read_to_string("./tests/nes_test_roms/other/nestest.log")
+            .expect("Expected a valid path");
+    let rom = load_rom(&raw_bytes).expect("Expected a valid rom");
+    let mut nes = NesEmulator::new(rom).expect("Expected a supported rom");
+    nes.cpu.regs.pc.set_addr(0xC000);
+    for (i, expected) in log.lines().enumerate() {
+        assert_eq!(expected, nes.trace_line(), "Differs on line {}", i + 1);
+        nes.step().expect("Expected the step to run");
+    }
+}
@@ -0,0 +1,94 @@
+extern crate nes_emu;
+use nes_emu::NesEmulator;
+use nes_emu::mapper::Cartridge;
+use nes_emu::rom::Rom;
+use nes_emu::rom::load_rom;
+use nes_emu::timing::Timing;
+
+// A NES 2.0 NROM image with the given timing mode that spins on JMP $8000
+fn spin_rom(timing_mode: u8) -> Rom {
+    let mut raw = vec![
+        b'N',
+        b'E',
+        b'S',
+        0x1A,
+        1,
+        1,
+        0,
+        0x08,
+        0,
+        0,
+        0,
+        0,
+        timing_mode,
+        0,
+        0,
+        0,
+    ];
+    let mut prg = vec![0xEA; 0x4000];
+    prg[..3].copy_from_slice(&[0x4C, 0x00, 0x80]);
+    for vector in prg[0x3FFA..].chunks_mut(2) {
+        vector.copy_from_slice(&[0x00, 0x80]);
+    }
+    raw.extend(prg);
+    raw.extend(vec![0; 0x2000]);
+    load_rom(&raw).expect("Expected a valid rom")
+}
+
+fn spin_nes(timing_mode: u8) -> NesEmulator {
+    NesEmulator::new(spin_rom(timing_mode)).expect("Expected a supported rom")
+}
+
+// CPU cycles between two frames, give or take the length of the JMP
+fn cycles_per_frame(nes: &mut NesEmulator) -> u64 {
+    nes.next_frame().expect("Expected the frame to run");
+    let start = nes.cycles();
+    nes.next_frame().expect("Expected the frame to run");
+    nes.cycles() - start
+}
+
+#[test]
+fn timing_from_header() {
+    assert_eq!(Timing::Ntsc, spin_nes(0).timing());
+    assert_eq!(Timing::Pal, spin_nes(1).timing());
+    assert_eq!(Timing::Ntsc, spin_nes(2).timing());
+    assert_eq!(Timing::Dendy, spin_nes(3).timing());
+}
+
+#[test]
+fn frame_lengths() {
+    // 341 dots * 262 lines / 3 dots per cycle
+    let mut ntsc = spin_nes(0);
+    assert!(cycles_per_frame(&mut ntsc).abs_diff(29781) <= 3);
+    // 341 dots * 312 lines / 3.2 dots per cycle
+    let mut pal = spin_nes(1);
+    assert!(cycles_per_frame(&mut pal).abs_diff(33248) <= 3);
+    // 341 dots * 312 lines / 3 dots per cycle
+    let mut dendy = spin_nes(3);
+    assert!(cycles_per_frame(&mut dendy).abs_diff(35464) <= 3);
+}
+
+#[test]
+fn caller_overrides_timing() {
+    let cartridge =
+        Cartridge::from_rom(spin_rom(0)).expect("Expected a valid cartridge");
+    let mut nes = NesEmulator::with_timing(cartridge, Timing::Pal);
+    assert_eq!(Timing::Pal, nes.timing());
+    assert!(cycles_per_frame(&mut nes).abs_diff(33248) <= 3);
+}
+
+#[test]
+fn cycles_only_go_up() {
+    let mut nes = spin_nes(0);
+    // The reset sequence runs on power on
+    assert_eq!(7, nes.cycles());
+    let mut last = nes.cycles();
+    for _ in 0..100 {
+        nes.step().expect("Expected the step to run");
+        // JMP takes 3 cycles
+        assert_eq!(last + 3, nes.cycles());
+        last = nes.cycles();
+    }
+    nes.next_frame().expect("Expected the frame to run");
+    assert!(nes.cycles() > last);
+}
@@ -0,0 +1,71 @@
+extern crate nes_emu;
+mod common;
+use common::TestRom;
+use nes_emu::NesEmulator;
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::rc::Rc;
+
+// An NROM image running a few instructions with different addressing modes
+fn trace_nes() -> NesEmulator {
+    let program = [
+        0xA2, 0x05, // LDX #$05
+        0xB5, 0x10, // LDA $10,X
+        0xA7, 0xA9, // LAX $A9
+        0x8D, 0x15, 0x40, // STA $4015
+        0x4C, 0x09, 0x80, // JMP $8009
+    ];
+    TestRom::new(&program).nes()
+}
+
+const EXPECTED: [&str; 5] = [
+    "8000  A2 05     LDX #$05                        \
+     A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+    "8002  B5 10     LDA $10,X @ 15 = 00             \
+     A:00 X:05 Y:00 P:24 SP:FD PPU:  0, 27 CYC:9",
+    "8004  A7 A9    *LAX $A9 = 00                    \
+     A:00 X:05 Y:00 P:26 SP:FD PPU:  0, 39 CYC:13",
+    "8006  8D 15 40  STA $4015 = FF                  \
+     A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 48 CYC:16",
+    "8009  4C 09 80  JMP $8009                       \
+     A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 60 CYC:20",
+];
+
+// Lets the test read back what the emulator wrote
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn trace_lines() {
+    let mut nes = trace_nes();
+    for expected in EXPECTED {
+        assert_eq!(expected, nes.trace_line());
+        nes.step().expect("Expected the step to run");
+    }
+}
+
+#[test]
+fn trace_to_sink() {
+    let mut nes = trace_nes();
+    let buffer = SharedBuffer::default();
+    nes.start_trace(Box::new(buffer.clone()));
+    for _ in 0..EXPECTED.len() {
+        nes.step().expect("Expected the step to run");
+    }
+    nes.stop_trace();
+    nes.step().expect("Expected the step to run");
+
+    let text = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+    assert_eq!(EXPECTED.to_vec(), text.lines().collect::<Vec<&str>>());
+}
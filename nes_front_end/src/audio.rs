@@ -0,0 +1,66 @@
+use anyhow::{Result, anyhow};
+use cpal::Stream;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use log::*;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+// Anything queued past this gets dropped, so running uncapped doesn't build up
+// an ever growing delay
+const MAX_QUEUED_MS: usize = 200;
+
+pub struct Audio {
+    queue: Arc<Mutex<VecDeque<f32>>>,
+    sample_rate: u32,
+    // The stream stops playing once it is dropped
+    _stream: Stream,
+}
+
+impl Audio {
+    pub fn new() -> Result<Audio> {
+        let host = cpal::default_host();
+        let device = host
+            .default_output_device()
+            .ok_or(anyhow!("No audio output device available"))?;
+        let config = device.default_output_config()?.config();
+        let channels = config.channels as usize;
+        let sample_rate = config.sample_rate;
+
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let cb_queue = queue.clone();
+        let stream = device.build_output_stream(
+            &config,
+            move |data: &mut [f32], _| {
+                let mut queue = cb_queue.lock().unwrap();
+                // The emulator only produces mono, so copy each sample into
+                // every channel of the frame
+                for frame in data.chunks_mut(channels) {
+                    let sample = queue.pop_front().unwrap_or(0.0);
+                    frame.fill(sample);
+                }
+            },
+            |e| error!("Audio stream error: {}", e),
+            None,
+        )?;
+        stream.play()?;
+
+        Ok(Audio {
+            queue,
+            sample_rate,
+            _stream: stream,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn queue(&self, samples: &[f32]) {
+        let mut queue = self.queue.lock().unwrap();
+        let max_len = self.sample_rate as usize * MAX_QUEUED_MS / 1000;
+        if queue.len() + samples.len() > max_len {
+            return;
+        }
+        queue.extend(samples);
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::{Result, anyhow};
+use audio::Audio;
 use config::{ButtonLayout, Config};
 use glfw::{Action, Context, Glfw, GlfwReceiver, Key, PWindow, WindowEvent, fail_on_errors};
 use log::Level;
@@ -12,10 +13,10 @@ use log::*;
 
 use crate::config::{EmuControl, EmuControlLayout};
 
+pub mod audio;
 pub mod config;
 pub mod ogl;
 
-const FPS_TIMER: u128 = 16667;
 const NES_SCREEN_WIDTH: u32 = 256;
 const NES_SCREEN_HEIGHT: u32 = 240;
 
@@ -30,6 +31,7 @@ struct NesFrontEnd {
     state_path: PathBuf,
     vsync: bool,
     window: PWindow,
+    audio: Option<Audio>,
     frame_count: usize,
 }
 
@@ -70,9 +72,22 @@ impl NesFrontEnd {
             glfw.set_swap_interval(glfw::SwapInterval::Sync(1));
         }
 
+        let mut nes = NesEmulator::new(rom)?;
+        // Keep running without sound rather than refusing to start
+        let audio = match Audio::new() {
+            Ok(audio) => {
+                nes.set_sample_rate(audio.sample_rate());
+                Some(audio)
+            }
+            Err(e) => {
+                warn!("Failed to open audio output: {}", e);
+                None
+            }
+        };
+
         Ok((
             NesFrontEnd {
-                nes: NesEmulator::new(rom),
+                nes,
                 ctrl1: ButtonLayout::make_ctrl_map(&cfg.ctrl1_layout)?,
                 ctrl2: ButtonLayout::make_ctrl_map(&cfg.ctrl2_layout)?,
                 emu_ctrl: EmuControlLayout::make_emu_ctrl_map(&cfg.emu_ctrl_layout)?,
@@ -82,6 +97,7 @@ impl NesFrontEnd {
                 vsync: cfg.vsync,
                 glfw,
                 window,
+                audio,
                 frame_count: 0,
             },
             events,
@@ -89,7 +105,16 @@ impl NesFrontEnd {
     }
 
     fn next_frame(&mut self) -> &[u8] {
-        self.nes.next_frame()
+        // The game can't carry on by itself, pause so the player can reset
+        // or load a state
+        if let Err(e) = self.nes.next_frame() {
+            error!("{}", e);
+            self.paused = true;
+        }
+        if let Some(audio) = &self.audio {
+            audio.queue(self.nes.get_audio_buffer());
+        }
+        self.nes.get_pixel_buffer()
     }
 
     fn frame_info(&self) -> (String, usize) {
@@ -112,7 +137,7 @@ impl NesFrontEnd {
 
     fn save_state(&self) -> Result<()> {
         let mut file = File::create(&self.state_path)?;
-        self.nes.get_state().save(&mut file)?;
+        self.nes.get_state()?.save(&mut file)?;
         info!("Saved state: {:?}", &self.state_path);
         Ok(())
     }
@@ -120,7 +145,7 @@ impl NesFrontEnd {
     fn load_state(&mut self) -> Result<()> {
         let mut file = File::open(&self.state_path)?;
         let state = nes_emu::state::State::load(&mut file)?;
-        self.nes.load_state(state);
+        self.nes.load_state(state)?;
         info!("Loaded state: {:?}", &self.state_path);
         Ok(())
     }
@@ -207,6 +232,8 @@ fn main() -> Result<(), Box<dyn Error>> {
         &Config::load_config("./config.toml".to_string())?,
     )?;
 
+    // PAL and Dendy consoles run at 50Hz
+    let frame_time = (1_000_000.0 / nes_fe.nes.timing().frame_rate()) as u128;
     let mut ts = Instant::now();
     let texture = ogl::init_shaders_and_texture(&mut nes_fe.window);
 
@@ -220,7 +247,7 @@ fn main() -> Result<(), Box<dyn Error>> {
             continue;
         }
 
-        if nes_fe.uncapped || (Instant::now() - ts).as_micros() > FPS_TIMER {
+        if nes_fe.uncapped || (Instant::now() - ts).as_micros() > frame_time {
             nes_fe.frame_count += 1;
             ogl::update_texture(texture, nes_fe.next_frame());
             ts = Instant::now();